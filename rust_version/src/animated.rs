@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Animated image formats. These are exported from a temporary capture once
+/// recording stops, since a good palette needs to see the whole clip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimatedFormat {
+    Gif,
+    WebP,
+}
+
+impl AnimatedFormat {
+    /// The format for a `container_format`, if it is an animated one.
+    pub fn from_container(format: &str) -> Option<Self> {
+        match format {
+            "gif" => Some(AnimatedFormat::Gif),
+            "webp" => Some(AnimatedFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dither {
+    None,
+    Bayer(u8), // Scale 0-5, higher is less visible but bands more
+    FloydSteinberg,
+    Sierra,
+}
+
+impl Dither {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Dither::None => "None",
+            Dither::Bayer(_) => "Bayer",
+            Dither::FloydSteinberg => "Floyd-Steinberg",
+            Dither::Sierra => "Sierra",
+        }
+    }
+
+    fn paletteuse_options(&self) -> String {
+        match self {
+            Dither::None => "dither=none".to_string(),
+            Dither::Bayer(scale) => format!("dither=bayer:bayer_scale={}", scale.min(&5)),
+            Dither::FloydSteinberg => "dither=floyd_steinberg".to_string(),
+            Dither::Sierra => "dither=sierra2_4a".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimatedOptions {
+    pub fps: u32,
+    pub width: Option<u32>, // None keeps the captured width
+    pub dither: Dither,
+    pub plays: u32, // How often the animation plays, 0 loops forever
+    pub max_kb: Option<u32>, // Shrink until the file fits
+}
+
+impl Default for AnimatedOptions {
+    fn default() -> Self {
+        Self {
+            fps: 15,
+            width: Some(800),
+            dither: Dither::Bayer(3),
+            plays: 0,
+            max_kb: None,
+        }
+    }
+}
+
+// How far the size target may take things down
+const MIN_FPS: u32 = 5;
+const SMOOTH_FPS: u32 = 10;
+const MIN_WIDTH: u32 = 160;
+
+impl AnimatedOptions {
+    /// The next, smaller attempt when the output is over the size target. The
+    /// frame rate goes down to 10 fps first (screen content is mostly still),
+    /// then the width, then the frame rate again. None when there is nothing left.
+    fn shrink(&self, source_width: u32) -> Option<Self> {
+        let width = self.width.unwrap_or(source_width).min(source_width);
+        let smaller_width = (width * 3 / 4) & !1; // Even, for chroma subsampling
+        if self.fps > SMOOTH_FPS {
+            Some(Self { fps: (self.fps * 3 / 4).max(SMOOTH_FPS), ..self.clone() })
+        } else if smaller_width >= MIN_WIDTH {
+            Some(Self { width: Some(smaller_width), ..self.clone() })
+        } else if self.fps > MIN_FPS {
+            Some(Self { fps: (self.fps - 2).max(MIN_FPS), ..self.clone() })
+        } else {
+            None
+        }
+    }
+
+    fn filters(&self) -> String {
+        match self.width {
+            Some(width) => format!("fps={},scale={}:-2:flags=lanczos", self.fps.max(1), width),
+            None => format!("fps={}", self.fps.max(1)),
+        }
+    }
+
+    // The muxers count differently: GIF's -loop is extra repeats (-1 for none),
+    // WebP's is the total number of plays
+    fn loop_arg(&self, format: AnimatedFormat) -> i64 {
+        match (format, self.plays) {
+            (_, 0) => 0,
+            (AnimatedFormat::Gif, 1) => -1,
+            (AnimatedFormat::Gif, plays) => i64::from(plays) - 1,
+            (AnimatedFormat::WebP, plays) => i64::from(plays),
+        }
+    }
+}
+
+fn run(cmd: &mut Command, what: &str) -> Result<(), String> {
+    let out = cmd.stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed: {}", what, String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+fn video_width(input: &Path) -> Result<u32, String> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width", "-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .map_err(|e| format!("Failed to start ffprobe: {}", e))?;
+    String::from_utf8_lossy(&out.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| format!("Could not read the video size of {:?}", input))
+}
+
+// palettegen then paletteuse: one palette for the whole clip, built from what
+// changes between frames rather than the mostly static background
+fn encode(input: &Path, output: &Path, format: AnimatedFormat, options: &AnimatedOptions) -> Result<(), String> {
+    let palette = output.with_extension("palette.png");
+    let filters = options.filters();
+
+    let pass1 = run(
+        Command::new("ffmpeg")
+            .arg("-v").arg("error")
+            .arg("-i").arg(input)
+            .arg("-vf").arg(format!("{},palettegen=stats_mode=diff", filters))
+            .arg("-y").arg(&palette),
+        "Palette generation",
+    );
+    if let Err(e) = pass1 {
+        let _ = std::fs::remove_file(&palette);
+        return Err(e);
+    }
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-i").arg(input)
+       .arg("-i").arg(&palette)
+       .arg("-lavfi").arg(format!(
+           "{}[x];[x][1:v]paletteuse={}:diff_mode=rectangle",
+           filters,
+           options.dither.paletteuse_options()
+       ));
+    match format {
+        AnimatedFormat::Gif => {
+            cmd.arg("-f").arg("gif");
+        }
+        AnimatedFormat::WebP => {
+            cmd.arg("-c:v").arg("libwebp_anim")
+               .arg("-quality").arg("75")
+               .arg("-f").arg("webp");
+        }
+    }
+    cmd.arg("-loop").arg(options.loop_arg(format).to_string())
+       .arg("-y").arg(output);
+
+    let pass2 = run(&mut cmd, "Export");
+    let _ = std::fs::remove_file(&palette);
+    pass2
+}
+
+/// Turns the video `input` into an animated GIF or WebP at `output`. With a
+/// size target, the export is repeated at lower settings until it fits.
+pub fn export(input: &Path, output: &Path, format: AnimatedFormat, options: &AnimatedOptions) -> Result<PathBuf, String> {
+    let source_width = video_width(input)?;
+    let mut attempt = options.clone();
+    loop {
+        encode(input, output, format, &attempt)?;
+        let Some(max_kb) = attempt.max_kb else {
+            return Ok(output.to_path_buf());
+        };
+        let size = std::fs::metadata(output).map(|m| m.len()).unwrap_or(0);
+        if size <= u64::from(max_kb) * 1024 {
+            return Ok(output.to_path_buf());
+        }
+        attempt = attempt.shrink(source_width).ok_or_else(|| {
+            format!("{:?} is {} KB even at {} fps, over the {} KB target", output, size / 1024, attempt.fps, max_kb)
+        })?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loop_counts_per_muxer() {
+        let once = AnimatedOptions { plays: 1, ..AnimatedOptions::default() };
+        assert_eq!(once.loop_arg(AnimatedFormat::Gif), -1);
+        assert_eq!(once.loop_arg(AnimatedFormat::WebP), 1);
+        let thrice = AnimatedOptions { plays: 3, ..AnimatedOptions::default() };
+        assert_eq!(thrice.loop_arg(AnimatedFormat::Gif), 2);
+        assert_eq!(AnimatedOptions::default().loop_arg(AnimatedFormat::Gif), 0);
+    }
+
+    #[test]
+    fn shrinks_fps_then_width_then_fps() {
+        let mut options = AnimatedOptions { fps: 20, width: None, ..AnimatedOptions::default() };
+        let mut steps = Vec::new();
+        while let Some(next) = options.shrink(640) {
+            steps.push((next.fps, next.width));
+            options = next;
+        }
+        assert_eq!(steps[0], (15, None));
+        assert_eq!(steps[1], (11, None));
+        assert_eq!(steps[2], (10, None));
+        assert_eq!(steps[3], (10, Some(480)));
+        assert_eq!(*steps.last().unwrap(), (MIN_FPS, Some(202)));
+    }
+
+    #[test]
+    fn filter_strings() {
+        let options = AnimatedOptions { fps: 12, width: Some(480), dither: Dither::Bayer(9), ..AnimatedOptions::default() };
+        assert_eq!(options.filters(), "fps=12,scale=480:-2:flags=lanczos");
+        assert_eq!(options.dither.paletteuse_options(), "dither=bayer:bayer_scale=5");
+        assert_eq!(AnimatedFormat::from_container("webp"), Some(AnimatedFormat::WebP));
+        assert_eq!(AnimatedFormat::from_container("mp4"), None);
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn exports_a_testsrc_clip_under_a_size_target() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-gif-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let clip = dir.join("clip.mp4");
+        let status = Command::new("ffmpeg")
+            .args(["-v", "error", "-f", "lavfi", "-i", "testsrc=size=640x480:rate=30", "-t", "2", "-y"])
+            .arg(&clip)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let options = AnimatedOptions { width: None, max_kb: Some(300), ..AnimatedOptions::default() };
+        let gif = export(&clip, &dir.join("clip.gif"), AnimatedFormat::Gif, &options).unwrap();
+        assert!(std::fs::metadata(&gif).unwrap().len() <= 300 * 1024);
+        assert!(!dir.join("clip.palette.png").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use crate::metadata;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A labelled point in a recording, in seconds of recorded time (paused time
+/// doesn't count).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub seconds: f64,
+    pub label: String,
+}
+
+// What goes into `<name>.markers.json`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Sidecar {
+    recording: String, // File name, the sidecar sits next to it
+    duration: f64,
+    markers: Vec<Marker>,
+}
+
+// Containers whose muxers write chapters
+const CHAPTER_CONTAINERS: &[&str] = &["mp4", "mov", "m4a", "mkv"];
+
+pub fn supports_chapters(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| CHAPTER_CONTAINERS.contains(&e))
+}
+
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    recording.with_extension("markers.json")
+}
+
+// ffmetadata treats these as syntax
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// One chapter per marker, each running until the next marker or the end.
+/// Anything before the first marker becomes a "Start" chapter.
+pub fn to_ffmetadata(markers: &[Marker], duration: f64) -> String {
+    let mut markers: Vec<&Marker> = markers.iter().filter(|m| m.seconds < duration).collect();
+    markers.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+
+    let start = Marker { seconds: 0.0, label: "Start".to_string() };
+    if markers.first().is_none_or(|m| m.seconds > 0.0) {
+        markers.insert(0, &start);
+    }
+
+    let ms = |seconds: f64| (seconds * 1000.0).round() as u64;
+    let mut text = String::from(";FFMETADATA1\n");
+    for (i, marker) in markers.iter().enumerate() {
+        let end = markers.get(i + 1).map_or(duration, |next| next.seconds);
+        text.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            ms(marker.seconds),
+            ms(end),
+            escape(&marker.label)
+        ));
+    }
+    text
+}
+
+pub fn write_sidecar(recording: &Path, markers: &[Marker], duration: f64) -> Result<PathBuf, String> {
+    let path = sidecar_path(recording);
+    let sidecar = Sidecar {
+        recording: recording.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        duration,
+        markers: markers.to_vec(),
+    };
+    let text = serde_json::to_string_pretty(&sidecar).map_err(|e| format!("Failed to serialize markers: {}", e))?;
+    std::fs::write(&path, text + "\n").map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(path)
+}
+
+/// Remuxes `recording` with the markers as chapters. The streams are copied.
+pub fn embed(recording: &Path, markers: &[Marker], duration: f64) -> Result<(), String> {
+    let ext = recording.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    let ffmetadata = recording.with_extension("ffmetadata");
+    let remuxed = recording.with_file_name(format!("{}.chapters.{}", stem, ext));
+    std::fs::write(&ffmetadata, to_ffmetadata(markers, duration))
+        .map_err(|e| format!("Failed to write {:?}: {}", ffmetadata, e))?;
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-i").arg(recording)
+       .arg("-i").arg(&ffmetadata)
+       .arg("-map").arg("0")
+       .arg("-map_metadata").arg("0")
+       .arg("-map_chapters").arg("1")
+       .arg("-c").arg("copy");
+    if ext != "mkv" {
+        cmd.arg("-movflags").arg(format!("+faststart{}", metadata::MOV_FLAG));
+    }
+    let out = cmd.arg("-y").arg(&remuxed)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e));
+    let _ = std::fs::remove_file(&ffmetadata);
+
+    let out = out?;
+    if !out.status.success() {
+        let _ = std::fs::remove_file(&remuxed);
+        return Err(format!("Adding chapters failed: {}", String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    std::fs::rename(&remuxed, recording).map_err(|e| format!("Failed to replace {:?}: {}", recording, e))
+}
+
+/// Writes the sidecar and, where the container allows it, the chapters.
+pub fn save(recording: &Path, markers: &[Marker], duration: f64) -> Result<(), String> {
+    if markers.is_empty() {
+        return Ok(());
+    }
+    write_sidecar(recording, markers, duration)?;
+    if supports_chapters(recording) {
+        embed(recording, markers, duration)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(seconds: f64, label: &str) -> Marker {
+        Marker { seconds, label: label.to_string() }
+    }
+
+    #[test]
+    fn markers_become_chapters_until_the_next_one() {
+        let markers = [marker(75.5, "Q&A; questions"), marker(12.0, "Feature X"), marker(200.0, "After the end")];
+        assert_eq!(
+            to_ffmetadata(&markers, 90.0),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=12000\ntitle=Start\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=12000\nEND=75500\ntitle=Feature X\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=75500\nEND=90000\ntitle=Q&A\\; questions\n"
+        );
+        assert!(supports_chapters(Path::new("demo.mkv")));
+        assert!(!supports_chapters(Path::new("demo.webm")));
+    }
+
+    #[test]
+    fn sidecar_lists_the_markers() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-chapters-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("demo.webm");
+
+        let markers = vec![marker(3.25, "Feature X")];
+        let path = write_sidecar(&recording, &markers, 10.0).unwrap();
+        assert_eq!(path, dir.join("demo.markers.json"));
+        let sidecar: Sidecar = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(sidecar, Sidecar { recording: "demo.webm".to_string(), duration: 10.0, markers });
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn embeds_chapters_into_mp4() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-chapters-mp4-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let clip = dir.join("clip.mp4");
+        let status = Command::new("ffmpeg")
+            .args(["-v", "error", "-f", "lavfi", "-i", "testsrc=size=320x240:rate=30", "-t", "4", "-c:v", "libx264", "-y"])
+            .arg(&clip)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        save(&clip, &[marker(1.0, "One"), marker(2.5, "Two")], 4.0).unwrap();
+        let out = Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "chapter_tags=title", "-of", "csv=p=0"])
+            .arg(&clip)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout).lines().collect::<Vec<_>>(), ["Start", "One", "Two"]);
+        assert!(sidecar_path(&clip).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
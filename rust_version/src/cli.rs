@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: rust_version [OPTIONS]
+
+Options:
+  --profile <NAME>   Start with a saved recording profile
+  --list-profiles    Print the saved profiles and exit
+  --stream <TARGET>  Stream live to rtmp://, rtmps://, srt:// or an HLS directory
+  --stream-bitrate <KBPS>
+                     Video bitrate for streaming
+  --record-copy      While streaming, also record to a file
+  --start <WHEN>     Schedule a recording: \"in 10m\", \"09:00\", \"2026-10-19 09:00\",
+                     \"weekdays 09:00\" or \"mon,thu 14:00\" (uses --profile if given)
+  --stop <WHEN>      Stop the scheduled recording \"after 30m\" or \"at 10:00\"
+  --list-schedules   Print the saved schedules and exit
+  -h, --help         Print this help
+
+Control a running instance with `rust_version ctl <COMMAND>`, see `ctl --help`.";
+
+pub const CTL_USAGE: &str = "Usage: rust_version ctl <COMMAND>
+
+Commands:
+  start [--profile <NAME> | --config <FILE>]
+                     Start recording, with a saved profile or a profile file
+                     (.toml or .json)
+  stop               Stop recording
+  pause              Pause the recording
+  resume             Resume the recording
+  marker [LABEL]     Add a chapter marker
+  status             Print what the recorder is doing
+
+The socket is $SCREEN_RECORDER_SOCKET, or screen-recorder.sock in $XDG_RUNTIME_DIR.";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub profile: Option<String>,
+    pub list_profiles: bool,
+    pub stream: Option<String>,
+    pub stream_bitrate: Option<u32>,
+    pub record_copy: bool,
+    pub start: Option<String>,
+    pub stop: Option<String>,
+    pub list_schedules: bool,
+    pub help: bool,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<CliArgs, String> {
+    let mut parsed = CliArgs::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+
+        match flag.as_str() {
+            "--profile" => parsed.profile = Some(value()?),
+            "--list-profiles" => parsed.list_profiles = true,
+            "--stream" => parsed.stream = Some(value()?),
+            "--stream-bitrate" => {
+                let value = value()?;
+                parsed.stream_bitrate = Some(value.parse().map_err(|_| format!("Invalid bitrate: {}", value))?);
+            }
+            "--record-copy" => parsed.record_copy = true,
+            "--start" => parsed.start = Some(value()?),
+            "--stop" => parsed.stop = Some(value()?),
+            "--list-schedules" => parsed.list_schedules = true,
+            "-h" | "--help" => parsed.help = true,
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if parsed.stop.is_some() && parsed.start.is_none() {
+        return Err("--stop needs --start".to_string());
+    }
+    Ok(parsed)
+}
+
+/// A `rust_version ctl` command for a running instance.
+#[derive(Debug, PartialEq)]
+pub enum CtlCommand {
+    Start { profile: Option<String>, config: Option<PathBuf> },
+    Stop,
+    Pause,
+    Resume,
+    Marker { label: Option<String> },
+    Status,
+    Help,
+}
+
+impl CtlCommand {
+    /// The JSON-RPC method it becomes.
+    pub fn method(&self) -> &'static str {
+        match self {
+            CtlCommand::Start { .. } => "start",
+            CtlCommand::Stop => "stop",
+            CtlCommand::Pause => "pause",
+            CtlCommand::Resume => "resume",
+            CtlCommand::Marker { .. } => "marker",
+            CtlCommand::Status | CtlCommand::Help => "status",
+        }
+    }
+}
+
+/// Parses what follows `ctl`.
+pub fn parse_ctl<I: IntoIterator<Item = String>>(args: I) -> Result<CtlCommand, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("ctl needs a command")?;
+    let rest: Vec<String> = args.collect();
+    let no_args = |command: CtlCommand| match rest.first() {
+        Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+        None => Ok(command),
+    };
+
+    match command.as_str() {
+        "start" => {
+            let (mut profile, mut config) = (None, None);
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                let (flag, inline_value) = match arg.split_once('=') {
+                    Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                    _ => (arg.as_str(), None),
+                };
+                let mut value = || inline_value.clone().or_else(|| rest.next().cloned()).ok_or_else(|| format!("{} needs a value", flag));
+                match flag {
+                    "--profile" => profile = Some(value()?),
+                    "--config" => config = Some(PathBuf::from(value()?)),
+                    _ => return Err(format!("Unknown argument: {}", arg)),
+                }
+            }
+            if profile.is_some() && config.is_some() {
+                return Err("Use either --profile or --config".to_string());
+            }
+            Ok(CtlCommand::Start { profile, config })
+        }
+        "stop" => no_args(CtlCommand::Stop),
+        "pause" => no_args(CtlCommand::Pause),
+        "resume" => no_args(CtlCommand::Resume),
+        "marker" => match rest.as_slice() {
+            [] => Ok(CtlCommand::Marker { label: None }),
+            [label] => Ok(CtlCommand::Marker { label: Some(label.clone()) }),
+            _ => Err("marker takes one label, quote it if it has spaces".to_string()),
+        },
+        "status" => no_args(CtlCommand::Status),
+        "-h" | "--help" | "help" => Ok(CtlCommand::Help),
+        _ => Err(format!("Unknown command: {}", command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &[&str]) -> Result<CliArgs, String> {
+        parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parses_profile_in_both_forms() {
+        assert_eq!(parse_str(&["--profile", "Bug report"]).unwrap().profile.as_deref(), Some("Bug report"));
+        assert_eq!(parse_str(&["--profile=Talk recording"]).unwrap().profile.as_deref(), Some("Talk recording"));
+    }
+
+    #[test]
+    fn parses_stream_options() {
+        let args = parse_str(&["--stream", "srt://10.0.0.2:9000", "--stream-bitrate=2500", "--record-copy"]).unwrap();
+        assert_eq!(args.stream.as_deref(), Some("srt://10.0.0.2:9000"));
+        assert_eq!(args.stream_bitrate, Some(2500));
+        assert!(args.record_copy);
+        assert!(parse_str(&["--stream-bitrate", "fast"]).is_err());
+    }
+
+    #[test]
+    fn parses_schedule_options() {
+        let args = parse_str(&["--start", "weekdays 09:00", "--stop=after 15m", "--profile", "Standup"]).unwrap();
+        assert_eq!(args.start.as_deref(), Some("weekdays 09:00"));
+        assert_eq!(args.stop.as_deref(), Some("after 15m"));
+        assert!(parse_str(&["--stop", "10:00"]).is_err());
+    }
+
+    #[test]
+    fn parses_ctl_commands() {
+        let ctl = |args: &[&str]| parse_ctl(args.iter().map(|s| s.to_string()));
+        assert_eq!(ctl(&["start", "--profile=Demo"]), Ok(CtlCommand::Start { profile: Some("Demo".to_string()), config: None }));
+        assert_eq!(
+            ctl(&["start", "--config", "talk.toml"]),
+            Ok(CtlCommand::Start { profile: None, config: Some(PathBuf::from("talk.toml")) })
+        );
+        assert_eq!(ctl(&["marker", "Q and A"]), Ok(CtlCommand::Marker { label: Some("Q and A".to_string()) }));
+        assert_eq!(ctl(&["status"]).unwrap().method(), "status");
+        assert!(ctl(&["start", "--profile", "a", "--config", "b.json"]).is_err());
+        assert!(ctl(&["stop", "now"]).is_err());
+        assert!(ctl(&["record"]).is_err());
+        assert!(ctl(&[]).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_values_and_unknown_flags() {
+        assert!(parse_str(&["--profile"]).is_err());
+        assert!(parse_str(&["--record-everything"]).is_err());
+    }
+}
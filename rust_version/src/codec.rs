@@ -0,0 +1,485 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::process::Command;
+
+/// Video codecs for file recordings. Streams pick their own (see stream.rs).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    Vp9,
+    Av1Svt,
+    Av1Aom,
+    H264Lossless, // x264 -qp 0
+    Ffv1,
+    ProRes,
+    DnxHr,
+}
+
+pub const VIDEO_CODECS: &[VideoCodec] = &[
+    VideoCodec::H264,
+    VideoCodec::Hevc,
+    VideoCodec::Vp9,
+    VideoCodec::Av1Svt,
+    VideoCodec::Av1Aom,
+    VideoCodec::H264Lossless,
+    VideoCodec::Ffv1,
+    VideoCodec::ProRes,
+    VideoCodec::DnxHr,
+];
+
+/// Containers for file recordings, with what the GUI calls them.
+pub const CONTAINERS: &[(&str, &str)] = &[
+    ("mp4", "MP4"),
+    ("webm", "WebM"),
+    ("mkv", "Matroska (MKV)"),
+    ("mov", "QuickTime (MOV)"),
+];
+
+impl VideoCodec {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "H.264",
+            VideoCodec::Hevc => "HEVC (H.265)",
+            VideoCodec::Vp9 => "VP9",
+            VideoCodec::Av1Svt => "AV1 (SVT-AV1)",
+            VideoCodec::Av1Aom => "AV1 (libaom)",
+            VideoCodec::H264Lossless => "H.264 lossless",
+            VideoCodec::Ffv1 => "FFV1 lossless",
+            VideoCodec::ProRes => "ProRes 422 HQ",
+            VideoCodec::DnxHr => "DNxHR HQ",
+        }
+    }
+
+    /// The ffmpeg encoder this needs.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 | VideoCodec::H264Lossless => "libx264",
+            VideoCodec::Hevc => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1Svt => "libsvtav1",
+            VideoCodec::Av1Aom => "libaom-av1",
+            VideoCodec::Ffv1 => "ffv1",
+            VideoCodec::ProRes => "prores_ks",
+            VideoCodec::DnxHr => "dnxhd",
+        }
+    }
+
+    /// What a container gets when no codec is chosen.
+    pub fn default_for(container: &str) -> Self {
+        match container {
+            "webm" => VideoCodec::Vp9,
+            _ => VideoCodec::H264,
+        }
+    }
+
+    /// The codec ffprobe calls `name`, as it would be recorded. 4:4:4 H.264
+    /// is taken as lossless, which is what this recorder writes it for.
+    pub fn from_probe(name: &str, pix_fmt: Option<&str>) -> Option<Self> {
+        match name {
+            "h264" if pix_fmt == Some(VideoCodec::H264Lossless.pix_fmt()) => Some(VideoCodec::H264Lossless),
+            "h264" => Some(VideoCodec::H264),
+            "hevc" => Some(VideoCodec::Hevc),
+            "vp9" => Some(VideoCodec::Vp9),
+            "av1" => Some(VideoCodec::Av1Svt),
+            "ffv1" => Some(VideoCodec::Ffv1),
+            "prores" => Some(VideoCodec::ProRes),
+            "dnxhd" => Some(VideoCodec::DnxHr),
+            _ => None,
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, VideoCodec::H264Lossless | VideoCodec::Ffv1)
+    }
+
+    /// Containers that can hold this codec and that players actually handle.
+    pub fn containers(&self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::H264Lossless => &["mp4", "mkv", "mov"],
+            VideoCodec::Vp9 => &["webm", "mkv"],
+            VideoCodec::Av1Svt | VideoCodec::Av1Aom => &["mp4", "webm", "mkv"],
+            // Lossless FFV1 is an archive format, Matroska is its home
+            VideoCodec::Ffv1 => &["mkv"],
+            VideoCodec::ProRes | VideoCodec::DnxHr => &["mov", "mkv"],
+        }
+    }
+
+    pub fn pix_fmt(&self) -> &'static str {
+        match self {
+            // x264's lossless mode would throw away chroma in 4:2:0
+            VideoCodec::H264Lossless => "yuv444p",
+            VideoCodec::Ffv1 => "bgr0", // The RGB the screen grabbers deliver, bit for bit
+            VideoCodec::ProRes => "yuv422p10le",
+            VideoCodec::DnxHr => "yuv422p",
+            _ => "yuv420p",
+        }
+    }
+
+    /// Whether the bitrate can be capped, which size-based splitting relies on.
+    pub fn rate_controlled(&self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Vp9 | VideoCodec::Av1Svt | VideoCodec::Av1Aom)
+    }
+
+    /// Rough video bitrate of a 1080p30 screen recording, for sizing segments.
+    pub fn nominal_kbps(&self) -> u32 {
+        match self {
+            VideoCodec::H264 => 4000,
+            VideoCodec::Hevc => 2500,
+            VideoCodec::Vp9 | VideoCodec::Av1Svt | VideoCodec::Av1Aom => 2000,
+            VideoCodec::H264Lossless => 40_000,
+            VideoCodec::Ffv1 => 80_000,
+            VideoCodec::DnxHr => 180_000,
+            VideoCodec::ProRes => 220_000,
+        }
+    }
+
+    /// Fails when `container` can't hold this codec.
+    pub fn check_container(&self, container: &str) -> Result<(), String> {
+        let containers = self.containers();
+        if containers.contains(&container) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} can't be recorded to {}, use {}",
+                self.label(),
+                container.to_uppercase(),
+                containers.iter().map(|c| c.to_uppercase()).collect::<Vec<_>>().join(", ")
+            ))
+        }
+    }
+
+    /// Encoder options, placed before the output.
+    pub fn encoder_args(&self, container: &str) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-c:v".into(), self.encoder().into()];
+        let extra: &[&str] = match self {
+            VideoCodec::H264 => &["-preset", "ultrafast", "-crf", "23"], // fast encoding for real-time
+            VideoCodec::Hevc => &["-preset", "ultrafast", "-crf", "28"],
+            VideoCodec::Vp9 => &["-b:v", "2M"],
+            VideoCodec::Av1Svt => &["-preset", "10", "-crf", "35"],
+            // libaom only does constant quality with -b:v 0
+            VideoCodec::Av1Aom => &["-usage", "realtime", "-cpu-used", "8", "-row-mt", "1", "-crf", "35", "-b:v", "0"],
+            VideoCodec::H264Lossless => &["-preset", "ultrafast", "-qp", "0"],
+            // Intra-only with per-slice checksums, so damage stays local
+            VideoCodec::Ffv1 => &["-level", "3", "-g", "1", "-slices", "4", "-slicecrc", "1"],
+            VideoCodec::ProRes => &["-profile:v", "3", "-vendor", "apl0"],
+            VideoCodec::DnxHr => &["-profile:v", "dnxhr_hq"],
+        };
+        args.extend(extra.iter().map(|s| s.to_string()));
+        if *self != VideoCodec::Vp9 {
+            args.extend(["-pix_fmt".into(), self.pix_fmt().into()]);
+        }
+        if *self == VideoCodec::Hevc && container != "mkv" {
+            // Apple players only take HEVC tagged as hvc1
+            args.extend(["-tag:v".into(), "hvc1".into()]);
+        }
+        args
+    }
+}
+
+/// Containers for audio-only recordings.
+pub const AUDIO_CONTAINERS: &[(&str, &str)] = &[
+    ("ogg", "Opus (OGG)"),
+    ("flac", "FLAC"),
+    ("wav", "WAV"),
+    ("m4a", "M4A (AAC)"),
+];
+
+/// Audio codecs for file recordings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+    Flac,
+    Pcm, // 16-bit little-endian
+}
+
+pub const AUDIO_CODECS: &[AudioCodec] = &[AudioCodec::Aac, AudioCodec::Opus, AudioCodec::Flac, AudioCodec::Pcm];
+
+impl AudioCodec {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "AAC",
+            AudioCodec::Opus => "Opus",
+            AudioCodec::Flac => "FLAC",
+            AudioCodec::Pcm => "PCM",
+        }
+    }
+
+    /// The codec ffprobe calls `name`, if it is one of these.
+    pub fn from_probe(name: &str) -> Option<Self> {
+        AUDIO_CODECS.iter().copied().find(|c| c.encoder().trim_start_matches("lib") == name)
+    }
+
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Flac => "flac",
+            AudioCodec::Pcm => "pcm_s16le",
+        }
+    }
+
+    /// What a container gets when no codec is chosen. Lossless and editing
+    /// video gets lossless audio to match.
+    pub fn default_for(container: &str, video: VideoCodec) -> Self {
+        let lossless_video = matches!(
+            video,
+            VideoCodec::H264Lossless | VideoCodec::Ffv1 | VideoCodec::ProRes | VideoCodec::DnxHr
+        );
+        match container {
+            "webm" | "ogg" => AudioCodec::Opus,
+            "flac" => AudioCodec::Flac,
+            "wav" => AudioCodec::Pcm,
+            "mkv" if lossless_video => AudioCodec::Flac,
+            "mov" if lossless_video => AudioCodec::Pcm,
+            _ => AudioCodec::Aac,
+        }
+    }
+
+    pub fn containers(&self) -> &'static [&'static str] {
+        match self {
+            AudioCodec::Aac => &["mp4", "mov", "mkv", "m4a"],
+            AudioCodec::Opus => &["webm", "mkv", "mp4", "ogg"],
+            AudioCodec::Flac => &["mkv", "mp4", "flac", "ogg"],
+            AudioCodec::Pcm => &["mov", "mkv", "wav"],
+        }
+    }
+
+    pub fn is_lossy(&self) -> bool {
+        matches!(self, AudioCodec::Aac | AudioCodec::Opus)
+    }
+
+    /// Sample rates the encoder takes. Opus only runs at a few fixed rates.
+    pub fn sample_rates(&self) -> &'static [u32] {
+        match self {
+            AudioCodec::Opus => &[8000, 12000, 16000, 24000, 48000],
+            _ => &[22050, 32000, 44100, 48000, 96000],
+        }
+    }
+
+    fn kbps_range(&self) -> (u32, u32) {
+        match self {
+            AudioCodec::Aac => (32, 512),
+            AudioCodec::Opus => (6, 510),
+            _ => (0, 0),
+        }
+    }
+}
+
+pub const DEFAULT_AUDIO_KBPS: u32 = 128;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+pub const MAX_CHANNELS: u32 = 2; // Opus needs channel mapping beyond stereo
+
+/// How audio is encoded. Unset fields use the defaults for the container.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub codec: Option<AudioCodec>,
+    pub kbps: u32, // Lossy codecs only
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            codec: None,
+            kbps: DEFAULT_AUDIO_KBPS,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            channels: 2,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// The codec actually used for `container` with `video`.
+    pub fn codec_for(&self, container: &str, video: VideoCodec) -> AudioCodec {
+        self.codec.unwrap_or_else(|| AudioCodec::default_for(container, video))
+    }
+
+    /// Checks the settings against the container and encoder limits.
+    pub fn validate(&self, container: &str, video: VideoCodec) -> Result<(), String> {
+        let codec = self.codec_for(container, video);
+        if !codec.containers().contains(&container) {
+            return Err(format!(
+                "{} audio can't be recorded to {}, use {}",
+                codec.label(),
+                container.to_uppercase(),
+                codec.containers().iter().map(|c| c.to_uppercase()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !codec.sample_rates().contains(&self.sample_rate) {
+            return Err(format!("{} doesn't support {} Hz", codec.label(), self.sample_rate));
+        }
+        if self.channels == 0 || self.channels > MAX_CHANNELS {
+            return Err(format!("{} channels are not supported", self.channels));
+        }
+        let (min, max) = codec.kbps_range();
+        if codec.is_lossy() && !(min..=max).contains(&self.kbps) {
+            return Err(format!("{} bitrate must be between {} and {} kbps", codec.label(), min, max));
+        }
+        Ok(())
+    }
+
+    /// Audio bitrate for sizing segments, at most what the codec can produce.
+    pub fn nominal_kbps(&self, container: &str, video: VideoCodec) -> u32 {
+        let raw = self.sample_rate * 16 * self.channels / 1000;
+        match self.codec_for(container, video) {
+            AudioCodec::Aac | AudioCodec::Opus => self.kbps,
+            AudioCodec::Flac => raw, // Usually well below, but never above
+            AudioCodec::Pcm => raw,
+        }
+    }
+
+    /// Encoder options, placed before the output.
+    pub fn encoder_args(&self, container: &str, video: VideoCodec) -> Vec<String> {
+        let codec = self.codec_for(container, video);
+        let mut args: Vec<String> = vec!["-c:a".into(), codec.encoder().into()];
+        if codec.is_lossy() {
+            args.extend(["-b:a".into(), format!("{}k", self.kbps)]);
+        }
+        args.extend([
+            "-ar".into(), self.sample_rate.to_string(),
+            "-ac".into(), self.channels.to_string(),
+        ]);
+        args
+    }
+}
+
+/// The encoders the installed ffmpeg was built with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    pub video_encoders: BTreeSet<String>,
+    pub audio_encoders: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Asks `ffmpeg -encoders`.
+    pub fn probe() -> Result<Self, String> {
+        let out = Command::new("ffmpeg")
+            .args(["-hide_banner", "-encoders"])
+            .output()
+            .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        Ok(parse_encoders(&String::from_utf8_lossy(&out.stdout)))
+    }
+
+    pub fn has_video(&self, codec: VideoCodec) -> bool {
+        self.video_encoders.contains(codec.encoder())
+    }
+
+    pub fn has_audio(&self, codec: AudioCodec) -> bool {
+        self.audio_encoders.contains(codec.encoder())
+    }
+
+    /// The container fits the codec and ffmpeg has the encoder.
+    pub fn validate(&self, codec: VideoCodec, container: &str) -> Result<(), String> {
+        codec.check_container(container)?;
+        if !self.has_video(codec) {
+            return Err(format!("This ffmpeg has no {} encoder for {}", codec.encoder(), codec.label()));
+        }
+        Ok(())
+    }
+
+    /// Like `validate`, for the audio settings.
+    pub fn validate_audio(&self, audio: &AudioSettings, container: &str, video: VideoCodec) -> Result<(), String> {
+        audio.validate(container, video)?;
+        let codec = audio.codec_for(container, video);
+        if !self.has_audio(codec) {
+            return Err(format!("This ffmpeg has no {} encoder for {}", codec.encoder(), codec.label()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of `ffmpeg -encoders`: a legend, a `------` line, then
+/// one ` V....D name  description` line per encoder.
+pub fn parse_encoders(text: &str) -> Capabilities {
+    let mut caps = Capabilities::default();
+    for line in text.lines().skip_while(|l| l.trim() != "------").skip(1) {
+        let mut fields = line.split_whitespace();
+        let (Some(flags), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        match flags.chars().next() {
+            Some('V') => caps.video_encoders.insert(name.to_string()),
+            Some('A') => caps.audio_encoders.insert(name.to_string()),
+            _ => false,
+        };
+    }
+    caps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODERS: &str = include_str!("../tests/fixtures/ffmpeg-encoders.txt");
+
+    #[test]
+    fn parses_encoder_list() {
+        let caps = parse_encoders(ENCODERS);
+        assert!(caps.video_encoders.contains("libx264"));
+        assert!(caps.video_encoders.contains("prores_ks"));
+        assert!(caps.audio_encoders.contains("libopus"));
+        // The legend and subtitle encoders are not encoders we can use
+        assert!(!caps.video_encoders.contains("="));
+        assert!(!caps.video_encoders.contains("webvtt"));
+    }
+
+    #[test]
+    fn validates_against_probed_encoders() {
+        let caps = parse_encoders(ENCODERS);
+        assert!(caps.validate(VideoCodec::Av1Aom, "webm").is_ok());
+        assert!(caps.validate(VideoCodec::Ffv1, "mkv").is_ok());
+        let missing = caps.validate(VideoCodec::Av1Svt, "mp4").unwrap_err();
+        assert!(missing.contains("libsvtav1"));
+        let wrong = caps.validate(VideoCodec::ProRes, "mp4").unwrap_err();
+        assert_eq!(wrong, "ProRes 422 HQ can't be recorded to MP4, use MOV, MKV");
+    }
+
+    #[test]
+    fn container_and_pixel_format_rules() {
+        assert_eq!(VideoCodec::default_for("webm"), VideoCodec::Vp9);
+        assert_eq!(VideoCodec::default_for("mov"), VideoCodec::H264);
+        assert!(VideoCodec::Hevc.check_container("webm").is_err());
+
+        let hevc = VideoCodec::Hevc.encoder_args("mp4").join(" ");
+        assert!(hevc.ends_with("-pix_fmt yuv420p -tag:v hvc1"));
+        assert!(!VideoCodec::Hevc.encoder_args("mkv").contains(&"hvc1".to_string()));
+        assert!(VideoCodec::H264Lossless.encoder_args("mkv").join(" ").contains("-qp 0 -pix_fmt yuv444p"));
+        // Every default codec is valid in its own container
+        for (container, _) in CONTAINERS {
+            let video = VideoCodec::default_for(container);
+            assert!(video.check_container(container).is_ok());
+            assert!(AudioSettings::default().validate(container, video).is_ok());
+        }
+        for (container, _) in AUDIO_CONTAINERS {
+            assert!(AudioSettings::default().validate(container, VideoCodec::H264).is_ok());
+        }
+    }
+
+    #[test]
+    fn audio_defaults_and_limits() {
+        assert_eq!(AudioCodec::default_for("webm", VideoCodec::Vp9), AudioCodec::Opus);
+        assert_eq!(AudioCodec::default_for("mov", VideoCodec::ProRes), AudioCodec::Pcm);
+        assert_eq!(AudioCodec::default_for("mkv", VideoCodec::Ffv1), AudioCodec::Flac);
+        assert_eq!(AudioCodec::default_for("wav", VideoCodec::H264), AudioCodec::Pcm);
+        assert_eq!(AudioCodec::default_for("m4a", VideoCodec::H264), AudioCodec::Aac);
+        assert_eq!(AudioCodec::from_probe("opus"), Some(AudioCodec::Opus));
+        assert_eq!(AudioCodec::from_probe("pcm_s16le"), Some(AudioCodec::Pcm));
+        assert_eq!(VideoCodec::from_probe("h264", Some("yuv444p")), Some(VideoCodec::H264Lossless));
+        assert_eq!(VideoCodec::from_probe("prores", Some("yuv422p10le")), Some(VideoCodec::ProRes));
+        assert_eq!(VideoCodec::from_probe("mpeg4", None), None);
+
+        let aac_in_webm = AudioSettings { codec: Some(AudioCodec::Aac), ..AudioSettings::default() };
+        assert!(aac_in_webm.validate("webm", VideoCodec::Vp9).is_err());
+        let opus_44k = AudioSettings { codec: Some(AudioCodec::Opus), sample_rate: 44100, ..AudioSettings::default() };
+        assert_eq!(opus_44k.validate("mkv", VideoCodec::H264).unwrap_err(), "Opus doesn't support 44100 Hz");
+        let quiet = AudioSettings { kbps: 16, ..AudioSettings::default() };
+        assert!(quiet.validate("mp4", VideoCodec::H264).is_err());
+        // The bitrate means nothing to lossless codecs
+        let flac = AudioSettings { codec: Some(AudioCodec::Flac), kbps: 0, ..AudioSettings::default() };
+        assert_eq!(flac.encoder_args("mkv", VideoCodec::H264), vec!["-c:a", "flac", "-ar", "48000", "-ac", "2"]);
+    }
+}
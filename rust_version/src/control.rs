@@ -0,0 +1,431 @@
+//! Local control API: JSON-RPC 2.0 over a Unix domain socket, one request
+//! and one response per line. `rust_version ctl` is the client.
+
+use crate::cli::CtlCommand;
+use crate::settings::Profile;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// What a client asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Start { profile: Option<String>, config: Option<Box<Profile>> },
+    Stop,
+    Pause,
+    Resume,
+    Marker { label: Option<String> },
+    Status,
+}
+
+/// The answer to `status`, also returned by `start`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub recording: bool,
+    pub paused: bool,
+    pub streaming: bool,
+    pub elapsed: f64, // Seconds, paused time doesn't count
+    pub markers: usize,
+    pub fps: Option<f64>,        // As ffmpeg last reported, while recording
+    pub size_bytes: Option<u64>, // Written so far, to all outputs
+    pub profile: Option<String>,
+    pub last_output: Option<PathBuf>,
+}
+
+/// A request waiting for the GUI thread to answer it.
+pub struct Call {
+    pub request: Request,
+    reply: mpsc::Sender<Result<Value, String>>,
+}
+
+impl Call {
+    pub fn answer(self, result: Result<Value, String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Hands `request` to the GUI thread, wakes it with `wake` and waits for the
+/// answer. Also used by the HTTP server.
+pub fn ask(calls: &mpsc::Sender<Call>, request: Request, wake: &dyn Fn()) -> Result<Value, String> {
+    let (reply, answer) = mpsc::channel();
+    calls.send(Call { request, reply }).map_err(|_| "The recorder is shutting down".to_string())?;
+    wake();
+    answer.recv_timeout(REPLY_TIMEOUT).unwrap_or_else(|_| Err("The recorder did not answer".to_string()))
+}
+
+// How long a client waits for the GUI thread. Stopping can take a while when
+// the recording is remuxed or exported.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(120);
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const RECORDER_ERROR: i64 = -32000;
+
+#[derive(Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct RawRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StartParams {
+    profile: Option<String>,
+    config: Option<Box<Profile>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MarkerParams {
+    label: Option<String>,
+}
+
+fn parse_params<T: Default + for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params).map_err(|e| RpcError { code: INVALID_PARAMS, message: e.to_string() })
+}
+
+// The request id (to echo back) and the request, or what is wrong with it
+fn parse(line: &str) -> (Value, Result<Request, RpcError>) {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return (Value::Null, Err(RpcError { code: PARSE_ERROR, message: e.to_string() })),
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let raw = match serde_json::from_value::<RawRequest>(value) {
+        Ok(raw) if raw.jsonrpc == "2.0" => raw,
+        Ok(_) => return (id, Err(RpcError { code: INVALID_REQUEST, message: "Only JSON-RPC 2.0 is spoken".to_string() })),
+        Err(e) => return (id, Err(RpcError { code: INVALID_REQUEST, message: e.to_string() })),
+    };
+    (raw.id, request(&raw.method, raw.params))
+}
+
+/// The request for `method` with its `params` (`null` when there are none).
+pub fn request(method: &str, params: Value) -> Result<Request, RpcError> {
+    match method {
+        "start" => parse_params::<StartParams>(params).and_then(|p| {
+            if p.profile.is_some() && p.config.is_some() {
+                return Err(RpcError { code: INVALID_PARAMS, message: "Give either a profile or a config".to_string() });
+            }
+            Ok(Request::Start { profile: p.profile, config: p.config })
+        }),
+        "stop" => Ok(Request::Stop),
+        "pause" => Ok(Request::Pause),
+        "resume" => Ok(Request::Resume),
+        "marker" => parse_params::<MarkerParams>(params).map(|p| Request::Marker { label: p.label }),
+        "status" => Ok(Request::Status),
+        other => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("Unknown method \"{}\"", other) }),
+    }
+}
+
+/// Answers one line of JSON-RPC, `handle` does the actual work.
+fn respond(line: &str, handle: impl FnOnce(Request) -> Result<Value, String>) -> String {
+    let (id, request) = parse(line);
+    let result = request.and_then(|request| {
+        handle(request).map_err(|message| RpcError { code: RECORDER_ERROR, message })
+    });
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } }),
+    };
+    response.to_string()
+}
+
+/// `$SCREEN_RECORDER_SOCKET`, or `screen-recorder.sock` in the user's runtime
+/// directory (`$XDG_RUNTIME_DIR`, falling back to the temp directory).
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("SCREEN_RECORDER_SOCKET") {
+        return PathBuf::from(path);
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("screen-recorder.sock"),
+        None => std::env::temp_dir().join(format!("screen-recorder-{}.sock", user_id())),
+    }
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
+
+/// Builds the request for `rust_version ctl` and sends it to `socket`.
+pub fn ctl(socket: &Path, command: &CtlCommand) -> Result<Value, String> {
+    let params = match command {
+        CtlCommand::Start { profile, config } => {
+            // Checked here so a typo isn't only found by the running instance
+            let config = config.as_deref().map(read_profile).transpose()?;
+            json!({ "profile": profile, "config": config })
+        }
+        CtlCommand::Marker { label } => json!({ "label": label }),
+        _ => Value::Null,
+    };
+    call(socket, command.method(), params)
+}
+
+// A profile as TOML (like in settings.toml) or JSON, judged by the extension
+fn read_profile(path: &Path) -> Result<Profile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    if path.extension().is_some_and(|e| e == "toml") {
+        toml::from_str(&text).map_err(|e| format!("Invalid profile {:?}: {}", path, e))
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid profile {:?}: {}", path, e))
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{Server, call};
+
+#[cfg(unix)]
+mod unix {
+    use super::{Call, REPLY_TIMEOUT, ask, respond};
+    use serde_json::{Value, json};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, mpsc};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    const POLL: Duration = Duration::from_millis(50);
+
+    type OnCall = Arc<dyn Fn() + Send + Sync>;
+
+    /// Listens on the socket until dropped. Requests are handed to the GUI
+    /// thread through `calls`, and `on_call` wakes it up.
+    pub struct Server {
+        path: PathBuf,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Server {
+        pub fn start(path: &Path, calls: mpsc::Sender<Call>, on_call: impl Fn() + Send + Sync + 'static) -> Result<Self, String> {
+            if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                if !metadata.file_type().is_socket() {
+                    return Err(format!("{:?} exists and is not a socket", path));
+                }
+                // Left behind by a crash, unless someone still answers
+                if UnixStream::connect(path).is_ok() {
+                    return Err(format!("Another instance is listening on {:?}", path));
+                }
+                let _ = std::fs::remove_file(path);
+            }
+            let listener = bind_private(path)?;
+            listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let stopped = stop.clone();
+            let on_call: OnCall = Arc::new(on_call);
+            let thread = std::thread::spawn(move || {
+                while !stopped.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let calls = calls.clone();
+                            let on_call = on_call.clone();
+                            std::thread::spawn(move || serve(stream, calls, on_call));
+                        }
+                        Err(_) => std::thread::sleep(POLL),
+                    }
+                }
+            });
+            Ok(Self { path: path.to_path_buf(), stop, thread: Some(thread) })
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    // Only this user may drive the recorder, from the moment the socket can be
+    // reached. It is bound in a directory nobody else can enter, made private
+    // and then moved into place. The umask is left alone, it is process-wide.
+    fn bind_private(path: &Path) -> Result<UnixListener, String> {
+        let name = path.file_name().ok_or_else(|| format!("{:?} is not a file path", path))?;
+        let dir = path.with_file_name(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)
+            .map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        let inside = dir.join("socket");
+        let bound = UnixListener::bind(&inside)
+            .and_then(|listener| {
+                std::fs::set_permissions(&inside, std::fs::Permissions::from_mode(0o600))?;
+                std::fs::rename(&inside, path)?;
+                Ok(listener)
+            })
+            .map_err(|e| format!("Failed to listen on {:?}: {}", path, e));
+        let _ = std::fs::remove_dir_all(&dir);
+        bound
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    // One client, as many requests as it sends
+    fn serve(stream: UnixStream, calls: mpsc::Sender<Call>, on_call: OnCall) {
+        if stream.set_nonblocking(false).is_err() {
+            return;
+        }
+        let Ok(reader) = stream.try_clone() else {
+            return;
+        };
+        let mut writer = stream;
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = respond(&line, |request| ask(&calls, request, on_call.as_ref()));
+            if writeln!(writer, "{}", response).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Sends one request and waits for the result.
+    pub fn call(path: &Path, method: &str, params: Value) -> Result<Value, String> {
+        let mut stream = UnixStream::connect(path)
+            .map_err(|e| format!("No recorder is listening on {:?}: {}", path, e))?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(5))).map_err(|e| e.to_string())?;
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        writeln!(stream, "{}", request).map_err(|e| format!("Failed to send the request: {}", e))?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).map_err(|e| format!("No answer: {}", e))?;
+        let response: Value = serde_json::from_str(&line).map_err(|e| format!("Invalid answer: {}", e))?;
+        match response.get("error") {
+            Some(error) => Err(error["message"].as_str().unwrap_or("Unknown error").to_string()),
+            None => Ok(response["result"].clone()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct Server;
+
+#[cfg(not(unix))]
+impl Server {
+    pub fn start(_: &Path, _: mpsc::Sender<Call>, _: impl Fn() + Send + Sync + 'static) -> Result<Self, String> {
+        Err("The control socket needs Unix domain sockets".to_string())
+    }
+
+    pub fn path(&self) -> &Path {
+        Path::new("")
+    }
+}
+
+#[cfg(not(unix))]
+pub fn call(_: &Path, _: &str, _: Value) -> Result<Value, String> {
+    Err("The control socket needs Unix domain sockets".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests_and_reports_errors() {
+        let (id, request) = parse(r#"{"jsonrpc":"2.0","id":7,"method":"start","params":{"profile":"Demo"}}"#);
+        assert_eq!(id, json!(7));
+        assert_eq!(request, Ok(Request::Start { profile: Some("Demo".to_string()), config: None }));
+
+        let (_, request) = parse(r#"{"jsonrpc":"2.0","id":1,"method":"start","params":{"config":{"format":"mkv"}}}"#);
+        let Ok(Request::Start { config: Some(config), .. }) = request else { panic!("{:?}", request) };
+        assert_eq!(config.format, "mkv");
+        assert_eq!(config.filename, Profile::default().filename);
+
+        assert_eq!(parse(r#"{"jsonrpc":"2.0","method":"marker"}"#).1, Ok(Request::Marker { label: None }));
+        let code = |line: &str| parse(line).1.unwrap_err().code;
+        assert_eq!(code("{not json"), PARSE_ERROR);
+        assert_eq!(code(r#"{"jsonrpc":"1.0","method":"stop"}"#), INVALID_REQUEST);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"record"}"#), METHOD_NOT_FOUND);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"marker","params":{"text":"x"}}"#), INVALID_PARAMS);
+    }
+
+    #[test]
+    fn recorder_errors_become_json_rpc_errors() {
+        let response = respond(r#"{"jsonrpc":"2.0","id":"a","method":"pause"}"#, |_| Err("Not recording".to_string()));
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response, json!({
+            "jsonrpc": "2.0",
+            "id": "a",
+            "error": { "code": RECORDER_ERROR, "message": "Not recording" },
+        }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_and_server_talk_over_the_socket() {
+        let path = std::env::temp_dir().join(format!("screen-recorder-control-{}.sock", std::process::id()));
+        let (calls, requests) = mpsc::channel();
+        let server = Server::start(&path, calls, || {}).unwrap();
+        assert!(Server::start(&path, mpsc::channel().0, || {}).is_err()); // Already taken
+        let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions());
+        assert_eq!(mode & 0o777, 0o600);
+        let name = format!(".{}.{}", path.file_name().unwrap().to_string_lossy(), std::process::id());
+        assert!(!path.with_file_name(name).exists()); // Bound in there, then moved out
+
+        // Stands in for the GUI thread
+        let app = std::thread::spawn(move || {
+            let mut markers = 0;
+            for call in requests.iter().take(3) {
+                let result = match &call.request {
+                    Request::Marker { label } => {
+                        markers += 1;
+                        Ok(json!({ "label": label.clone().unwrap_or(format!("Marker {}", markers)) }))
+                    }
+                    Request::Status => serde_json::to_value(Status { recording: true, markers, ..Status::default() })
+                        .map_err(|e| e.to_string()),
+                    _ => Err("Not recording".to_string()),
+                };
+                call.answer(result);
+            }
+        });
+
+        let marker = ctl(&path, &CtlCommand::Marker { label: Some("Intro".to_string()) }).unwrap();
+        assert_eq!(marker, json!({ "label": "Intro" }));
+        let status: Status = serde_json::from_value(ctl(&path, &CtlCommand::Status).unwrap()).unwrap();
+        assert!(status.recording);
+        assert_eq!(status.markers, 1);
+        assert_eq!(ctl(&path, &CtlCommand::Pause).unwrap_err(), "Not recording");
+
+        app.join().unwrap();
+        drop(server);
+        assert!(!path.exists());
+
+        // Anything but a stale socket is left alone
+        std::fs::write(&path, "notes").unwrap();
+        assert!(Server::start(&path, mpsc::channel().0, || {}).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+/// Countdown lengths offered in the GUI, 0 starts right away.
+pub const CHOICES: &[u32] = &[0, 3, 5, 10];
+
+/// Time between the overlay closing and the capture starting, so neither the
+/// overlay's last frame nor the minimizing window end up in the recording.
+pub const GRACE: Duration = Duration::from_millis(400);
+
+/// The seconds before a recording starts.
+#[derive(Clone, Copy, Debug)]
+pub struct Countdown {
+    ends: Instant,
+}
+
+impl Countdown {
+    pub fn new(seconds: u32, now: Instant) -> Self {
+        Self { ends: now + Duration::from_secs(u64::from(seconds)) }
+    }
+
+    /// The number to show, or `None` once the overlay should be gone.
+    pub fn remaining(&self, now: Instant) -> Option<u64> {
+        let left = self.ends.checked_duration_since(now).filter(|left| !left.is_zero())?;
+        Some(left.as_millis().div_ceil(1000) as u64)
+    }
+
+    /// Whether the capture should start now.
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.ends + GRACE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_down_then_waits_for_the_overlay_to_close() {
+        let now = Instant::now();
+        let countdown = Countdown::new(3, now);
+        assert_eq!(countdown.remaining(now), Some(3));
+        assert_eq!(countdown.remaining(now + Duration::from_millis(2100)), Some(1));
+        assert_eq!(countdown.remaining(now + Duration::from_secs(3)), None);
+        assert!(!countdown.is_due(now + Duration::from_secs(3)));
+        assert!(countdown.is_due(now + Duration::from_secs(3) + GRACE));
+
+        // Without a countdown there is still the grace period
+        let instant = Countdown::new(0, now);
+        assert_eq!(instant.remaining(now), None);
+        assert!(!instant.is_due(now));
+    }
+}
@@ -1,145 +1,538 @@
 use std::process::Command;
 
-#[derive(Debug, Clone)]
+/// Where a device comes from, which also decides how ffmpeg has to open it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Alsa,
+    Pulse,
+    V4l2,
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Dshow,
+    Lavfi, // Synthetic sources from the mock provider
+}
+
+impl Backend {
+    /// The value passed to ffmpeg's `-f` for inputs of this backend.
+    pub fn ffmpeg_format(&self) -> &'static str {
+        match self {
+            Backend::Alsa => "alsa",
+            Backend::Pulse => "pulse",
+            Backend::V4l2 => "v4l2",
+            Backend::Dshow => "dshow",
+            Backend::Lavfi => "lavfi",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Device {
     pub name: String,
-    pub id: String, // For Windows: name. For Linux: /dev/videoX or alsa name
+    pub id: String, // For Windows: name. For Linux: /dev/videoX, alsa or pulse name
+    pub group: Option<String>, // Sound card the device belongs to, if any
+    pub backend: Backend,
+}
+
+impl Device {
+    /// Name including the group, for places where the group header isn't shown.
+    pub fn label(&self) -> String {
+        match &self.group {
+            Some(group) => format!("{} - {}", group, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// A source of capture devices. Each platform API is one provider, the UI and
+/// the recorder only ever see the merged `Device` lists.
+pub trait DeviceProvider {
+    fn video_devices(&self) -> Vec<Device> {
+        Vec::new()
+    }
+
+    fn audio_devices(&self) -> Vec<Device> {
+        Vec::new()
+    }
+}
+
+/// Set this to get the mock provider instead of the real hardware, handy on
+/// headless machines and CI.
+pub const MOCK_DEVICES_ENV: &str = "SCREEN_RECORDER_MOCK_DEVICES";
+
+/// The providers available on this platform.
+pub fn system_providers() -> Vec<Box<dyn DeviceProvider>> {
+    if std::env::var_os(MOCK_DEVICES_ENV).is_some() {
+        return vec![Box::new(MockProvider::default())];
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        vec![Box::new(DshowProvider)]
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        vec![Box::new(V4l2Provider), Box::new(PulseProvider), Box::new(AlsaProvider)]
+    }
+}
+
+pub fn collect_video_devices(providers: &[Box<dyn DeviceProvider>]) -> Vec<Device> {
+    providers.iter().flat_map(|p| p.video_devices()).collect()
+}
+
+pub fn collect_audio_devices(providers: &[Box<dyn DeviceProvider>]) -> Vec<Device> {
+    let devices: Vec<Device> = providers.iter().flat_map(|p| p.audio_devices()).collect();
+
+    // Default is always safe, make sure it is offered even if no tool could be run
+    #[cfg(not(target_os = "windows"))]
+    if !devices.iter().any(|d| d.id == "default") {
+        let default = Device {
+            name: "Default".to_string(),
+            id: "default".to_string(),
+            group: None,
+            backend: Backend::Alsa,
+        };
+        return std::iter::once(default).chain(devices).collect();
+    }
+
+    devices
 }
 
-#[cfg(target_os = "windows")]
 pub fn get_video_devices() -> Vec<Device> {
-    parse_ffmpeg_dshow("video")
+    collect_video_devices(&system_providers())
 }
 
-#[cfg(target_os = "windows")]
 pub fn get_audio_devices() -> Vec<Device> {
-    parse_ffmpeg_dshow("audio")
+    collect_audio_devices(&system_providers())
+}
+
+/// Fake devices backed by ffmpeg's lavfi test sources, so recordings work
+/// without any real hardware attached.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    pub video: Vec<Device>,
+    pub audio: Vec<Device>,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self {
+            video: vec![Device {
+                name: "Test Pattern".to_string(),
+                id: "testsrc=size=640x480:rate=30".to_string(),
+                group: None,
+                backend: Backend::Lavfi,
+            }],
+            audio: vec![Device {
+                name: "Sine 440 Hz".to_string(),
+                id: "sine=frequency=440:sample_rate=48000".to_string(),
+                group: None,
+                backend: Backend::Lavfi,
+            }],
+        }
+    }
+}
+
+impl DeviceProvider for MockProvider {
+    fn video_devices(&self) -> Vec<Device> {
+        self.video.clone()
+    }
+
+    fn audio_devices(&self) -> Vec<Device> {
+        self.audio.clone()
+    }
 }
 
 #[cfg(not(target_os = "windows"))]
-pub fn get_video_devices() -> Vec<Device> {
-    let mut devices = Vec::new();
+pub struct V4l2Provider;
 
-    // Try v4l2-ctl first for nice names
-    if let Ok(output) = Command::new("v4l2-ctl").arg("--list-devices").output() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut current_name = String::new();
-        for line in stdout.lines() {
-            if !line.starts_with('\t') && !line.starts_with(' ') && !line.is_empty() {
-                // Device name (remove colon at end)
-                current_name = line.trim_end_matches(':').to_string();
-            } else if let Some(path) = line.trim().strip_prefix("/dev/video") {
-                // We found a device path like /dev/video0
-                // Usually the first one is capture, others might be metadata
-                let full_path = format!("/dev/video{}", path);
-                devices.push(Device {
-                    name: format!("{} ({})", current_name, full_path),
-                    id: full_path.clone(),
-                });
-                // We only take one path per device name to avoid duplicates for now
-                // Or we can list all. Let's list all unique paths.
-                current_name = String::new(); // Reset to avoid re-using name for next path if logic assumes 1:many
+#[cfg(not(target_os = "windows"))]
+impl DeviceProvider for V4l2Provider {
+    fn video_devices(&self) -> Vec<Device> {
+        let mut devices = Vec::new();
+
+        // Try v4l2-ctl first for nice names
+        if let Ok(output) = Command::new("v4l2-ctl").arg("--list-devices").output() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let mut current_name = String::new();
+            for line in stdout.lines() {
+                if !line.starts_with('\t') && !line.starts_with(' ') && !line.is_empty() {
+                    // Device name (remove colon at end)
+                    current_name = line.trim_end_matches(':').to_string();
+                } else if let Some(path) = line.trim().strip_prefix("/dev/video") {
+                    // We found a device path like /dev/video0
+                    // Usually the first one is capture, others might be metadata
+                    let full_path = format!("/dev/video{}", path);
+                    devices.push(Device {
+                        name: format!("{} ({})", current_name, full_path),
+                        id: full_path.clone(),
+                        group: None,
+                        backend: Backend::V4l2,
+                    });
+                }
             }
         }
-    }
 
-    // Fallback: Check /dev/video* if list is empty
-    if devices.is_empty() {
-        if let Ok(entries) = std::fs::read_dir("/dev") {
+        // Fallback: Check /dev/video* if list is empty
+        if devices.is_empty()
+            && let Ok(entries) = std::fs::read_dir("/dev")
+        {
             for entry in entries.flatten() {
-                if let Ok(name) = entry.file_name().into_string() {
-                    if name.starts_with("video") {
-                        let path = format!("/dev/{}", name);
-                        devices.push(Device {
-                            name: path.clone(),
-                            id: path,
-                        });
-                    }
+                if let Ok(name) = entry.file_name().into_string()
+                    && name.starts_with("video")
+                {
+                    let path = format!("/dev/{}", name);
+                    devices.push(Device {
+                        name: path.clone(),
+                        id: path,
+                        group: None,
+                        backend: Backend::V4l2,
+                    });
                 }
             }
         }
+
+        // Sort for consistency
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        devices
     }
+}
 
-    // Sort for consistency
-    devices.sort_by(|a, b| a.id.cmp(&b.id));
-    devices
+#[cfg(not(target_os = "windows"))]
+pub struct AlsaProvider;
+
+#[cfg(not(target_os = "windows"))]
+impl DeviceProvider for AlsaProvider {
+    fn audio_devices(&self) -> Vec<Device> {
+        match Command::new("arecord").arg("-L").output() {
+            Ok(output) => parse_arecord_list(&String::from_utf8_lossy(&output.stdout)),
+            Err(_) => Vec::new(),
+        }
+    }
 }
 
+/// PulseAudio, or PipeWire through its pulse compatibility layer.
 #[cfg(not(target_os = "windows"))]
-pub fn get_audio_devices() -> Vec<Device> {
+pub struct PulseProvider;
+
+#[cfg(not(target_os = "windows"))]
+impl DeviceProvider for PulseProvider {
+    fn audio_devices(&self) -> Vec<Device> {
+        match Command::new("pactl").args(["list", "sources"]).output() {
+            Ok(output) if output.status.success() => {
+                parse_pactl_sources(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Parses the output of `pactl list sources`, one `Source #N` block per device.
+/// Monitor sources (what is playing on an output) are grouped separately.
+#[cfg(not(target_os = "windows"))]
+pub fn parse_pactl_sources(output: &str) -> Vec<Device> {
     let mut devices = Vec::new();
+    let mut current: Option<(String, String)> = None;
 
-    // Default is always safe
-    devices.push(Device {
-        name: "Default".to_string(),
-        id: "default".to_string(),
-    });
-
-    // Try arecord -L
-    if let Ok(output) = Command::new("arecord").arg("-L").output() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        // Format:
-        // name
-        //     Description
-        //
-        // We want 'name' for the ID.
-        let mut lines = stdout.lines();
-        while let Some(line) = lines.next() {
-            if !line.starts_with(' ') && !line.starts_with('\t') && !line.is_empty() {
-                let id = line.trim().to_string();
-                // Skip cryptic names if possible, but for now include all
-                if id == "null" { continue; }
-
-                // Description is usually next line
-                // let desc = lines.next().map(|s| s.trim()).unwrap_or(&id);
-                // We'll just use ID as name for now, simpler.
+    let mut flush = |current: &mut Option<(String, String)>| {
+        if let Some((id, name)) = current.take()
+            && !id.is_empty()
+        {
+            let group = if id.ends_with(".monitor") {
+                Some("PulseAudio Monitors".to_string())
+            } else {
+                Some("PulseAudio".to_string())
+            };
+            let name = if name.is_empty() { id.clone() } else { name };
+            devices.push(Device { name, id, group, backend: Backend::Pulse });
+        }
+    };
 
-                devices.push(Device {
-                    name: id.clone(),
-                    id,
-                });
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Source #") {
+            flush(&mut current);
+            current = Some((String::new(), String::new()));
+        } else if let Some((id, name)) = current.as_mut() {
+            if let Some(value) = trimmed.strip_prefix("Name: ") {
+                *id = value.to_string();
+            } else if let Some(value) = trimmed.strip_prefix("Description: ") {
+                *name = value.to_string();
             }
         }
     }
+    flush(&mut current);
 
+    // Real inputs before monitors
+    devices.sort_by_key(|d| d.id.ends_with(".monitor"));
     devices
 }
 
+// PCM plugins that are playback-only, rate converters or otherwise not something
+// anyone would pick as a recording source.
+#[cfg(not(target_os = "windows"))]
+const HIDDEN_ALSA_PLUGINS: &[&str] = &[
+    "null", "dmix", "usbstream", "iec958", "spdif", "hdmi", "a52", "dca",
+    "lavrate", "samplerate", "speexrate", "speex", "upmix", "vdownmix", "jack", "oss",
+];
+
+/// Parses the output of `arecord -L`.
+///
+/// The format is a PCM name on its own line followed by one or more indented
+/// description lines:
+///
+/// ```text
+/// sysdefault:CARD=PCH
+///     HDA Intel PCH, ALC3246 Analog
+///     Default Audio Device
+/// ```
+///
+/// Entries bound to a card (`CARD=...`) get the first description line as their
+/// group and the rest as their name. Ungrouped entries (`default`, `pipewire`,
+/// ...) come first, followed by each card's entries kept together in the order
+/// the cards first appeared.
+#[cfg(not(target_os = "windows"))]
+pub fn parse_arecord_list(output: &str) -> Vec<Device> {
+    let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, desc)) = entries.last_mut() {
+                desc.push(line.trim().to_string());
+            }
+        } else {
+            entries.push((line.trim().to_string(), Vec::new()));
+        }
+    }
+
+    let mut ungrouped = Vec::new();
+    let mut cards: Vec<(String, Vec<Device>)> = Vec::new();
+
+    for (id, desc) in entries {
+        let plugin = id.split(':').next().unwrap_or(&id);
+        if HIDDEN_ALSA_PLUGINS.contains(&plugin) || plugin.starts_with("surround") {
+            continue;
+        }
+
+        let card = id
+            .split_once(':')
+            .and_then(|(_, args)| args.split(',').find_map(|arg| arg.strip_prefix("CARD=")))
+            .map(|card| card.to_string());
+
+        match card {
+            Some(card) if !desc.is_empty() => {
+                let group = desc[0].clone();
+                let name = if desc.len() > 1 {
+                    desc[1..].join(" ")
+                } else {
+                    id.clone()
+                };
+                let device = Device { name, id, group: Some(group), backend: Backend::Alsa };
+                match cards.iter_mut().find(|(c, _)| *c == card) {
+                    Some((_, list)) => list.push(device),
+                    None => cards.push((card, vec![device])),
+                }
+            }
+            _ => {
+                let name = if desc.is_empty() { id.clone() } else { desc.join(" ") };
+                ungrouped.push(Device { name, id, group: None, backend: Backend::Alsa });
+            }
+        }
+    }
+
+    ungrouped.extend(cards.into_iter().flat_map(|(_, list)| list));
+    ungrouped
+}
+
 #[cfg(target_os = "windows")]
-fn parse_ffmpeg_dshow(device_type: &str) -> Vec<Device> {
-    let mut devices = Vec::new();
+pub struct DshowProvider;
+
+#[cfg(target_os = "windows")]
+impl DeviceProvider for DshowProvider {
+    fn video_devices(&self) -> Vec<Device> {
+        list_dshow_devices("video")
+    }
+
+    fn audio_devices(&self) -> Vec<Device> {
+        list_dshow_devices("audio")
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn list_dshow_devices(device_type: &str) -> Vec<Device> {
     // ffmpeg -list_devices true -f dshow -i dummy
     // Output is in stderr
     let output = Command::new("ffmpeg")
-        .args(&["-list_devices", "true", "-f", "dshow", "-i", "dummy"])
+        .args(["-list_devices", "true", "-f", "dshow", "-i", "dummy"])
         .output();
 
-    if let Ok(out) = output {
-        let stderr = String::from_utf8_lossy(&out.stderr);
-        let mut in_section = false;
-
-        for line in stderr.lines() {
-            if line.contains("DirectShow video devices") {
-                in_section = device_type == "video";
-            } else if line.contains("DirectShow audio devices") {
-                in_section = device_type == "audio";
-            } else if in_section {
-                // Lines with devices look like: [dshow @ ...]  "Device Name"
-                // Alternative names look like:  [dshow @ ...]     Alternative name ...
-                if let Some(start) = line.find("\"") {
-                    if let Some(end) = line[start+1..].find("\"") {
-                        let name = &line[start+1..start+1+end];
-                        // Avoid empty names
-                        if !name.is_empty() {
-                            devices.push(Device {
-                                name: name.to_string(),
-                                id: name.to_string(), // dshow uses name as ID
-                            });
-                        }
-                    }
-                }
+    match output {
+        Ok(out) => parse_dshow_list(&String::from_utf8_lossy(&out.stderr), device_type),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Parses ffmpeg's dshow device listing. Older builds print section headers,
+/// newer ones tag each device line with `(video)` / `(audio)`.
+#[cfg(target_os = "windows")]
+pub fn parse_dshow_list(stderr: &str, device_type: &str) -> Vec<Device> {
+    let mut devices = Vec::new();
+    let mut in_section = false;
+
+    for line in stderr.lines() {
+        if line.contains("DirectShow video devices") {
+            in_section = device_type == "video";
+            continue;
+        } else if line.contains("DirectShow audio devices") {
+            in_section = device_type == "audio";
+            continue;
+        }
+
+        // Lines with devices look like: [dshow @ ...]  "Device Name"
+        // Alternative names look like:  [dshow @ ...]     Alternative name "..."
+        if line.contains("Alternative name") {
+            continue;
+        }
+        let tagged = line.trim_end().ends_with(&format!("({})", device_type));
+        let tagged_other = line.trim_end().ends_with(')') && line.contains("\" (");
+        if !(tagged || (in_section && !tagged_other)) {
+            continue;
+        }
+
+        if let Some(start) = line.find('"')
+            && let Some(end) = line[start + 1..].find('"')
+        {
+            let name = &line[start + 1..start + 1 + end];
+            // Avoid empty names
+            if !name.is_empty() {
+                devices.push(Device {
+                    name: name.to_string(),
+                    id: name.to_string(), // dshow uses name as ID
+                    group: None,
+                    backend: Backend::Dshow,
+                });
             }
         }
     }
     devices
 }
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+
+    const PACTL_SOURCES: &str = include_str!("../tests/fixtures/pactl-list-sources.txt");
+    const PIPEWIRE_LAPTOP: &str = include_str!("../tests/fixtures/arecord-L-pipewire.txt");
+    const TWO_CARDS: &str = include_str!("../tests/fixtures/arecord-L-two-cards.txt");
+
+    fn ids(devices: &[Device]) -> Vec<&str> {
+        devices.iter().map(|d| d.id.as_str()).collect()
+    }
+
+    #[test]
+    fn captures_multi_line_descriptions() {
+        let devices = parse_arecord_list(PIPEWIRE_LAPTOP);
+        let sysdefault = devices.iter().find(|d| d.id == "sysdefault:CARD=PCH").unwrap();
+        assert_eq!(sysdefault.group.as_deref(), Some("HDA Intel PCH, ALC3246 Analog"));
+        assert_eq!(sysdefault.name, "Default Audio Device");
+
+        let default = devices.iter().find(|d| d.id == "default").unwrap();
+        assert_eq!(default.group, None);
+        assert_eq!(default.name, "Default ALSA Output (currently PipeWire Media Server)");
+    }
+
+    #[test]
+    fn hides_plugin_aliases() {
+        let devices = parse_arecord_list(PIPEWIRE_LAPTOP);
+        let ids = ids(&devices);
+        for hidden in ["null", "lavrate", "samplerate", "speexrate", "upmix", "vdownmix", "dmix:CARD=PCH,DEV=0", "usbstream:CARD=PCH"] {
+            assert!(!ids.contains(&hidden), "{} should be hidden", hidden);
+        }
+        assert!(ids.contains(&"pipewire"));
+        assert!(ids.contains(&"hw:CARD=PCH,DEV=0"));
+    }
+
+    #[test]
+    fn groups_entries_by_card() {
+        let devices = parse_arecord_list(TWO_CARDS);
+        assert_eq!(
+            ids(&devices),
+            vec![
+                "default",
+                "pulse",
+                "sysdefault:CARD=PCH",
+                "front:CARD=PCH,DEV=0",
+                "dsnoop:CARD=PCH,DEV=0",
+                "hw:CARD=PCH,DEV=0",
+                "plughw:CARD=PCH,DEV=0",
+                "sysdefault:CARD=Webcam",
+                "front:CARD=Webcam,DEV=0",
+                "dsnoop:CARD=Webcam,DEV=0",
+                "hw:CARD=Webcam,DEV=0",
+                "plughw:CARD=Webcam,DEV=0",
+            ]
+        );
+        let webcam = devices.iter().find(|d| d.id == "hw:CARD=Webcam,DEV=0").unwrap();
+        assert_eq!(webcam.group.as_deref(), Some("C922 Pro Stream Webcam, USB Audio"));
+        assert_eq!(webcam.name, "Direct hardware device without any conversions");
+    }
+
+    #[test]
+    fn empty_output_yields_no_devices() {
+        assert!(parse_arecord_list("").is_empty());
+    }
+
+    #[test]
+    fn parses_pulse_sources_with_monitors_last() {
+        let devices = parse_pactl_sources(PACTL_SOURCES);
+        assert_eq!(
+            ids(&devices),
+            vec![
+                "alsa_input.pci-0000_00_1f.3.analog-stereo",
+                "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor",
+            ]
+        );
+        assert_eq!(devices[0].name, "Built-in Audio Analog Stereo");
+        assert_eq!(devices[0].backend, Backend::Pulse);
+        assert_eq!(devices[1].group.as_deref(), Some("PulseAudio Monitors"));
+    }
+
+    #[test]
+    fn mock_provider_feeds_both_lists() {
+        let providers: Vec<Box<dyn DeviceProvider>> = vec![Box::new(MockProvider::default())];
+        let video = collect_video_devices(&providers);
+        let audio = collect_audio_devices(&providers);
+        assert_eq!(video.len(), 1);
+        assert_eq!(video[0].backend, Backend::Lavfi);
+        // The ALSA default is added in front of whatever the providers found
+        assert_eq!(ids(&audio), vec!["default", "sine=frequency=440:sample_rate=48000"]);
+    }
+}
+
+#[cfg(all(test, target_os = "windows"))]
+mod dshow_tests {
+    use super::*;
+
+    #[test]
+    fn skips_alternative_names_and_other_sections() {
+        let stderr = "[dshow @ 0000] DirectShow video devices (some may be both video and audio devices)\n\
+                      [dshow @ 0000]  \"Integrated Camera\"\n\
+                      [dshow @ 0000]     Alternative name \"@device_pnp_\\\\?\\usb#vid\"\n\
+                      [dshow @ 0000] DirectShow audio devices\n\
+                      [dshow @ 0000]  \"Microphone (Realtek Audio)\"\n";
+        let audio = parse_dshow_list(stderr, "audio");
+        assert_eq!(audio.len(), 1);
+        assert_eq!(audio[0].id, "Microphone (Realtek Audio)");
+        assert_eq!(parse_dshow_list(stderr, "video")[0].id, "Integrated Camera");
+    }
+
+    #[test]
+    fn understands_tagged_device_lines() {
+        let stderr = "[dshow @ 0000] \"Integrated Camera\" (video)\n\
+                      [dshow @ 0000] \"Microphone (Realtek Audio)\" (audio)\n";
+        assert_eq!(parse_dshow_list(stderr, "audio")[0].id, "Microphone (Realtek Audio)");
+        assert_eq!(parse_dshow_list(stderr, "video")[0].id, "Integrated Camera");
+    }
+}
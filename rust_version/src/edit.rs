@@ -0,0 +1,445 @@
+use crate::animated::{self, AnimatedFormat, AnimatedOptions};
+use crate::codec::{self, AudioCodec, AudioSettings, VideoCodec};
+use crate::probe::{self, MediaInfo};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// What to keep of a recording: everything between `in_point` and
+/// `out_point`, minus the cut ranges. All times in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditPlan {
+    pub in_point: f64,
+    pub out_point: f64,
+    pub cuts: Vec<(f64, f64)>,
+}
+
+/// How an edit was exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportMethod {
+    StreamCopy, // Every kept range starts on a keyframe, no quality loss
+    SmartCut,   // Only from each cut to the next keyframe is re-encoded, in the source's codecs
+    ReEncode,   // The source's codecs can't be matched, everything is re-encoded
+}
+
+// A part of the output, in seconds of the input
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Piece {
+    Copy(f64, f64),
+    ReEncode(f64, f64),
+}
+
+// How close to a keyframe a cut has to be to count as on it, about a frame at 30 fps
+const KEYFRAME_TOLERANCE: f64 = 0.02;
+
+impl EditPlan {
+    /// The whole recording.
+    pub fn new(duration: f64) -> Self {
+        Self { in_point: 0.0, out_point: duration, cuts: Vec::new() }
+    }
+
+    /// The ranges that end up in the output, in order, without overlaps.
+    pub fn kept_ranges(&self) -> Vec<(f64, f64)> {
+        let mut cuts: Vec<(f64, f64)> = self.cuts.iter()
+            .map(|&(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut ranges = Vec::new();
+        let mut start = self.in_point;
+        for (cut_start, cut_end) in cuts {
+            if cut_start > start {
+                ranges.push((start, cut_start.min(self.out_point)));
+            }
+            start = start.max(cut_end);
+        }
+        ranges.push((start, self.out_point));
+        ranges.retain(|(a, b)| b - a > KEYFRAME_TOLERANCE);
+        ranges
+    }
+
+    /// Length of the result in seconds.
+    pub fn output_duration(&self) -> f64 {
+        self.kept_ranges().iter().map(|(a, b)| b - a).sum()
+    }
+
+    pub fn validate(&self, duration: f64) -> Result<(), String> {
+        if self.in_point < 0.0 || self.out_point > duration + KEYFRAME_TOLERANCE {
+            return Err(format!("In and out points must be within 0 and {:.2} s", duration));
+        }
+        if self.out_point <= self.in_point {
+            return Err("The out point must come after the in point".to_string());
+        }
+        if self.kept_ranges().is_empty() {
+            return Err("Nothing left to export".to_string());
+        }
+        Ok(())
+    }
+
+    // Stream copy only cuts cleanly at keyframes, so each kept range is
+    // copied from its first keyframe on and re-encoded before that. Where a
+    // range ends doesn't matter.
+    fn pieces(&self, keyframes: &[f64]) -> Vec<Piece> {
+        let on_keyframe = |t: f64| t <= KEYFRAME_TOLERANCE || keyframes.iter().any(|k| (k - t).abs() <= KEYFRAME_TOLERANCE);
+        let mut pieces = Vec::new();
+        for (start, end) in self.kept_ranges() {
+            if on_keyframe(start) {
+                pieces.push(Piece::Copy(start, end));
+                continue;
+            }
+            match keyframes.iter().copied().find(|&k| k > start && k < end - KEYFRAME_TOLERANCE) {
+                Some(keyframe) => {
+                    pieces.push(Piece::ReEncode(start, keyframe));
+                    pieces.push(Piece::Copy(keyframe, end));
+                }
+                None => pieces.push(Piece::ReEncode(start, end)),
+            }
+        }
+        pieces
+    }
+
+    // select/aselect expression matching the kept ranges
+    fn select_expr(&self) -> String {
+        self.kept_ranges().iter()
+            .map(|(a, b)| format!("between(t,{:.3},{:.3})", a, b))
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+fn run(cmd: &mut Command) -> Result<(), String> {
+    let out = cmd.stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Export failed: {}", String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+fn faststart(cmd: &mut Command, output: &Path) {
+    if output.extension().is_some_and(|e| e == "mp4" || e == "mov" || e == "m4a") {
+        cmd.arg("-movflags").arg("+faststart");
+    }
+}
+
+// The codec of `info`'s video if `container` takes it. Lossless video stays
+// lossless where the container allows, anything else gets the default.
+fn target_video(info: &MediaInfo, container: &str) -> VideoCodec {
+    let source = info.video_codec.as_deref().and_then(|name| VideoCodec::from_probe(name, info.pix_fmt.as_deref()));
+    match source {
+        Some(codec) if codec.check_container(container).is_ok() => codec,
+        Some(codec) if codec.is_lossless() => [VideoCodec::H264Lossless, VideoCodec::Ffv1]
+            .into_iter()
+            .find(|lossless| lossless.check_container(container).is_ok())
+            .unwrap_or_else(|| VideoCodec::default_for(container)),
+        _ => VideoCodec::default_for(container),
+    }
+}
+
+// Encoder options for `video`, matching the source's pixel format and
+// bitrate when it is the source's own codec
+fn video_args(info: &MediaInfo, video: VideoCodec, container: &str) -> Vec<String> {
+    let mut args = video.encoder_args(container);
+    let same_codec = info.video_codec.as_deref().and_then(|name| VideoCodec::from_probe(name, info.pix_fmt.as_deref())) == Some(video);
+    if !same_codec {
+        return args;
+    }
+    if let Some(pix_fmt) = &info.pix_fmt {
+        args.extend(["-pix_fmt".to_string(), pix_fmt.clone()]); // The last one wins
+    }
+    if let Some(kbps) = info.video_kbps.filter(|_| video.rate_controlled()) {
+        args.extend([
+            "-b:v".to_string(), format!("{}k", kbps),
+            "-maxrate".to_string(), format!("{}k", kbps),
+            "-bufsize".to_string(), format!("{}k", kbps * 2),
+        ]);
+    }
+    args
+}
+
+// The source's audio codec, bitrate, rate and channels if `container` takes
+// them, otherwise the container's default codec with as much of them as fits
+fn target_audio(info: &MediaInfo, container: &str, video: VideoCodec) -> AudioSettings {
+    let source = AudioSettings {
+        codec: info.audio_codec.as_deref().and_then(AudioCodec::from_probe),
+        kbps: info.audio_kbps.unwrap_or(codec::DEFAULT_AUDIO_KBPS),
+        sample_rate: info.sample_rate.unwrap_or(codec::DEFAULT_SAMPLE_RATE),
+        channels: info.channels.unwrap_or(2),
+    };
+    [source, AudioSettings { codec: None, ..source }, AudioSettings::default()]
+        .into_iter()
+        .find(|settings| settings.validate(container, video).is_ok())
+        .unwrap_or_default()
+}
+
+// Encoder options that reproduce the source exactly enough for re-encoded
+// pieces to be joined with copied ones, if its codecs are ones we write
+fn matching_args(info: &MediaInfo, container: &str) -> Option<Vec<String>> {
+    let video = VideoCodec::from_probe(info.video_codec.as_deref()?, info.pix_fmt.as_deref())?;
+    video.check_container(container).ok()?;
+    let mut args = video_args(info, video, container);
+    if info.has_audio() {
+        let audio = target_audio(info, container, video);
+        if audio.codec != info.audio_codec.as_deref().and_then(AudioCodec::from_probe) {
+            return None;
+        }
+        args.extend(audio.encoder_args(container, video));
+    }
+    Some(args)
+}
+
+// Cuts one piece out of `input`. Video and audio only, so copied and
+// re-encoded pieces have the same streams.
+fn export_piece(input: &Path, piece: Piece, encoder_args: &[String], to: &Path) -> Result<(), String> {
+    let (start, end) = match piece {
+        Piece::Copy(start, end) | Piece::ReEncode(start, end) => (start, end),
+    };
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-ss").arg(format!("{:.3}", start))
+       .arg("-i").arg(input)
+       .arg("-t").arg(format!("{:.3}", end - start))
+       .arg("-map").arg("0:v?")
+       .arg("-map").arg("0:a?");
+    match piece {
+        Piece::Copy(..) => {
+            cmd.arg("-c").arg("copy");
+        }
+        Piece::ReEncode(..) => {
+            cmd.args(encoder_args);
+        }
+    }
+    cmd.arg("-avoid_negative_ts").arg("make_zero");
+    faststart(&mut cmd, to);
+    cmd.arg("-y").arg(to);
+    run(&mut cmd)
+}
+
+// One ffmpeg per piece, then the pieces are joined with the concat demuxer
+fn export_pieces(input: &Path, output: &Path, pieces: &[Piece], encoder_args: &[String]) -> Result<(), String> {
+    if let [piece] = pieces {
+        return export_piece(input, *piece, encoder_args, output);
+    }
+
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let staging = output.with_extension("pieces");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| format!("Failed to create {:?}: {}", staging, e))?;
+
+    let result = (|| {
+        let mut list = String::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            let path = staging.join(format!("{:03}.{}", i, ext));
+            export_piece(input, *piece, encoder_args, &path)?;
+            list.push_str(&format!("file '{}'\n", path.display()));
+        }
+        let list_path = staging.join("list.txt");
+        std::fs::write(&list_path, list).map_err(|e| format!("Failed to write {:?}: {}", list_path, e))?;
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.arg("-v").arg("error")
+           .arg("-f").arg("concat")
+           .arg("-safe").arg("0")
+           .arg("-i").arg(&list_path)
+           .arg("-c").arg("copy");
+        faststart(&mut cmd, output);
+        cmd.arg("-y").arg(output);
+        run(&mut cmd)
+    })();
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+// A single pass that drops everything outside the kept ranges and closes the
+// gaps, for sources whose codecs can't be matched piece by piece
+fn export_reencode(input: &Path, output: &Path, plan: &EditPlan, info: &MediaInfo) -> Result<(), String> {
+    let container = output.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let video = target_video(info, container);
+    let select = plan.select_expr();
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-i").arg(input)
+       .arg("-vf").arg(format!("select='{}',setpts=N/FRAME_RATE/TB", select));
+    cmd.args(video_args(info, video, container));
+    if info.has_audio() {
+        cmd.arg("-af").arg(format!("aselect='{}',asetpts=N/SR/TB", select));
+        cmd.args(target_audio(info, container, video).encoder_args(container, video));
+    }
+    faststart(&mut cmd, output);
+    cmd.arg("-y").arg(output);
+    run(&mut cmd)
+}
+
+/// Writes the kept parts of `input` to `output`. Parts starting on a keyframe
+/// are copied; otherwise only up to the next keyframe is re-encoded, in the
+/// source's codecs and quality.
+pub fn export(input: &Path, output: &Path, plan: &EditPlan) -> Result<(PathBuf, ExportMethod), String> {
+    if input == output {
+        return Err("Export to a different file than the recording".to_string());
+    }
+    let info = probe::probe(input)?;
+    plan.validate(info.duration)?;
+
+    let keyframes = match info.video_codec {
+        Some(_) => probe::keyframes(input)?,
+        None => Vec::new(),
+    };
+    let pieces = match info.video_codec {
+        Some(_) => plan.pieces(&keyframes),
+        // Every audio packet is a keyframe
+        None => plan.kept_ranges().into_iter().map(|(a, b)| Piece::Copy(a, b)).collect(),
+    };
+    if pieces.iter().all(|p| matches!(p, Piece::Copy(..))) {
+        export_pieces(input, output, &pieces, &[])?;
+        return Ok((output.to_path_buf(), ExportMethod::StreamCopy));
+    }
+    let container = output.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    match matching_args(&info, container) {
+        Some(args) => {
+            export_pieces(input, output, &pieces, &args)?;
+            Ok((output.to_path_buf(), ExportMethod::SmartCut))
+        }
+        None => {
+            export_reencode(input, output, plan, &info)?;
+            Ok((output.to_path_buf(), ExportMethod::ReEncode))
+        }
+    }
+}
+
+/// Writes `input` to whatever `output`'s extension calls for. Streams the
+/// container can hold are copied, the rest re-encoded as close to the source
+/// as the container allows.
+pub fn convert(input: &Path, output: &Path) -> Result<PathBuf, String> {
+    if input == output {
+        return Err("Export to a different file than the recording".to_string());
+    }
+    let container = output.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    if let Some(format) = AnimatedFormat::from_container(container) {
+        return animated::export(input, output, format, &AnimatedOptions::default());
+    }
+    let info = probe::probe(input)?;
+    let video = target_video(&info, container);
+    let source_video = info.video_codec.as_deref().and_then(|name| VideoCodec::from_probe(name, info.pix_fmt.as_deref()));
+    let audio = target_audio(&info, container, video);
+    let source_audio = info.audio_codec.as_deref().and_then(AudioCodec::from_probe);
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-i").arg(input)
+       .arg("-map").arg("0:v?")
+       .arg("-map").arg("0:a?");
+    if info.video_codec.is_some() {
+        if source_video == Some(video) {
+            cmd.arg("-c:v").arg("copy");
+        } else {
+            cmd.args(video_args(&info, video, container));
+        }
+    }
+    if info.has_audio() {
+        if source_audio.is_some() && audio.codec == source_audio {
+            cmd.arg("-c:a").arg("copy");
+        } else {
+            cmd.args(audio.encoder_args(container, video));
+        }
+    }
+    faststart(&mut cmd, output);
+    cmd.arg("-n").arg(output);
+    run(&mut cmd)?;
+    Ok(output.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_are_taken_out_of_the_range() {
+        let plan = EditPlan { in_point: 1.0, out_point: 10.0, cuts: vec![(6.0, 4.0), (0.0, 2.0), (8.0, 12.0)] };
+        assert_eq!(plan.kept_ranges(), vec![(2.0, 4.0), (6.0, 8.0)]);
+        assert_eq!(plan.output_duration(), 4.0);
+        assert_eq!(plan.select_expr(), "between(t,2.000,4.000)+between(t,6.000,8.000)");
+    }
+
+    #[test]
+    fn validates_points() {
+        assert!(EditPlan::new(5.0).validate(5.0).is_ok());
+        assert!(EditPlan { in_point: 3.0, out_point: 2.0, cuts: vec![] }.validate(5.0).is_err());
+        assert!(EditPlan { in_point: 0.0, out_point: 9.0, cuts: vec![] }.validate(5.0).is_err());
+        assert!(EditPlan { in_point: 0.0, out_point: 5.0, cuts: vec![(0.0, 5.0)] }.validate(5.0).is_err());
+    }
+
+    #[test]
+    fn re_encodes_in_the_source_codecs() {
+        let ffv1 = MediaInfo {
+            video_codec: Some("ffv1".to_string()),
+            pix_fmt: Some("bgr0".to_string()),
+            audio_codec: Some("flac".to_string()),
+            sample_rate: Some(44100),
+            channels: Some(1),
+            ..MediaInfo::default()
+        };
+        let args = matching_args(&ffv1, "mkv").unwrap().join(" ");
+        assert!(args.starts_with("-c:v ffv1 "));
+        assert!(args.ends_with("-c:a flac -ar 44100 -ac 1"));
+        // MP4 can't hold FFV1, but stays lossless
+        assert_eq!(target_video(&ffv1, "mp4"), VideoCodec::H264Lossless);
+        assert_eq!(target_video(&ffv1, "webm"), VideoCodec::Vp9);
+
+        let h264 = MediaInfo { video_codec: Some("h264".to_string()), video_kbps: Some(6000), ..MediaInfo::default() };
+        assert!(video_args(&h264, VideoCodec::H264, "mp4").join(" ").contains("-maxrate 6000k"));
+        let mpeg4 = MediaInfo { video_codec: Some("mpeg4".to_string()), ..MediaInfo::default() };
+        assert_eq!(matching_args(&mpeg4, "mp4"), None);
+    }
+
+    #[test]
+    fn copies_only_when_ranges_start_on_keyframes() {
+        let keyframes = [0.0, 2.0, 4.0, 6.0];
+        let on = EditPlan { in_point: 2.0, out_point: 5.5, cuts: vec![(3.0, 4.0)] };
+        assert_eq!(on.pieces(&keyframes), vec![Piece::Copy(2.0, 3.0), Piece::Copy(4.0, 5.5)]);
+        // Only up to the next keyframe is re-encoded
+        let off = EditPlan { in_point: 2.0, out_point: 5.5, cuts: vec![(3.0, 4.5)] };
+        assert_eq!(off.pieces(&keyframes), vec![Piece::Copy(2.0, 3.0), Piece::ReEncode(4.5, 5.5)]);
+        let long = EditPlan { in_point: 0.5, out_point: 5.0, cuts: vec![] };
+        assert_eq!(long.pieces(&keyframes), vec![Piece::ReEncode(0.5, 2.0), Piece::Copy(2.0, 5.0)]);
+    }
+
+    fn testsrc_clip(dir: &Path) -> PathBuf {
+        let clip = dir.join("clip.mp4");
+        let status = Command::new("ffmpeg")
+            .args(["-v", "error",
+                   "-f", "lavfi", "-i", "testsrc=size=320x240:rate=30",
+                   "-f", "lavfi", "-i", "sine=frequency=440:sample_rate=48000",
+                   "-t", "6", "-c:v", "libx264", "-g", "30", "-c:a", "aac", "-y"])
+            .arg(&clip)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        clip
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn exports_testsrc_clips_both_ways() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-edit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let clip = testsrc_clip(&dir);
+
+        // Keyframes every second, so whole seconds can be copied
+        let copy = EditPlan { in_point: 1.0, out_point: 5.0, cuts: vec![(2.0, 3.0)] };
+        let (out, method) = export(&clip, &dir.join("copy.mp4"), &copy).unwrap();
+        assert_eq!(method, ExportMethod::StreamCopy);
+        assert!((probe::probe(&out).unwrap().duration - 3.0).abs() < 0.2);
+
+        let smart = EditPlan { in_point: 0.5, out_point: 4.25, cuts: vec![] };
+        let (out, method) = export(&clip, &dir.join("smart.mp4"), &smart).unwrap();
+        assert_eq!(method, ExportMethod::SmartCut);
+        let info = probe::probe(&out).unwrap();
+        assert!((info.duration - 3.75).abs() < 0.2);
+        assert!(info.has_audio());
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use chrono::{DateTime, Local};
+use crate::segment::{self, SEGMENT_PLACEHOLDER};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_TEMPLATE: &str = "{date}_{time}_{mode}.{ext}";
+
+pub const PLACEHOLDERS_HELP: &str = "{date} {time} {mode} {monitor} {ext} {segment}";
+
+// Extensions we produce ourselves. A template ending in one of these gets it
+// swapped for the selected format instead of ending up as `clip.mp4.webm`.
+const CONTAINER_EXTENSIONS: &[&str] = &["mp4", "webm", "mkv", "mov", "gif", "webp", "ogg", "flac", "wav", "m4a"];
+
+/// Values the placeholders of a filename template are filled with.
+pub struct TemplateContext<'a> {
+    pub now: DateTime<Local>,
+    pub mode: &'a str,
+    pub monitor: &'a str,
+    pub ext: &'a str,
+    pub segmented: bool, // Keep `{segment}` for the segment muxer, drop it otherwise
+}
+
+/// Expands `{date}`, `{time}`, `{mode}`, `{monitor}` and `{ext}` and makes sure
+/// the result ends in `.{ext}`. Unknown placeholders are kept as they are.
+pub fn render_template(template: &str, ctx: &TemplateContext) -> String {
+    let segment = if ctx.segmented { SEGMENT_PLACEHOLDER } else { "" };
+    let name = template
+        .replace(SEGMENT_PLACEHOLDER, segment)
+        .replace("{date}", &ctx.now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &ctx.now.format("%H-%M-%S").to_string())
+        .replace("{mode}", &sanitize(ctx.mode))
+        .replace("{monitor}", &sanitize(ctx.monitor))
+        .replace("{ext}", ctx.ext);
+    let name = sanitize(&name);
+
+    let stem = match name.rsplit_once('.') {
+        Some((stem, ext)) if CONTAINER_EXTENSIONS.contains(&ext.to_lowercase().as_str()) => stem,
+        _ => name.as_str(),
+    };
+    // Separators left dangling by empty placeholders
+    let stem = stem.trim_matches(|c| c == '-' || c == '_');
+    let stem = if stem.is_empty() { "recording" } else { stem };
+    format!("{}.{}", stem, ctx.ext)
+}
+
+/// Returns `dir/file_name`, or `stem_1.ext`, `stem_2.ext`, ... if it is taken.
+pub fn unique_path(dir: &Path, file_name: &str) -> PathBuf {
+    unique_path_by(dir, file_name, |path| path.exists())
+}
+
+fn unique_path_by(dir: &Path, file_name: &str, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let path = dir.join(file_name);
+    if !taken(&path) {
+        return path;
+    }
+
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) => (stem, format!(".{}", ext)),
+        None => (file_name, String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{}_{}{}", stem, n, ext)))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+/// Renders the template and picks the final output path. Existing files are
+/// only reused when `overwrite` is set.
+pub fn resolve_output_path(dir: &Path, template: &str, ctx: &TemplateContext, overwrite: bool) -> PathBuf {
+    let file_name = render_template(template, ctx);
+    if overwrite {
+        dir.join(file_name)
+    } else if ctx.segmented {
+        // The name itself is never written, only the index and the segments
+        unique_path_by(dir, &file_name, |path| {
+            segment::segment_list_path(path).exists()
+                || segment::segment_path(&segment::segment_pattern(path), 0).exists()
+        })
+    } else {
+        unique_path(dir, &file_name)
+    }
+}
+
+// Replaces characters that are not allowed (or annoying) in file names
+fn sanitize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | ' ' => '-',
+            '(' | ')' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .replace("-_", "_")
+        .trim_matches(|c| c == '-' || c == '_')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn ctx(ext: &'static str) -> TemplateContext<'static> {
+        TemplateContext {
+            now: Local.with_ymd_and_hms(2026, 3, 14, 9, 26, 53).unwrap(),
+            mode: "screen",
+            monitor: "Monitor 1 (Primary)",
+            ext,
+            segmented: false,
+        }
+    }
+
+    #[test]
+    fn expands_all_placeholders() {
+        assert_eq!(
+            render_template("{date}_{time}_{mode}_{monitor}.{ext}", &ctx("mp4")),
+            "2026-03-14_09-26-53_screen_Monitor-1_Primary.mp4"
+        );
+    }
+
+    #[test]
+    fn extension_follows_the_container_format() {
+        assert_eq!(render_template("recording.mp4", &ctx("webm")), "recording.webm");
+        assert_eq!(render_template("demo", &ctx("webm")), "demo.webm");
+        assert_eq!(render_template("v1.2 release", &ctx("mp4")), "v1.2-release.mp4");
+    }
+
+    #[test]
+    fn segment_placeholder_only_survives_when_segmenting() {
+        assert_eq!(render_template("talk_{segment}", &ctx("mp4")), "talk.mp4");
+        let segmented = TemplateContext { segmented: true, ..ctx("mp4") };
+        assert_eq!(render_template("talk_{segment}", &segmented), "talk_{segment}.mp4");
+    }
+
+    #[test]
+    fn numbers_colliding_names() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-naming-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("clip.mp4"), b"").unwrap();
+        std::fs::write(dir.join("clip_1.mp4"), b"").unwrap();
+
+        assert_eq!(resolve_output_path(&dir, "clip", &ctx("mp4"), false), dir.join("clip_2.mp4"));
+        assert_eq!(resolve_output_path(&dir, "clip", &ctx("mp4"), true), dir.join("clip.mp4"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,436 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// What a global hotkey does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Start,
+    Stop,
+    TogglePause,
+    Marker,
+}
+
+pub const ACTIONS: &[Action] = &[Action::Start, Action::Stop, Action::TogglePause, Action::Marker];
+
+impl Action {
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Start => "Start",
+            Action::Stop => "Stop",
+            Action::TogglePause => "Pause / Resume",
+            Action::Marker => "Drop marker",
+        }
+    }
+}
+
+/// Bindings that didn't work out, with the reason.
+pub type Problems = Vec<(Action, String)>;
+
+/// A key plus modifiers, written like "Ctrl+Alt+R". Only letters, digits and
+/// F1-F12 are allowed, those exist under the same name on every layout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub super_key: bool,
+    pub key: String, // "R", "5" or "F9"
+}
+
+impl Hotkey {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut hotkey = Hotkey { ctrl: false, alt: false, shift: false, super_key: false, key: String::new() };
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => hotkey.ctrl = true,
+                "alt" => hotkey.alt = true,
+                "shift" => hotkey.shift = true,
+                "super" | "win" | "meta" => hotkey.super_key = true,
+                _ if !hotkey.key.is_empty() => return Err(format!("\"{}\" has more than one key", text)),
+                key => hotkey.key = key.to_ascii_uppercase(),
+            }
+        }
+        let valid_key = match hotkey.key.as_bytes() {
+            [c] => c.is_ascii_alphanumeric(),
+            [b'F', n @ ..] => std::str::from_utf8(n).ok().and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| (1..=12).contains(&n)),
+            _ => false,
+        };
+        if !valid_key {
+            return Err(format!("\"{}\" needs a letter, digit or F1-F12 key", text));
+        }
+        // A bare letter would swallow typing everywhere else
+        if !hotkey.ctrl && !hotkey.alt && !hotkey.super_key && !hotkey.is_function_key() {
+            return Err(format!("\"{}\" needs Ctrl, Alt or Super", text));
+        }
+        Ok(hotkey)
+    }
+
+    fn is_function_key(&self) -> bool {
+        self.key.len() > 1
+    }
+
+    /// The X11 keysym name, e.g. "r" or "F9".
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn keysym_name(&self) -> String {
+        if self.is_function_key() { self.key.clone() } else { self.key.to_ascii_lowercase() }
+    }
+
+    /// The Windows virtual-key code.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    fn virtual_key(&self) -> u32 {
+        match self.key.strip_prefix('F') {
+            Some(n) if self.is_function_key() => 0x6F + n.parse::<u32>().unwrap_or(1), // VK_F1 is 0x70
+            _ => self.key.as_bytes()[0] as u32, // Letters and digits are their ASCII code
+        }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl+"), (self.alt, "Alt+"), (self.shift, "Shift+"), (self.super_key, "Super+")] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+/// The bindings as saved in the settings. An empty string leaves the action
+/// unbound.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeySettings {
+    pub enabled: bool,
+    pub start: String,
+    pub stop: String,
+    pub pause: String,
+    pub marker: String,
+}
+
+impl Default for HotkeySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: "Ctrl+Alt+R".to_string(),
+            stop: "Ctrl+Alt+S".to_string(),
+            pause: "Ctrl+Alt+P".to_string(),
+            marker: "Ctrl+Alt+M".to_string(),
+        }
+    }
+}
+
+impl HotkeySettings {
+    pub fn binding_mut(&mut self, action: Action) -> &mut String {
+        match action {
+            Action::Start => &mut self.start,
+            Action::Stop => &mut self.stop,
+            Action::TogglePause => &mut self.pause,
+            Action::Marker => &mut self.marker,
+        }
+    }
+
+    fn binding(&self, action: Action) -> &str {
+        match action {
+            Action::Start => &self.start,
+            Action::Stop => &self.stop,
+            Action::TogglePause => &self.pause,
+            Action::Marker => &self.marker,
+        }
+    }
+
+    /// Splits the bindings into the ones that can be registered and the
+    /// problems with the rest: typos and keys bound to two actions.
+    pub fn bindings(&self) -> (Vec<(Action, Hotkey)>, Problems) {
+        let mut bindings: Vec<(Action, Hotkey)> = Vec::new();
+        let mut problems = Vec::new();
+        for &action in ACTIONS {
+            let text = self.binding(action).trim();
+            if text.is_empty() {
+                continue;
+            }
+            match Hotkey::parse(text) {
+                Ok(hotkey) => match bindings.iter().find(|(_, other)| *other == hotkey) {
+                    Some((other, _)) => problems.push((action, format!("{} is already used for {}", hotkey, other.label()))),
+                    None => bindings.push((action, hotkey)),
+                },
+                Err(e) => problems.push((action, e)),
+            }
+        }
+        (bindings, problems)
+    }
+}
+
+// How often the listener thread looks for key presses and for being dropped
+const POLL: Duration = Duration::from_millis(50);
+
+/// Registered global hotkeys, held by a background thread until dropped.
+pub struct Listener {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Listener {
+    /// Registers the hotkeys and calls `on_action` from the listener thread
+    /// whenever one is pressed. Also returns the hotkeys that could not be
+    /// registered, usually because another program holds them.
+    pub fn start(
+        bindings: Vec<(Action, Hotkey)>,
+        on_action: impl Fn(Action) + Send + 'static,
+    ) -> Result<(Self, Problems), String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || platform::listen(bindings, on_action, stop, ready_tx))
+        };
+        match ready_rx.recv() {
+            Ok(Ok(failed)) => Ok((Self { stop, thread: Some(thread) }, failed)),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => Err("The hotkey listener stopped unexpectedly".to_string()),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// What a platform listener reports once it has tried every hotkey
+type Ready = mpsc::Sender<Result<Problems, String>>;
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{Action, Hotkey, POLL, Ready};
+    use std::ffi::CString;
+    use std::os::raw::{c_int, c_uint};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+    use x11_dl::xlib;
+
+    // Set by the error handler when a grab is refused
+    static GRAB_REFUSED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" fn on_error(_: *mut xlib::Display, event: *mut xlib::XErrorEvent) -> c_int {
+        if unsafe { (*event).error_code } == xlib::BadAccess {
+            GRAB_REFUSED.store(true, Ordering::SeqCst);
+        }
+        0
+    }
+
+    // Caps Lock and Num Lock are part of the modifier state, so every
+    // combination of them is grabbed too
+    const LOCKS: [c_uint; 4] = [0, xlib::LockMask, xlib::Mod2Mask, xlib::LockMask | xlib::Mod2Mask];
+
+    // X11 repeats KeyPress while a key is held
+    const REPEAT_GUARD: Duration = Duration::from_millis(400);
+
+    fn modifiers(hotkey: &Hotkey) -> c_uint {
+        let mut mask = 0;
+        for (held, bit) in [
+            (hotkey.ctrl, xlib::ControlMask),
+            (hotkey.alt, xlib::Mod1Mask),
+            (hotkey.shift, xlib::ShiftMask),
+            (hotkey.super_key, xlib::Mod4Mask),
+        ] {
+            if held {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    pub fn listen(bindings: Vec<(Action, Hotkey)>, on_action: impl Fn(Action), stop: Arc<AtomicBool>, ready: Ready) {
+        let xlib = match xlib::Xlib::open() {
+            Ok(xlib) => xlib,
+            Err(e) => {
+                let _ = ready.send(Err(format!("Failed to load Xlib: {}", e)));
+                return;
+            }
+        };
+        // A connection of our own, only ever used from this thread
+        let display = unsafe { (xlib.XOpenDisplay)(std::ptr::null()) };
+        if display.is_null() {
+            let _ = ready.send(Err("Global hotkeys need an X11 display (or XWayland)".to_string()));
+            return;
+        }
+
+        let mut grabbed = Vec::new();
+        let mut failed = Vec::new();
+        unsafe {
+            let root = (xlib.XDefaultRootWindow)(display);
+            let previous = (xlib.XSetErrorHandler)(Some(on_error));
+            for (action, hotkey) in bindings {
+                let name = CString::new(hotkey.keysym_name()).unwrap_or_default();
+                let keycode = (xlib.XKeysymToKeycode)(display, (xlib.XStringToKeysym)(name.as_ptr())) as c_uint;
+                if keycode == 0 {
+                    failed.push((action, format!("{} is not on this keyboard", hotkey)));
+                    continue;
+                }
+                let mask = modifiers(&hotkey);
+                GRAB_REFUSED.store(false, Ordering::SeqCst);
+                for lock in LOCKS {
+                    (xlib.XGrabKey)(display, keycode as c_int, mask | lock, root, xlib::False, xlib::GrabModeAsync, xlib::GrabModeAsync);
+                }
+                (xlib.XSync)(display, xlib::False);
+                if GRAB_REFUSED.load(Ordering::SeqCst) {
+                    for lock in LOCKS {
+                        (xlib.XUngrabKey)(display, keycode as c_int, mask | lock, root);
+                    }
+                    failed.push((action, format!("{} is taken by another program", hotkey)));
+                } else {
+                    grabbed.push((action, keycode, mask));
+                }
+            }
+            (xlib.XSync)(display, xlib::False);
+            (xlib.XSetErrorHandler)(previous);
+        }
+        let _ = ready.send(Ok(failed));
+
+        let mut last: Option<(Action, Instant)> = None;
+        let mut event: xlib::XEvent = unsafe { std::mem::zeroed() };
+        while !stop.load(Ordering::SeqCst) {
+            while unsafe { (xlib.XPending)(display) } > 0 {
+                unsafe { (xlib.XNextEvent)(display, &mut event) };
+                if event.get_type() != xlib::KeyPress {
+                    continue;
+                }
+                let key = unsafe { event.key };
+                let state = key.state & !(xlib::LockMask | xlib::Mod2Mask);
+                let Some(&(action, _, _)) = grabbed.iter().find(|(_, code, mask)| *code == key.keycode && *mask == state) else {
+                    continue;
+                };
+                if last.is_some_and(|(a, at)| a == action && at.elapsed() < REPEAT_GUARD) {
+                    continue;
+                }
+                last = Some((action, Instant::now()));
+                on_action(action);
+            }
+            std::thread::sleep(POLL);
+        }
+
+        unsafe {
+            let root = (xlib.XDefaultRootWindow)(display);
+            for (_, keycode, mask) in grabbed {
+                for lock in LOCKS {
+                    (xlib.XUngrabKey)(display, keycode as c_int, mask | lock, root);
+                }
+            }
+            (xlib.XCloseDisplay)(display);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{Action, Hotkey, POLL, Ready};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, RegisterHotKey, UnregisterHotKey,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{MSG, PM_REMOVE, PeekMessageW, WM_HOTKEY};
+
+    fn modifiers(hotkey: &Hotkey) -> u32 {
+        let mut mask = MOD_NOREPEAT;
+        for (held, bit) in [(hotkey.ctrl, MOD_CONTROL), (hotkey.alt, MOD_ALT), (hotkey.shift, MOD_SHIFT), (hotkey.super_key, MOD_WIN)] {
+            if held {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    pub fn listen(bindings: Vec<(Action, Hotkey)>, on_action: impl Fn(Action), stop: Arc<AtomicBool>, ready: Ready) {
+        // Without a window the hotkeys belong to this thread and WM_HOTKEY
+        // lands in its message queue
+        let mut registered = Vec::new();
+        let mut failed = Vec::new();
+        for (id, (action, hotkey)) in (1..).zip(bindings) {
+            if unsafe { RegisterHotKey(std::ptr::null_mut(), id, modifiers(&hotkey), hotkey.virtual_key()) } != 0 {
+                registered.push((id, action));
+            } else {
+                failed.push((action, format!("{} is taken by another program", hotkey)));
+            }
+        }
+        let _ = ready.send(Ok(failed));
+
+        let mut msg: MSG = unsafe { std::mem::zeroed() };
+        while !stop.load(Ordering::SeqCst) {
+            while unsafe { PeekMessageW(&mut msg, std::ptr::null_mut(), WM_HOTKEY, WM_HOTKEY, PM_REMOVE) } != 0 {
+                if let Some(&(_, action)) = registered.iter().find(|(id, _)| *id as usize == msg.wParam) {
+                    on_action(action);
+                }
+            }
+            std::thread::sleep(POLL);
+        }
+        for (id, _) in registered {
+            unsafe { UnregisterHotKey(std::ptr::null_mut(), id) };
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use super::{Action, Hotkey, Ready};
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    pub fn listen(_: Vec<(Action, Hotkey)>, _: impl Fn(Action), _: Arc<AtomicBool>, ready: Ready) {
+        let _ = ready.send(Err("Global hotkeys are not supported on this platform".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_hotkeys() {
+        let hotkey = Hotkey::parse("alt + ctrl+r").unwrap();
+        assert!(hotkey.ctrl && hotkey.alt && !hotkey.shift);
+        assert_eq!(hotkey.to_string(), "Ctrl+Alt+R");
+        assert_eq!(hotkey.keysym_name(), "r");
+        assert_eq!(hotkey.virtual_key(), 0x52);
+
+        let f9 = Hotkey::parse("Shift+F9").unwrap();
+        assert_eq!(f9.keysym_name(), "F9");
+        assert_eq!(f9.virtual_key(), 0x78);
+
+        assert!(Hotkey::parse("R").is_err()); // Would eat every R typed
+        assert!(Hotkey::parse("F").is_err());
+        assert!(Hotkey::parse("Shift+F").is_err());
+        assert!(Hotkey::parse("Ctrl+F13").is_err());
+        assert!(Hotkey::parse("Ctrl+A+B").is_err());
+        assert!(Hotkey::parse("Ctrl+Space").is_err());
+    }
+
+    #[test]
+    fn reports_typos_and_duplicates() {
+        let settings = HotkeySettings {
+            stop: "ctrl+alt+r".to_string(),
+            pause: "Ctrl+Alt+".to_string(),
+            marker: String::new(),
+            ..HotkeySettings::default()
+        };
+        let (bindings, problems) = settings.bindings();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].0, Action::Start);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0], (Action::Stop, "Ctrl+Alt+R is already used for Start".to_string()));
+        assert_eq!(problems[1].0, Action::TogglePause);
+    }
+}
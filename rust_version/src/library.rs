@@ -0,0 +1,283 @@
+use crate::probe;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Thumbnails are raw RGBA frames of this size, letterboxed, so the GUI can
+/// turn them into textures without an image decoder.
+pub const THUMB_WIDTH: usize = 160;
+pub const THUMB_HEIGHT: usize = 90;
+
+/// A finished recording.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub created: i64, // Unix seconds
+    pub duration: f64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub profile: Option<String>,
+    pub thumbnail: Option<PathBuf>,
+}
+
+impl Entry {
+    /// One line of details for the history list.
+    pub fn summary(&self) -> String {
+        let secs = self.duration.round() as u64;
+        let mut parts = vec![
+            format!("{:02}:{:02}", secs / 60, secs % 60),
+            format!("{:.1} MB", self.size as f64 / 1_000_000.0),
+        ];
+        if self.width > 0 {
+            parts.push(format!("{}x{}", self.width, self.height));
+        }
+        let codecs: Vec<&str> = [&self.video_codec, &self.audio_codec].into_iter().flatten().map(String::as_str).collect();
+        if !codecs.is_empty() {
+            parts.push(codecs.join("/"));
+        }
+        if let Some(profile) = &self.profile {
+            parts.push(profile.clone());
+        }
+        if let Some(created) = chrono::DateTime::from_timestamp(self.created, 0) {
+            parts.push(created.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+        }
+        parts.join(" · ")
+    }
+}
+
+/// Past recordings, newest first, kept in `history.toml` next to the settings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Library {
+    pub entries: Vec<Entry>,
+}
+
+impl Library {
+    pub fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "screen-recorder")
+            .map(|dirs| dirs.data_dir().join("history.toml"))
+    }
+
+    pub fn thumbnail_dir() -> PathBuf {
+        directories::ProjectDirs::from("", "", "screen-recorder")
+            .map(|dirs| dirs.cache_dir().join("thumbnails"))
+            .unwrap_or_else(|| std::env::temp_dir().join("screen-recorder-thumbnails"))
+    }
+
+    /// Loads the index and forgets recordings that were deleted or moved.
+    pub fn load() -> Result<Self, String> {
+        let mut library = match Self::path() {
+            Some(path) => Self::load_from(&path)?,
+            None => Self::default(),
+        };
+        library.prune();
+        Ok(library)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("Invalid history: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {:?}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("No data directory available")?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        let text = toml::to_string_pretty(self).map_err(|e| format!("Failed to serialize history: {}", e))?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// Probes `path` and puts it at the top. A recording that is already
+    /// listed (e.g. overwritten) gets its entry refreshed.
+    pub fn add(&mut self, path: &Path, profile: Option<String>, thumbnail_dir: &Path) -> Result<(), String> {
+        let info = probe::probe(path)?;
+        let metadata = std::fs::metadata(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let thumbnail = info.video_codec.as_ref().and_then(|_| {
+            let thumb = thumbnail_dir.join(format!("{:016x}.rgba", path_hash(path)));
+            make_thumbnail(path, &thumb, info.duration).ok().map(|_| thumb)
+        });
+
+        self.entries.retain(|e| e.path != path);
+        self.entries.insert(0, Entry {
+            path: path.to_path_buf(),
+            created: chrono::Local::now().timestamp(),
+            duration: info.duration,
+            size: metadata.len(),
+            width: info.width,
+            height: info.height,
+            video_codec: info.video_codec,
+            audio_codec: info.audio_codec,
+            profile,
+            thumbnail,
+        });
+        Ok(())
+    }
+
+    /// Drops entries whose file is gone. Returns whether anything changed.
+    pub fn prune(&mut self) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path.exists());
+        self.entries.len() != before
+    }
+
+    /// Renames the recording, keeping its extension and folder.
+    pub fn rename(&mut self, index: usize, new_stem: &str) -> Result<PathBuf, String> {
+        let entry = self.entries.get_mut(index).ok_or("No such recording")?;
+        let new_stem = new_stem.trim();
+        if new_stem.is_empty() || new_stem.contains(['/', '\\']) {
+            return Err(format!("\"{}\" is not a valid file name", new_stem));
+        }
+        let new_path = match entry.path.extension() {
+            Some(ext) => entry.path.with_file_name(format!("{}.{}", new_stem, ext.to_string_lossy())),
+            None => entry.path.with_file_name(new_stem),
+        };
+        if new_path.exists() {
+            return Err(format!("{:?} already exists", new_path));
+        }
+        std::fs::rename(&entry.path, &new_path).map_err(|e| format!("Failed to rename {:?}: {}", entry.path, e))?;
+        entry.path = new_path.clone();
+        Ok(new_path)
+    }
+
+    /// Deletes the recording and its thumbnail.
+    pub fn delete(&mut self, index: usize) -> Result<(), String> {
+        let entry = self.entries.get(index).ok_or("No such recording")?;
+        match std::fs::remove_file(&entry.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to delete {:?}: {}", entry.path, e)),
+        }
+        if let Some(thumb) = &entry.thumbnail {
+            let _ = std::fs::remove_file(thumb);
+        }
+        self.entries.remove(index);
+        Ok(())
+    }
+}
+
+fn path_hash(path: &Path) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A frame from a second in (or the middle of short clips) as raw RGBA.
+pub fn make_thumbnail(video: &Path, thumbnail: &Path, duration: f64) -> Result<(), String> {
+    if let Some(parent) = thumbnail.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let out = Command::new("ffmpeg")
+        .arg("-v").arg("error")
+        .arg("-ss").arg(format!("{:.2}", (duration / 2.0).min(1.0)))
+        .arg("-i").arg(video)
+        .arg("-frames:v").arg("1")
+        .arg("-vf").arg(format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+            w = THUMB_WIDTH,
+            h = THUMB_HEIGHT
+        ))
+        .arg("-f").arg("rawvideo")
+        .arg("-pix_fmt").arg("rgba")
+        .arg("-y").arg(thumbnail)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Thumbnail failed: {}", String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+/// The pixels of a thumbnail, if it is there and complete.
+pub fn load_thumbnail(thumbnail: &Path) -> Option<Vec<u8>> {
+    std::fs::read(thumbnail).ok().filter(|bytes| bytes.len() == THUMB_WIDTH * THUMB_HEIGHT * 4)
+}
+
+/// Shows the file in the file manager, selected where the platform allows it.
+pub fn reveal(path: &Path) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        Command::new("explorer")
+            .arg(format!("/select,{}", path.display()))
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to open Explorer: {}", e))
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let dir = path.parent().ok_or("Recording has no folder")?;
+        open::that(dir).map_err(|e| format!("Failed to open {:?}: {}", dir, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: PathBuf) -> Entry {
+        Entry {
+            path,
+            created: 1_773_480_413,
+            duration: 83.4,
+            size: 12_345_678,
+            width: 1920,
+            height: 1080,
+            video_codec: Some("h264".to_string()),
+            audio_codec: Some("aac".to_string()),
+            profile: Some("Bug report".to_string()),
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn index_round_trips_and_prunes_missing_files() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("kept.mp4"), b"").unwrap();
+
+        let library = Library { entries: vec![entry(dir.join("kept.mp4")), entry(dir.join("gone.mp4"))] };
+        library.save_to(&dir.join("history.toml")).unwrap();
+        let mut loaded = Library::load_from(&dir.join("history.toml")).unwrap();
+        assert_eq!(loaded, library);
+
+        assert!(loaded.prune());
+        assert_eq!(loaded.entries.len(), 1);
+        assert!(loaded.entries[0].summary().starts_with("01:23 · 12.3 MB · 1920x1080 · h264/aac · Bug report · "));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renames_and_deletes_files() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-library-ops-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.mp4"), b"").unwrap();
+        std::fs::write(dir.join("b.mp4"), b"").unwrap();
+        let mut library = Library { entries: vec![entry(dir.join("a.mp4"))] };
+
+        assert!(library.rename(0, "b").is_err()); // Taken
+        assert!(library.rename(0, "../x").is_err());
+        assert_eq!(library.rename(0, "demo").unwrap(), dir.join("demo.mp4"));
+        assert!(dir.join("demo.mp4").exists());
+
+        library.delete(0).unwrap();
+        assert!(!dir.join("demo.mp4").exists());
+        assert!(library.entries.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
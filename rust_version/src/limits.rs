@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// When to stop a recording on its own, so a forgotten one can't fill the
+/// disk. `None` turns a condition off.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StopLimits {
+    pub max_minutes: Option<u32>,
+    pub max_megabytes: Option<u64>, // All segments together when splitting
+    pub min_free_megabytes: Option<u64>, // On the disk being written to
+    pub idle_minutes: Option<u32>, // Nothing changed on screen for this long
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    MaxDuration(u32),
+    MaxSize(u64),
+    LowDiskSpace(u64),
+    Idle(u32),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::MaxDuration(minutes) => write!(f, "reached the {} min limit", minutes),
+            StopReason::MaxSize(mb) => write!(f, "reached the {} MB limit", mb),
+            StopReason::LowDiskSpace(mb) => write!(f, "less than {} MB free on disk", mb),
+            StopReason::Idle(minutes) => write!(f, "nothing changed on screen for {} min", minutes),
+        }
+    }
+}
+
+/// What the limits are checked against. Anything unknown is `None`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    pub elapsed: Duration, // Without paused time
+    pub written_bytes: Option<u64>,
+    pub free_bytes: Option<u64>,
+    pub idle: bool,
+}
+
+const MB: u64 = 1_000_000;
+
+impl StopLimits {
+    pub fn is_enabled(&self) -> bool {
+        *self != StopLimits::default()
+    }
+
+    /// The first limit that was hit, if any.
+    pub fn check(&self, progress: &Progress) -> Option<StopReason> {
+        if let Some(minutes) = self.max_minutes
+            && progress.elapsed >= Duration::from_secs(u64::from(minutes) * 60)
+        {
+            return Some(StopReason::MaxDuration(minutes));
+        }
+        if let (Some(mb), Some(written)) = (self.max_megabytes, progress.written_bytes)
+            && written >= mb * MB
+        {
+            return Some(StopReason::MaxSize(mb));
+        }
+        if let (Some(mb), Some(free)) = (self.min_free_megabytes, progress.free_bytes)
+            && free < mb * MB
+        {
+            return Some(StopReason::LowDiskSpace(mb));
+        }
+        match self.idle_minutes {
+            Some(minutes) if progress.idle => Some(StopReason::Idle(minutes)),
+            _ => None,
+        }
+    }
+}
+
+/// Space left for `dir` on its disk, if the system tells.
+#[cfg(unix)]
+pub fn free_bytes(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // f_bavail is what unprivileged users can still write
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(target_os = "windows")]
+pub fn free_bytes(dir: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let path: Vec<u16> = dir.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut available = 0u64;
+    let ok = unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+pub fn free_bytes(_dir: &Path) -> Option<u64> {
+    None
+}
+
+// Frames per second the idle watcher looks at, and the width it scales them
+// down to. Plenty to notice a changing screen, and cheap to run alongside.
+const IDLE_FPS: u32 = 2;
+const IDLE_WIDTH: u32 = 320;
+
+/// A second, low-rate capture of the same screen that runs ffmpeg's
+/// `freezedetect` and flags when nothing has changed for a while. The
+/// recording itself is left alone, so any codec or filter still works.
+pub struct IdleWatch {
+    input: Box<dyn Fn() -> Command + Send>,
+    minutes: u32,
+    running: Option<(Child, Arc<AtomicBool>)>, // None while paused
+}
+
+impl IdleWatch {
+    /// `input` makes an ffmpeg command with the screen as its only input,
+    /// logging at the default level where the filter reports.
+    pub fn start(input: impl Fn() -> Command + Send + 'static, minutes: u32) -> Result<Self, String> {
+        let mut watch = Self { input: Box::new(input), minutes, running: None };
+        watch.resume()?;
+        Ok(watch)
+    }
+
+    /// Stops watching, e.g. while the recording is paused.
+    pub fn pause(&mut self) {
+        if let Some((mut child, _)) = self.running.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    /// Watches again from scratch, so time spent paused doesn't count as idle.
+    pub fn resume(&mut self) -> Result<(), String> {
+        self.pause();
+        let mut input = (self.input)();
+        input.arg("-an")
+             .arg("-vf").arg(format!(
+                 "fps={},scale={}:-2,freezedetect=d={}",
+                 IDLE_FPS, IDLE_WIDTH, u64::from(self.minutes.max(1)) * 60
+             ))
+             .arg("-f").arg("null").arg("-")
+             .stdin(Stdio::null())
+             .stdout(Stdio::null())
+             .stderr(Stdio::piped());
+        let mut child = input.spawn().map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+
+        let idle = Arc::new(AtomicBool::new(false));
+        let stderr = child.stderr.take().ok_or("No stderr from ffmpeg")?;
+        let flag = idle.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+                if let Some(idle) = freeze_event(&String::from_utf8_lossy(&line)) {
+                    flag.store(idle, Ordering::Relaxed);
+                }
+            }
+        });
+        self.running = Some((child, idle));
+        Ok(())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.running.as_ref().is_some_and(|(_, idle)| idle.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for IdleWatch {
+    fn drop(&mut self) {
+        self.pause();
+    }
+}
+
+// true when freezedetect reports a freeze, false when it ends
+fn freeze_event(line: &str) -> Option<bool> {
+    if line.contains("lavfi.freezedetect.freeze_start") {
+        Some(true)
+    } else if line.contains("lavfi.freezedetect.freeze_end") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_first_limit_hit() {
+        let limits = StopLimits {
+            max_minutes: Some(60),
+            max_megabytes: Some(500),
+            min_free_megabytes: Some(1000),
+            idle_minutes: Some(10),
+        };
+        let fine = Progress {
+            elapsed: Duration::from_secs(59 * 60),
+            written_bytes: Some(499 * MB),
+            free_bytes: Some(1000 * MB),
+            idle: false,
+        };
+        assert_eq!(limits.check(&fine), None);
+        assert_eq!(limits.check(&Progress { elapsed: Duration::from_secs(3600), ..fine }), Some(StopReason::MaxDuration(60)));
+        assert_eq!(limits.check(&Progress { written_bytes: Some(500 * MB), ..fine }), Some(StopReason::MaxSize(500)));
+        assert_eq!(limits.check(&Progress { free_bytes: Some(999 * MB), ..fine }), Some(StopReason::LowDiskSpace(1000)));
+        assert_eq!(limits.check(&Progress { idle: true, ..fine }), Some(StopReason::Idle(10)));
+        // Unknown sizes never stop anything
+        assert_eq!(limits.check(&Progress { written_bytes: None, free_bytes: None, ..fine }), None);
+        assert_eq!(StopLimits::default().check(&Progress { idle: true, ..fine }), None);
+    }
+
+    #[test]
+    fn reads_freezedetect_output() {
+        assert_eq!(freeze_event("[freezedetect @ 0x55d1c0] lavfi.freezedetect.freeze_start: 600.5"), Some(true));
+        assert_eq!(freeze_event("[freezedetect @ 0x55d1c0] lavfi.freezedetect.freeze_end: 640"), Some(false));
+        assert_eq!(freeze_event("[freezedetect @ 0x55d1c0] lavfi.freezedetect.freeze_duration: 40"), None);
+        assert!(free_bytes(&std::env::temp_dir()).is_some());
+    }
+}
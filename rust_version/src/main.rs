@@ -1,11 +1,66 @@
-mod recorder;
+mod animated;
+mod chapters;
+mod cli;
+mod codec;
+mod control;
+mod countdown;
 mod device_list;
+mod edit;
+mod filename;
+mod hotkeys;
+mod library;
+mod limits;
+mod metadata;
+mod outputs;
+mod preview;
+mod probe;
+mod recorder;
+mod recovery;
+mod remote;
+mod replay;
+mod schedule;
+mod segment;
+mod settings;
+mod stats;
+mod stream;
+mod tray;
 
+use animated::{AnimatedFormat, AnimatedOptions, Dither};
+use codec::{AudioCodec, AudioSettings, Capabilities, VideoCodec};
+use control::Request as ControlRequest;
+use countdown::Countdown;
+use device_list::{Device, get_audio_devices, get_video_devices};
 use display_info::DisplayInfo;
+use edit::{EditPlan, ExportMethod};
 use eframe::egui;
+use hotkeys::Action as HotkeyAction;
+use library::Library;
+use limits::StopLimits;
+use metadata::Metadata;
+use outputs::{OutputSpec, OutputTarget};
+use preview::Preview;
 use recorder::{Recorder, RecordingConfig, RecordingMode};
-use device_list::{Device, get_video_devices, get_audio_devices};
+use recovery::CrashSafety;
+use schedule::{Clock, Schedule, Scheduler, Start, Stop, SystemClock};
+use segment::Segmentation;
+use settings::{Profile, Region, Settings};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::mpsc;
+use stream::{StreamConfig, StreamTarget};
+use tray::Action as TrayAction;
+
+// What the preview shows: mode, region and camera. It restarts when it changes.
+type PreviewSource = (RecordingMode, i32, i32, u32, u32, Option<String>);
+
+// How long the source has to stay the same before the preview restarts, so
+// dragging the region doesn't start one ffmpeg per frame
+const PREVIEW_SETTLE: std::time::Duration = std::time::Duration::from_millis(500);
+
+// A hidden window gets no redraws on Windows and macOS, which would stop the
+// tray, hotkeys, control calls, schedules and limits, as they are all handled
+// in update(). The window is minimized there instead.
+const HIDE_TO_TRAY: bool = cfg!(target_os = "linux");
 
 #[derive(Clone, Debug, PartialEq)]
 struct MonitorInfo {
@@ -56,15 +111,41 @@ struct ScreenRecorderApp {
     // Devices
     video_devices: Vec<Device>,
     audio_devices: Vec<Device>,
+    selected_video_device_index: usize,
+    selected_audio_device_index: usize,
 
     // Config state
     mode: RecordingMode,
     output_dir: PathBuf,
-    filename: String,
-    format: String, // "mp4", "webm"
+    filename: String, // Template, e.g. "{date}_{time}_{mode}.{ext}"
+    format: String, // See codec::CONTAINERS, plus "gif" and "webp"
+    video_codec: Option<VideoCodec>, // None uses the format's default
+    capabilities: Option<Capabilities>, // What the installed ffmpeg can encode
+    overwrite: bool,
+    crash_safety: CrashSafety,
+    remux_on_stop: bool,
+    segmentation: Segmentation,
+    replay_buffer: bool,
+    replay_seconds: u32,
+    stream_enabled: bool,
+    stream_target: String, // Without the RTMP stream key
+    stream_key: String, // Kept in the OS keyring
+    stream_key_problem: Option<String>,
+    stream_video_kbps: u32,
+    stream_keyframe_seconds: u32,
+    stream_local_copy: bool,
+    stream_local_kbps: Option<u32>,
+    animated: AnimatedOptions,
     audio_enabled: bool,
-    audio_devices: Vec<String>,
-    selected_audio_device: String,
+    audio: AudioSettings,
+    metadata: Metadata,
+    metadata_tags: String, // Comma-separated while being edited
+    limits: StopLimits,
+    countdown_seconds: u32, // 0 starts right away
+    minimize_on_record: bool,
+
+    // Partial recordings left over from a crash
+    orphans: Vec<PathBuf>,
 
     // Region state
     region_custom: bool,
@@ -73,28 +154,166 @@ struct ScreenRecorderApp {
     reg_w: u32,
     reg_h: u32,
 
+    // Settings / profiles
+    settings: Settings,
+    new_profile_name: String,
+
+    recording_profile: Option<String>, // What the running recording was started with
+    trim: Option<TrimDialog>,
+    marker_label: String, // Label for the next marker, empty numbers it
+    countdown: Option<Countdown>, // Running before the capture starts
+    minimized_for_recording: bool, // Restored once the recording stops
+
+    // History
+    library: Library,
+    thumbnails: HashMap<PathBuf, egui::TextureHandle>,
+    renaming: Option<(usize, String)>,
+    confirm_delete: Option<usize>,
+    reexport_format: String,
+
+    // Global hotkeys, pressed actions arrive through the channel
+    hotkey_listener: Option<hotkeys::Listener>,
+    hotkey_sender: mpsc::Sender<HotkeyAction>,
+    hotkey_actions: mpsc::Receiver<HotkeyAction>,
+    hotkey_problems: hotkeys::Problems,
+
+    // Live preview, running while idle if turned on in the settings
+    preview: Option<Preview>,
+    preview_source: Option<PreviewSource>, // What the preview was started for
+    preview_pending: Option<(PreviewSource, std::time::Instant)>, // Changed, waiting to settle
+    preview_texture: Option<egui::TextureHandle>,
+    preview_problem: Option<String>,
+
+    // Tray icon, chosen menu entries arrive through the channel
+    tray: Option<tray::Tray>,
+    tray_sender: mpsc::Sender<TrayAction>,
+    tray_actions: mpsc::Receiver<TrayAction>,
+    tray_problem: Option<String>,
+    hidden_to_tray: bool, // Shown again once the recording stops
+    was_recording: bool,
+
+    // Control socket for scripts, requests arrive through the channel
+    control: Option<control::Server>,
+    control_sender: mpsc::Sender<control::Call>,
+    control_calls: mpsc::Receiver<control::Call>,
+    control_problem: Option<String>,
+    remote: Option<remote::Server>, // HTTP server, off by default
+    remote_problem: Option<String>,
+
+    // Exports run on a worker thread, one at a time, and report back through the channel
+    job_sender: mpsc::Sender<JobDone>,
+    job_results: mpsc::Receiver<JobDone>,
+    job_running: bool,
+
+    // Schedules themselves are in the settings
+    scheduler: Scheduler,
+    schedule_form: ScheduleForm,
+
     status_message: String,
 }
 
+// A schedule being added in the GUI, as typed
+#[derive(Default)]
+struct ScheduleForm {
+    name: String,
+    start: String,
+    stop: String,
+    profile: Option<String>,
+}
+
+impl ScheduleForm {
+    fn parse(&self, now: chrono::NaiveDateTime) -> Result<Schedule, String> {
+        Ok(Schedule {
+            name: self.name.trim().to_string(),
+            start: Start::parse(&self.start, now)?,
+            stop: Stop::parse(&self.stop)?,
+            profile: self.profile.clone(),
+            enabled: true,
+        })
+    }
+}
+
+// A finished background job
+enum JobDone {
+    Trimmed(Result<(PathBuf, ExportMethod), String>),
+    ReExported(Result<PathBuf, String>),
+    ReplaySaved(Result<PathBuf, String>),
+}
+
+// The trim window for a finished recording
+struct TrimDialog {
+    input: PathBuf,
+    duration: f64,
+    plan: EditPlan,
+}
+
+fn seconds_field(value: &mut f64, max: f64) -> egui::DragValue<'_> {
+    egui::DragValue::new(value).range(0.0..=max).speed(0.1).suffix(" s")
+}
+
+// A checkbox that turns an optional limit on at `default`, and its value
+fn limit_row<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    limit: &mut Option<T>,
+    default: T,
+    range: std::ops::RangeInclusive<T>,
+    suffix: &str,
+) {
+    ui.horizontal(|ui| {
+        let mut on = limit.is_some();
+        if ui.checkbox(&mut on, label).changed() {
+            *limit = on.then_some(default);
+        }
+        if let Some(value) = limit {
+            ui.add(egui::DragValue::new(value).range(range).suffix(suffix));
+        }
+    });
+}
+
+fn default_output_dir() -> PathBuf {
+    if let Some(user_dirs) = directories::UserDirs::new() {
+        user_dirs.video_dir().unwrap_or(user_dirs.home_dir()).to_path_buf()
+    } else {
+        PathBuf::from(".")
+    }
+}
+
 impl ScreenRecorderApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let monitors = get_monitors();
-        let video_devices = get_video_devices();
-        let audio_devices = get_audio_devices();
-
-        // Default paths
-        let output_dir = if let Some(user_dirs) = directories::UserDirs::new() {
-            user_dirs.video_dir().unwrap_or(user_dirs.home_dir()).to_path_buf()
-        } else {
-            PathBuf::from(".")
-        };
+    fn new(cc: &eframe::CreationContext<'_>, settings: Settings, profile: Profile) -> Self {
+        let library = Library::load().unwrap_or_else(|e| {
+            eprintln!("{}, starting with an empty history", e);
+            Library::default()
+        });
+        let mut app = Self::with_devices(settings, get_monitors(), get_video_devices(), get_audio_devices(), library);
+        app.capabilities = Capabilities::probe().ok();
+        let ctx = cc.egui_ctx.clone();
+        match control::Server::start(&control::socket_path(), app.control_sender.clone(), move || ctx.request_repaint()) {
+            Ok(server) => app.control = Some(server),
+            Err(e) => app.control_problem = Some(e),
+        }
+        app.apply_profile(&profile);
+        app.orphans = recovery::find_orphans(&app.output_dir);
+        app.register_hotkeys(&cc.egui_ctx);
+        app.start_tray(&cc.egui_ctx);
+        app.start_remote(&cc.egui_ctx);
+        app
+    }
 
+    // The app state without a window, servers or probing
+    fn with_devices(
+        settings: Settings,
+        monitors: Vec<MonitorInfo>,
+        video_devices: Vec<Device>,
+        audio_devices: Vec<Device>,
+        library: Library,
+    ) -> Self {
         // Ensure we default to a safe monitor if something goes wrong
         let default_mon = monitors.first().unwrap();
-
-        // Initial audio scan
-        let audio_devices = recorder::get_audio_devices();
-        let selected_audio_device = audio_devices.first().cloned().unwrap_or_else(|| "default".to_string());
+        let (hotkey_sender, hotkey_actions) = mpsc::channel();
+        let (tray_sender, tray_actions) = mpsc::channel();
+        let (control_sender, control_calls) = mpsc::channel();
+        let (job_sender, job_results) = mpsc::channel();
 
         Self {
             recorder: Recorder::new(),
@@ -102,34 +321,1202 @@ impl ScreenRecorderApp {
             selected_monitor_index: 0,
             video_devices,
             audio_devices,
+            selected_video_device_index: 0,
+            selected_audio_device_index: 0,
             mode: RecordingMode::Screen,
-            output_dir,
-            filename: "recording.mp4".to_string(),
+            output_dir: default_output_dir(),
+            filename: filename::DEFAULT_TEMPLATE.to_string(),
             format: "mp4".to_string(),
+            video_codec: None,
+            capabilities: None,
+            overwrite: false,
+            crash_safety: CrashSafety::Off,
+            remux_on_stop: true,
+            segmentation: Segmentation::Off,
+            replay_buffer: false,
+            replay_seconds: replay::DEFAULT_REPLAY_SECONDS,
+            stream_enabled: false,
+            stream_target: String::new(),
+            stream_key: String::new(),
+            stream_key_problem: None,
+            stream_video_kbps: stream::DEFAULT_VIDEO_KBPS,
+            stream_keyframe_seconds: stream::DEFAULT_KEYFRAME_SECONDS,
+            stream_local_copy: false,
+            stream_local_kbps: None,
+            animated: AnimatedOptions::default(),
             audio_enabled: false,
-            audio_devices,
-            selected_audio_device,
+            audio: AudioSettings::default(),
+            metadata: Metadata::default(),
+            metadata_tags: String::new(),
+            limits: StopLimits::default(),
+            countdown_seconds: 0,
+            minimize_on_record: false,
+            orphans: Vec::new(),
             region_custom: false,
             reg_x: default_mon.x,
             reg_y: default_mon.y,
             reg_w: default_mon.width,
             reg_h: default_mon.height,
+            settings,
+            new_profile_name: String::new(),
+            recording_profile: None,
+            trim: None,
+            marker_label: String::new(),
+            countdown: None,
+            minimized_for_recording: false,
+            library,
+            thumbnails: HashMap::new(),
+            renaming: None,
+            confirm_delete: None,
+            reexport_format: "mp4".to_string(),
+            hotkey_listener: None,
+            hotkey_sender,
+            hotkey_actions,
+            hotkey_problems: Vec::new(),
+            preview: None,
+            preview_source: None,
+            preview_pending: None,
+            preview_texture: None,
+            preview_problem: None,
+            tray: None,
+            tray_sender,
+            tray_actions,
+            tray_problem: None,
+            hidden_to_tray: false,
+            was_recording: false,
+            control: None,
+            control_sender,
+            control_calls,
+            control_problem: None,
+            remote: None,
+            remote_problem: None,
+            job_sender,
+            job_results,
+            job_running: false,
+            scheduler: Scheduler::new(Box::new(SystemClock)),
+            schedule_form: ScheduleForm::default(),
             status_message: "Ready".to_string(),
         }
     }
 
-    fn refresh_audio_devices(&mut self) {
-        self.audio_devices = recorder::get_audio_devices();
-        if !self.audio_devices.contains(&self.selected_audio_device) {
-             if let Some(first) = self.audio_devices.first() {
-                 self.selected_audio_device = first.clone();
-             }
+    /// Loads a profile into the UI state. Devices and monitors that are gone
+    /// fall back to the first available one.
+    fn apply_profile(&mut self, profile: &Profile) {
+        self.mode = profile.mode.clone();
+
+        self.selected_monitor_index = profile.monitor.as_ref()
+            .and_then(|name| self.monitors.iter().position(|m| &m.name == name))
+            .unwrap_or(0);
+        let mon = &self.monitors[self.selected_monitor_index];
+        let region = profile.region.unwrap_or(Region { x: mon.x, y: mon.y, width: mon.width, height: mon.height });
+        self.region_custom = profile.region.is_some();
+        self.reg_x = region.x;
+        self.reg_y = region.y;
+        self.reg_w = region.width;
+        self.reg_h = region.height;
+
+        self.selected_video_device_index = profile.camera_device.as_ref()
+            .and_then(|id| self.video_devices.iter().position(|d| &d.id == id))
+            .unwrap_or(0);
+        self.audio_enabled = profile.audio_enabled;
+        self.selected_audio_device_index = profile.audio_device.as_ref()
+            .and_then(|id| self.audio_devices.iter().position(|d| &d.id == id))
+            .unwrap_or(0);
+
+        self.output_dir = profile.output_dir.clone().unwrap_or_else(default_output_dir);
+        self.filename = profile.filename.clone();
+        self.format = profile.format.clone();
+        self.overwrite = profile.overwrite;
+        self.crash_safety = profile.crash_safety;
+        self.remux_on_stop = profile.remux_on_stop;
+        self.segmentation = profile.segmentation;
+        self.replay_buffer = profile.replay_buffer;
+        self.replay_seconds = profile.replay_seconds;
+        self.stream_enabled = profile.stream_enabled;
+        let (server, key) = stream::split_key(&profile.stream_target);
+        self.stream_key = match key {
+            Some(key) => key,
+            None => stream::load_key(&server).unwrap_or_else(|e| {
+                self.stream_key_problem = Some(e);
+                None
+            }).unwrap_or_default(),
+        };
+        self.stream_target = server;
+        self.stream_video_kbps = profile.stream_video_kbps;
+        self.stream_keyframe_seconds = profile.stream_keyframe_seconds;
+        self.stream_local_copy = profile.stream_local_copy;
+        self.stream_local_kbps = profile.stream_local_kbps;
+        self.video_codec = profile.video_codec;
+        self.audio = profile.audio;
+        self.animated = profile.animated.clone();
+        self.metadata = profile.metadata.clone();
+        self.metadata_tags = profile.metadata.tags.join(", ");
+        self.limits = profile.limits;
+        self.countdown_seconds = profile.countdown_seconds;
+        self.minimize_on_record = profile.minimize_on_record;
+    }
+
+    fn current_profile(&self) -> Profile {
+        Profile {
+            mode: self.mode.clone(),
+            monitor: self.monitors.get(self.selected_monitor_index).map(|m| m.name.clone()),
+            region: self.region_custom.then_some(Region {
+                x: self.reg_x,
+                y: self.reg_y,
+                width: self.reg_w,
+                height: self.reg_h,
+            }),
+            camera_device: self.video_devices.get(self.selected_video_device_index).map(|d| d.id.clone()),
+            audio_enabled: self.audio_enabled,
+            audio_device: self.audio_devices.get(self.selected_audio_device_index).map(|d| d.id.clone()),
+            output_dir: Some(self.output_dir.clone()),
+            filename: self.filename.clone(),
+            format: self.format.clone(),
+            overwrite: self.overwrite,
+            crash_safety: self.crash_safety,
+            remux_on_stop: self.remux_on_stop,
+            segmentation: self.segmentation,
+            replay_buffer: self.replay_buffer,
+            replay_seconds: self.replay_seconds,
+            stream_enabled: self.stream_enabled,
+            stream_target: stream::split_key(&self.stream_target).0,
+            stream_video_kbps: self.stream_video_kbps,
+            stream_keyframe_seconds: self.stream_keyframe_seconds,
+            stream_local_copy: self.stream_local_copy,
+            stream_local_kbps: self.stream_local_kbps,
+            video_codec: self.video_codec,
+            audio: self.audio,
+            animated: self.animated.clone(),
+            metadata: Metadata { tags: Metadata::parse_tags(&self.metadata_tags), ..self.metadata.clone() },
+            limits: self.limits,
+            countdown_seconds: self.countdown_seconds,
+            minimize_on_record: self.minimize_on_record,
+        }
+    }
+
+    fn stream_config(&self) -> Result<Option<StreamConfig>, String> {
+        if !self.stream_enabled {
+            return Ok(None);
+        }
+        Ok(Some(StreamConfig {
+            target: StreamTarget::parse(&stream::with_key(&self.stream_target, &self.stream_key))?,
+            video_kbps: self.stream_video_kbps,
+            keyframe_seconds: self.stream_keyframe_seconds,
+        }))
+    }
+
+    /// Audio-only recordings get audio formats, everything else video ones.
+    fn fit_format_to_mode(&mut self) {
+        let audio_format = codec::AUDIO_CONTAINERS.iter().any(|(c, _)| *c == self.format);
+        if self.mode == RecordingMode::AudioOnly && !audio_format {
+            self.format = "ogg".to_string();
+        } else if self.mode != RecordingMode::AudioOnly && audio_format {
+            self.format = "mp4".to_string();
+        }
+    }
+
+    fn current_video_codec(&self) -> VideoCodec {
+        self.video_codec.unwrap_or_else(|| VideoCodec::default_for(&self.format))
+    }
+
+    /// The chosen codec fits the format and the installed ffmpeg has it.
+    fn validate_codec(&self) -> Result<(), String> {
+        if AnimatedFormat::from_container(&self.format).is_some() || self.mode == RecordingMode::AudioOnly {
+            return Ok(());
+        }
+        let codec = self.current_video_codec();
+        match &self.capabilities {
+            Some(caps) => caps.validate(codec, &self.format),
+            None => codec.check_container(&self.format),
+        }
+    }
+
+    /// Same for the audio settings, when recording audio.
+    fn validate_audio(&self) -> Result<(), String> {
+        if !self.audio_enabled || AnimatedFormat::from_container(&self.format).is_some() {
+            return Ok(());
+        }
+        let video = self.current_video_codec();
+        match &self.capabilities {
+            Some(caps) => caps.validate_audio(&self.audio, &self.format, video),
+            None => self.audio.validate(&self.format, video),
+        }
+    }
+
+    fn open_trim(&mut self, input: PathBuf) {
+        match probe::probe(&input) {
+            Ok(info) if info.duration > 0.0 => {
+                self.trim = Some(TrimDialog { input, duration: info.duration, plan: EditPlan::new(info.duration) });
+            }
+            Ok(_) => self.status_message = format!("Can't tell how long {:?} is", input),
+            Err(e) => self.status_message = format!("Error: {}", e),
+        }
+    }
+
+    fn show_trim(&mut self, ctx: &egui::Context) {
+        let Some(trim) = &mut self.trim else {
+            return;
+        };
+        let mut open = true;
+        let mut export = false;
+        let job_running = self.job_running;
+        egui::Window::new("Trim")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let name = trim.input.file_name().unwrap_or_default().to_string_lossy();
+                ui.label(format!("{} ({:.1} s)", name, trim.duration));
+                let duration = trim.duration;
+                ui.horizontal(|ui| {
+                    ui.label("In:");
+                    ui.add(seconds_field(&mut trim.plan.in_point, duration));
+                    ui.label("Out:");
+                    ui.add(seconds_field(&mut trim.plan.out_point, duration));
+                });
+
+                ui.label("Cut out:");
+                let mut remove = None;
+                for (i, (start, end)) in trim.plan.cuts.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(seconds_field(start, duration));
+                        ui.label("to");
+                        ui.add(seconds_field(end, duration));
+                        if ui.button("🗑").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    trim.plan.cuts.remove(i);
+                }
+                if ui.button("➕ Add cut").clicked() {
+                    let middle = (trim.plan.in_point + trim.plan.out_point) / 2.0;
+                    trim.plan.cuts.push((middle, (middle + 1.0).min(trim.plan.out_point)));
+                }
+
+                ui.separator();
+                match trim.plan.validate(trim.duration) {
+                    Ok(()) => {
+                        ui.label(format!("Result: {:.1} s", trim.plan.output_duration()));
+                        export = ui.add_enabled(!job_running, egui::Button::new("Export")).clicked();
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                }
+            });
+
+        if export {
+            let input = trim.input.clone();
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = input.extension().unwrap_or_default().to_string_lossy();
+            let dir = input.parent().unwrap_or(std::path::Path::new("."));
+            let output = filename::unique_path(dir, &format!("{}_trimmed.{}", stem, ext));
+            let plan = trim.plan.clone();
+            self.status_message = format!("Trimming to {:?}...", output);
+            self.run_job(ctx, move || JobDone::Trimmed(edit::export(&input, &output, &plan)));
+            open = false;
+        }
+        if !open {
+            self.trim = None;
+        }
+    }
+
+    // Runs `job` on a worker thread, update() picks up the result
+    fn run_job(&mut self, ctx: &egui::Context, job: impl FnOnce() -> JobDone + Send + 'static) {
+        let sender = self.job_sender.clone();
+        let ctx = ctx.clone();
+        self.job_running = true;
+        std::thread::spawn(move || {
+            let _ = sender.send(job());
+            ctx.request_repaint();
+        });
+    }
+
+    fn on_job_done(&mut self, done: JobDone) {
+        self.job_running = false;
+        match done {
+            JobDone::Trimmed(result) => {
+                self.status_message = match &result {
+                    Ok((path, ExportMethod::StreamCopy)) => format!("Trimmed without re-encoding to {:?}", path),
+                    Ok((path, ExportMethod::SmartCut)) => format!("Trimmed (re-encoded up to the next keyframe after each cut) to {:?}", path),
+                    Ok((path, ExportMethod::ReEncode)) => format!("Trimmed (re-encoded) to {:?}", path),
+                    Err(e) => format!("Error: {}", e),
+                };
+                if let Ok((path, _)) = result {
+                    self.remember(&path);
+                }
+            }
+            JobDone::ReExported(result) => match result {
+                Ok(path) => {
+                    self.status_message = format!("Exported to {:?}", path);
+                    self.remember(&path);
+                }
+                Err(e) => self.status_message = format!("Error: {}", e),
+            },
+            JobDone::ReplaySaved(result) => match result {
+                Ok(path) => {
+                    self.status_message = format!("Replay saved to {:?}", path);
+                    self.remember(&path);
+                    self.recorder.replay_saved(path);
+                }
+                Err(e) => self.status_message = format!("Error: {}", e),
+            },
+        }
+    }
+
+    fn save_replay(&mut self, ctx: &egui::Context) {
+        if self.job_running {
+            self.status_message = "Error: An export is still running".to_string();
+            return;
+        }
+        let template = filename::TemplateContext { mode: "replay", segmented: false, ..self.template_context() };
+        let path = filename::resolve_output_path(&self.output_dir, &self.filename, &template, false);
+        match self.recorder.replay_snapshot() {
+            Ok(snapshot) => {
+                self.status_message = "Saving replay...".to_string();
+                self.run_job(ctx, move || JobDone::ReplaySaved(replay::save(&snapshot, &path)));
+            }
+            Err(e) => self.status_message = format!("Error: {}", e),
+        }
+    }
+
+    // Record, Stop and Pause are shared by the buttons and the global hotkeys
+    fn can_record(&self) -> bool {
+        let missing_camera = matches!(self.mode, RecordingMode::Camera | RecordingMode::PiP)
+            && self.video_devices.is_empty();
+        let missing_audio = self.audio_enabled && self.audio_devices.is_empty();
+        !missing_camera && !missing_audio
+    }
+
+    fn start_recording(&mut self) {
+        if self.recorder.is_recording() {
+            return;
+        }
+        self.save_settings();
+        self.record_current_settings();
+    }
+
+    // Records with `profile` instead of what the GUI is set to. The GUI and
+    // the saved settings keep what the user set up.
+    fn record_profile(&mut self, name: Option<String>, profile: &Profile) {
+        let gui = self.current_profile();
+        let stream_key = self.stream_key.clone(); // Not in the profile
+        self.apply_profile(profile);
+        self.record_current_settings();
+        self.apply_profile(&gui);
+        self.stream_key = stream_key;
+        self.recording_profile = name;
+    }
+
+    // Starts a recording of what the GUI is set to, without saving it
+    fn record_current_settings(&mut self) {
+        if !self.can_record() {
+            self.status_message = "Error: Missing required devices".to_string();
+            return;
+        }
+        let path = filename::resolve_output_path(
+            &self.output_dir, &self.filename, &self.template_context(), self.overwrite);
+        let config = self.recording_config(path.clone());
+        let extra_outputs = if self.stream_enabled && self.stream_local_copy {
+            vec![OutputSpec {
+                target: OutputTarget::File { path: path.clone(), format: self.format.clone() },
+                video_kbps: self.stream_local_kbps,
+            }]
+        } else {
+            Vec::new()
+        };
+
+        self.preview = None; // Frees the camera for the recording
+        self.recording_profile = self.settings.active_profile.clone();
+
+        let started = self.validate_codec()
+            .and_then(|_| self.validate_audio())
+            .and_then(|_| self.stream_config())
+            .and_then(|stream| self.recorder.start(&RecordingConfig { stream, extra_outputs, ..config }));
+        match started {
+            Ok(_) => self.status_message = format!("Recording to {:?}", path),
+            Err(e) => self.status_message = format!("Error: {}", e),
+        }
+    }
+
+    // A file recording of what is set up in the GUI. Streaming and extra
+    // outputs are added by the caller.
+    fn recording_config(&self, path: PathBuf) -> RecordingConfig {
+        let camera_dev = self.video_devices.get(self.selected_video_device_index).cloned();
+
+        let audio_dev = if self.audio_enabled {
+            self.audio_devices.get(self.selected_audio_device_index).cloned()
+        } else {
+            None
+        };
+
+        RecordingConfig {
+            output_path: path,
+            width: self.reg_w,
+            height: self.reg_h,
+            x: self.reg_x,
+            y: self.reg_y,
+            mode: self.mode.clone(),
+            camera_device: camera_dev,
+            audio_device: audio_dev,
+            audio: self.audio,
+            container_format: self.format.clone(),
+            video_codec: self.video_codec,
+            overwrite: self.overwrite,
+            crash_safety: self.crash_safety,
+            remux_on_stop: self.remux_on_stop,
+            segmentation: self.segmentation,
+            replay_seconds: self.replay_buffer.then_some(self.replay_seconds),
+            stream: None,
+            extra_outputs: Vec::new(),
+            animated: self.animated.clone(),
+            metadata: Metadata { tags: Metadata::parse_tags(&self.metadata_tags), ..self.metadata.clone() },
+            monitor: self.monitors.get(self.selected_monitor_index).map(|m| m.name.clone()),
+            limits: self.limits,
+        }
+    }
+
+    /// Starts recording once the countdown is over and this window is out of
+    /// the way, or right away if neither is wanted.
+    fn request_recording(&mut self) {
+        if self.recorder.is_recording() || self.countdown.is_some() {
+            return;
+        }
+        if !self.can_record() {
+            self.status_message = "Error: Missing required devices".to_string();
+            return;
+        }
+        if self.countdown_seconds == 0 && !self.minimize_on_record {
+            self.start_recording();
+            return;
+        }
+        self.countdown = Some(Countdown::new(self.countdown_seconds, std::time::Instant::now()));
+    }
+
+    fn cancel_countdown(&mut self) {
+        if self.countdown.take().is_some() {
+            self.status_message = "Recording cancelled".to_string();
+        }
+    }
+
+    // The overlay counting down, centered on what is about to be recorded. It
+    // closes a moment before the capture starts so it isn't in the recording.
+    fn show_countdown(&mut self, ctx: &egui::Context) {
+        let Some(countdown) = self.countdown else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        if countdown.is_due(now) {
+            self.countdown = None;
+            self.start_recording();
+            return;
+        }
+        ctx.request_repaint_after(std::time::Duration::from_millis(50));
+        if self.minimize_on_record && !self.minimized_for_recording {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+            self.minimized_for_recording = true;
+        }
+        let Some(seconds) = countdown.remaining(now) else {
+            return;
+        };
+
+        // Monitor coordinates are in pixels, viewports in points
+        let ppp = ctx.pixels_per_point();
+        let size = egui::vec2(160.0, 160.0);
+        let center = egui::pos2(
+            (self.reg_x as f32 + self.reg_w as f32 / 2.0) / ppp,
+            (self.reg_y as f32 + self.reg_h as f32 / 2.0) / ppp,
+        );
+        let mut cancel = false;
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("countdown"),
+            egui::ViewportBuilder::default()
+                .with_title("Recording starts in")
+                .with_inner_size(size)
+                .with_position(center - size / 2.0)
+                .with_decorations(false)
+                .with_transparent(true)
+                .with_always_on_top()
+                .with_taskbar(false)
+                .with_resizable(false),
+            |ctx, _class| {
+                let frame = egui::Frame::NONE.fill(egui::Color32::from_black_alpha(200)).corner_radius(24.0);
+                egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
+                    ui.centered_and_justified(|ui| {
+                        ui.label(egui::RichText::new(seconds.to_string()).size(96.0).strong().color(egui::Color32::WHITE))
+                            .on_hover_text("Click or press Esc to cancel");
+                    });
+                });
+                cancel = ctx.input(|i| i.key_pressed(egui::Key::Escape) || i.pointer.any_click());
+            },
+        );
+        if cancel {
+            self.cancel_countdown();
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        let _ = self.try_stop_recording(); // Already in the status line
+    }
+
+    // Stops the recording or countdown and says how it went in the status line
+    fn try_stop_recording(&mut self) -> Result<(), String> {
+        if self.countdown.is_some() {
+            self.cancel_countdown();
+            return Ok(());
+        }
+        let stopped = self.recorder.stop();
+        match &stopped {
+            Ok(_) if let Some(step) = self.recorder.finishing() => self.status_message = format!("{}...", step),
+            Ok(_) => match self.recorder.last_output().map(|p| p.to_path_buf()) {
+                Some(path) => {
+                    self.status_message = format!("Saved to {:?}", path);
+                    self.remember(&path);
+                }
+                None => self.status_message = "Saved.".to_string(),
+            },
+            Err(e) => self.status_message = format!("Error stopping: {}", e),
+        }
+        stopped
+    }
+
+    // Post-processing of a stopped recording is done
+    fn on_recording_finished(&mut self, result: Result<PathBuf, String>) {
+        match result {
+            Ok(path) => {
+                self.status_message = format!("Saved to {:?}", path);
+                self.remember(&path);
+            }
+            Err(e) => self.status_message = format!("Error stopping: {}", e),
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let result = if self.recorder.is_paused() { self.recorder.resume() } else { self.recorder.pause() };
+        if let Err(e) = result {
+            self.status_message = format!("Error: {}", e);
+        }
+    }
+
+    fn drop_marker(&mut self) {
+        let label = std::mem::take(&mut self.marker_label);
+        self.status_message = match self.recorder.add_marker(Some(label)) {
+            Ok(marker) => {
+                let secs = marker.seconds as u64;
+                format!("\"{}\" at {:02}:{:02}", marker.label, secs / 60, secs % 60)
+            }
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
+    /// (Re-)registers the global hotkeys from the settings.
+    fn register_hotkeys(&mut self, ctx: &egui::Context) {
+        self.hotkey_listener = None; // Releases the old grabs first
+        let (bindings, problems) = self.settings.hotkeys.bindings();
+        self.hotkey_problems = problems;
+        if !self.settings.hotkeys.enabled || bindings.is_empty() {
+            return;
+        }
+        let sender = self.hotkey_sender.clone();
+        let ctx = ctx.clone();
+        let on_action = move |action| {
+            let _ = sender.send(action);
+            ctx.request_repaint(); // The window may not have focus
+        };
+        match hotkeys::Listener::start(bindings, on_action) {
+            Ok((listener, failed)) => {
+                self.hotkey_listener = Some(listener);
+                self.hotkey_problems.extend(failed);
+            }
+            Err(e) => self.status_message = format!("Error: {}", e),
+        }
+    }
+
+    fn on_hotkey(&mut self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::Start => self.request_recording(),
+            HotkeyAction::Stop if self.recorder.is_recording() || self.countdown.is_some() => self.stop_recording(),
+            HotkeyAction::TogglePause if self.recorder.is_recording() => self.toggle_pause(),
+            HotkeyAction::Marker => self.drop_marker(),
+            _ => {}
+        }
+    }
+
+    fn preview_source(&self) -> PreviewSource {
+        (
+            self.mode.clone(),
+            self.reg_x,
+            self.reg_y,
+            self.reg_w,
+            self.reg_h,
+            self.video_devices.get(self.selected_video_device_index).map(|d| d.id.clone()),
+        )
+    }
+
+    // Runs the preview while it is wanted and nothing is being recorded, and
+    // restarts it once a changed source has settled
+    fn update_preview(&mut self, ctx: &egui::Context) {
+        let minimized = ctx.input(|i| i.viewport().minimized == Some(true));
+        let wanted = self.settings.preview
+            && self.mode != RecordingMode::AudioOnly
+            && !self.recorder.is_recording()
+            && self.countdown.is_none()
+            && !minimized
+            && !self.hidden_to_tray;
+        let source = wanted.then(|| self.preview_source());
+
+        if source == self.preview_source {
+            self.preview_pending = None;
+        } else {
+            self.preview = None;
+            match source {
+                None => {
+                    self.preview_source = None;
+                    self.preview_pending = None;
+                }
+                Some(source) => {
+                    let since = match &self.preview_pending {
+                        Some((pending, since)) if *pending == source => *since,
+                        _ => {
+                            let now = std::time::Instant::now();
+                            self.preview_pending = Some((source.clone(), now));
+                            now
+                        }
+                    };
+                    if since.elapsed() >= PREVIEW_SETTLE {
+                        let repaint = ctx.clone();
+                        let started = Preview::start(&self.recording_config(PathBuf::new()), move || repaint.request_repaint());
+                        self.preview_problem = started.as_ref().err().cloned();
+                        self.preview = started.ok();
+                        self.preview_source = Some(source);
+                        self.preview_pending = None;
+                    } else {
+                        ctx.request_repaint_after(PREVIEW_SETTLE);
+                    }
+                }
+            }
+        }
+
+        if let Some(preview) = &self.preview {
+            if let Some(pixels) = preview.take_frame() {
+                let image = egui::ColorImage::from_rgba_unmultiplied([preview::WIDTH, preview::HEIGHT], &pixels);
+                match &mut self.preview_texture {
+                    Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                    None => self.preview_texture = Some(ctx.load_texture("preview", image, egui::TextureOptions::LINEAR)),
+                }
+            }
+            if let Some(problem) = preview.problem() {
+                self.preview_problem = Some(problem);
+            }
+        }
+    }
+
+    fn show_preview(&mut self, ui: &mut egui::Ui) {
+        if ui.checkbox(&mut self.settings.preview, "Live preview")
+            .on_hover_text("A few frames a second of what would be recorded")
+            .changed()
+        {
+            self.save_settings();
+        }
+        if !self.settings.preview {
+            return;
+        }
+        if self.mode == RecordingMode::AudioOnly {
+            ui.small("Nothing to show for audio only");
+        } else if self.recorder.is_recording() || self.countdown.is_some() {
+            ui.small("Paused while recording");
+        } else if let Some(problem) = &self.preview_problem {
+            ui.colored_label(egui::Color32::RED, problem);
+        } else if let (Some(texture), Some(_)) = (&self.preview_texture, &self.preview) {
+            ui.image((texture.id(), egui::vec2(preview::WIDTH as f32, preview::HEIGHT as f32)));
+        } else {
+            ui.spinner();
+        }
+    }
+
+    /// Shows or removes the tray icon as the settings say.
+    fn start_tray(&mut self, ctx: &egui::Context) {
+        self.tray = None;
+        self.tray_problem = None;
+        if !self.settings.tray.enabled {
+            return;
+        }
+        let sender = self.tray_sender.clone();
+        let ctx = ctx.clone();
+        let on_action = move |action| {
+            let _ = sender.send(action);
+            ctx.request_repaint(); // The window may be hidden
+        };
+        match tray::Tray::start(self.tray_state(), on_action) {
+            Ok(tray) => self.tray = Some(tray),
+            Err(e) => self.tray_problem = Some(e),
+        }
+    }
+
+    // The most recent recording, also from earlier sessions
+    fn last_recording(&self) -> Option<PathBuf> {
+        self.recorder.last_output()
+            .map(|p| p.to_path_buf())
+            .or_else(|| self.library.entries.first().map(|e| e.path.clone()))
+    }
+
+    fn tray_state(&self) -> tray::State {
+        tray::State {
+            recording: self.recorder.is_recording(),
+            paused: self.recorder.is_paused(),
+            elapsed_secs: self.recorder.get_duration().as_secs(),
+            can_pause: cfg!(not(target_os = "windows")),
+            has_last: self.last_recording().is_some(),
+        }
+    }
+
+    fn show_window(&mut self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        self.hidden_to_tray = false;
+    }
+
+    fn on_tray(&mut self, action: TrayAction, ctx: &egui::Context) {
+        match action {
+            TrayAction::Start => self.request_recording(),
+            TrayAction::Stop if self.recorder.is_recording() || self.countdown.is_some() => self.stop_recording(),
+            TrayAction::TogglePause if self.recorder.is_recording() => self.toggle_pause(),
+            TrayAction::OpenLast => {
+                if let Some(path) = self.last_recording()
+                    && let Err(e) = open::that(&path)
+                {
+                    self.status_message = format!("Error: Could not open {:?}: {}", path, e);
+                }
+            }
+            TrayAction::ShowWindow => self.show_window(ctx),
+            _ => {}
+        }
+    }
+
+    /// Starts, restarts or stops the HTTP server as the settings say.
+    fn start_remote(&mut self, ctx: &egui::Context) {
+        self.remote = None; // Frees the port first
+        self.remote_problem = None;
+        if !self.settings.remote.enabled {
+            return;
+        }
+        let ctx = ctx.clone();
+        match remote::Server::start(&self.settings.remote, self.control_sender.clone(), move || ctx.request_repaint()) {
+            Ok(server) => self.remote = Some(server),
+            Err(e) => self.remote_problem = Some(e),
+        }
+    }
+
+    fn new_remote_token(&mut self) {
+        match remote::new_token() {
+            Ok(token) => self.settings.remote.token = token,
+            Err(e) => self.remote_problem = Some(e),
+        }
+    }
+
+    fn control_status(&self) -> control::Status {
+        let stats = self.recorder.stats();
+        control::Status {
+            recording: self.recorder.is_recording(),
+            paused: self.recorder.is_paused(),
+            streaming: self.recorder.is_streaming(),
+            elapsed: self.recorder.get_duration().as_secs_f64(),
+            markers: self.recorder.markers().len(),
+            fps: stats.map(|s| s.fps),
+            size_bytes: stats.map(|s| s.total_bytes),
+            profile: if self.recorder.is_recording() { self.recording_profile.clone() } else { self.settings.active_profile.clone() },
+            last_output: self.recorder.last_output().map(|p| p.to_path_buf()),
+        }
+    }
+
+    fn on_control(&mut self, request: ControlRequest) -> Result<serde_json::Value, String> {
+        match request {
+            ControlRequest::Start { profile, config } => {
+                if self.recorder.is_recording() {
+                    return Err("Already recording".to_string());
+                }
+                self.countdown = None; // Scripts start right away
+                let requested = match (profile, config) {
+                    (Some(name), _) => Some((Some(name.clone()), self.settings.profile(&name)?.clone())),
+                    (None, Some(config)) => Some((None, *config)),
+                    (None, None) => None,
+                };
+                match requested {
+                    Some((name, profile)) => self.record_profile(name, &profile),
+                    None => self.start_recording(),
+                }
+                if !self.recorder.is_recording() {
+                    return Err(self.status_message.trim_start_matches("Error: ").to_string());
+                }
+            }
+            ControlRequest::Stop => {
+                if !self.recorder.is_recording() {
+                    return Err("Not recording".to_string());
+                }
+                self.try_stop_recording()?;
+            }
+            ControlRequest::Pause => self.recorder.pause()?,
+            ControlRequest::Resume => self.recorder.resume()?,
+            ControlRequest::Marker { label } => {
+                let marker = self.recorder.add_marker(label)?;
+                return serde_json::to_value(marker).map_err(|e| e.to_string());
+            }
+            ControlRequest::Status => {}
+        }
+        serde_json::to_value(self.control_status()).map_err(|e| e.to_string())
+    }
+
+    fn on_schedule(&mut self, event: schedule::Event) {
+        match event {
+            schedule::Event::Start(schedule) => {
+                if let Some(name) = &schedule.profile {
+                    let Some(profile) = self.settings.profiles.get(name).cloned() else {
+                        self.status_message = format!("Error: \"{}\" uses the missing profile \"{}\"", schedule.name, name);
+                        return;
+                    };
+                    self.record_profile(Some(name.clone()), &profile);
+                } else {
+                    self.start_recording();
+                }
+                self.status_message = format!("{} (scheduled: {})", self.status_message, schedule.name);
+            }
+            schedule::Event::Stop(schedule) => {
+                if self.recorder.is_recording() {
+                    self.stop_recording();
+                    self.status_message = format!("{} (scheduled: {})", self.status_message, schedule.name);
+                }
+            }
+            schedule::Event::Skipped(schedule) => {
+                self.status_message = format!("Skipped \"{}\", already recording", schedule.name);
+            }
+            schedule::Event::Missed(schedule) => {
+                self.status_message = format!("Missed \"{}\" while the recorder wasn't running", schedule.name);
+            }
+        }
+    }
+
+    fn show_schedules(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut remove = None;
+        for (i, schedule) in self.settings.schedules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut schedule.enabled, &schedule.name).changed();
+                ui.small(schedule.summary());
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.settings.schedules.remove(i);
+            changed = true;
+        }
+        if let Some((schedule, at)) = self.scheduler.next(&self.settings.schedules) {
+            ui.small(format!("Next: {} on {}", schedule.name, at.format("%a %Y-%m-%d %H:%M")));
+        }
+        ui.separator();
+
+        let form = &mut self.schedule_form;
+        egui::Grid::new("schedule_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Name:");
+            ui.add(egui::TextEdit::singleline(&mut form.name).hint_text("Standup").desired_width(180.0));
+            ui.end_row();
+            ui.label("Start:");
+            ui.add(egui::TextEdit::singleline(&mut form.start).hint_text("weekdays 09:00, 14:30, in 10m").desired_width(180.0));
+            ui.end_row();
+            ui.label("Stop:");
+            ui.add(egui::TextEdit::singleline(&mut form.stop).hint_text("after 15m, at 10:00, or empty").desired_width(180.0));
+            ui.end_row();
+            ui.label("Profile:");
+            egui::ComboBox::from_id_salt("schedule_profile_combo")
+                .selected_text(form.profile.as_deref().unwrap_or("Current settings"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut form.profile, None, "Current settings");
+                    for name in self.settings.profiles.keys() {
+                        ui.selectable_value(&mut form.profile, Some(name.clone()), name);
+                    }
+                });
+            ui.end_row();
+        });
+        let parsed = form.parse(self.scheduler.now());
+        match &parsed {
+            Err(e) if !form.start.is_empty() => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            _ => {}
+        }
+        let ready = parsed.is_ok() && !form.name.trim().is_empty();
+        if ui.add_enabled(ready, egui::Button::new("Add Schedule")).clicked()
+            && let Ok(schedule) = parsed
+        {
+            self.status_message = format!("Scheduled \"{}\": {}", schedule.name, schedule.summary());
+            self.settings.schedules.push(schedule);
+            self.schedule_form = ScheduleForm::default();
+            changed = true;
+        }
+        if changed {
+            self.save_settings();
+        }
+    }
+
+    /// Adds a finished recording to the history.
+    fn remember(&mut self, path: &std::path::Path) {
+        // Segment playlists and HLS output are not single recordings
+        if path.extension().is_some_and(|e| e == "m3u8") {
+            return;
+        }
+        let result = self.library.add(path, self.settings.active_profile.clone(), &Library::thumbnail_dir())
+            .and_then(|_| self.library.save());
+        if let Err(e) = result {
+            eprintln!("Could not add {:?} to the history: {}", path, e);
+        }
+        self.thumbnails.remove(path);
+    }
+
+    fn thumbnail(&mut self, ctx: &egui::Context, entry: &library::Entry) -> Option<egui::TextureHandle> {
+        if let Some(texture) = self.thumbnails.get(&entry.path) {
+            return Some(texture.clone());
+        }
+        let pixels = library::load_thumbnail(entry.thumbnail.as_ref()?)?;
+        let image = egui::ColorImage::from_rgba_unmultiplied([library::THUMB_WIDTH, library::THUMB_HEIGHT], &pixels);
+        let texture = ctx.load_texture(entry.path.to_string_lossy(), image, egui::TextureOptions::LINEAR);
+        self.thumbnails.insert(entry.path.clone(), texture.clone());
+        Some(texture)
+    }
+
+    fn show_history(&mut self, ui: &mut egui::Ui) {
+        enum Action {
+            Rename(usize, String),
+            Delete(usize),
+            ReExport(usize),
+        }
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Re-export as:");
+            egui::ComboBox::from_id_salt("reexport_combo")
+                .selected_text(&self.reexport_format)
+                .show_ui(ui, |ui| {
+                    let formats = codec::CONTAINERS.iter().chain(codec::AUDIO_CONTAINERS).map(|(c, _)| *c);
+                    for format in formats.chain(["gif", "webp"]) {
+                        ui.selectable_value(&mut self.reexport_format, format.to_string(), format);
+                    }
+                });
+        });
+
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            for (i, entry) in self.library.entries.clone().iter().enumerate() {
+                ui.horizontal(|ui| {
+                    match self.thumbnail(ui.ctx(), entry) {
+                        Some(texture) => {
+                            ui.image((texture.id(), egui::vec2(80.0, 45.0)));
+                        }
+                        None => {
+                            ui.add_sized([80.0, 45.0], egui::Label::new("🔊"));
+                        }
+                    }
+                    ui.vertical(|ui| {
+                        match &mut self.renaming {
+                            Some((index, name)) if *index == i => {
+                                ui.horizontal(|ui| {
+                                    ui.text_edit_singleline(name);
+                                    if ui.button("✔").clicked() {
+                                        action = Some(Action::Rename(i, name.clone()));
+                                    }
+                                });
+                            }
+                            _ => {
+                                ui.label(entry.path.file_name().unwrap_or_default().to_string_lossy());
+                            }
+                        }
+                        ui.small(entry.summary());
+                        ui.horizontal(|ui| {
+                            if ui.small_button("Open").clicked() {
+                                let _ = open::that(&entry.path);
+                            }
+                            if ui.small_button("Reveal").clicked()
+                                && let Err(e) = library::reveal(&entry.path)
+                            {
+                                self.status_message = format!("Error: {}", e);
+                            }
+                            if ui.small_button("Rename").clicked() {
+                                let stem = entry.path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                                self.renaming = Some((i, stem));
+                            }
+                            if self.confirm_delete == Some(i) {
+                                if ui.small_button("Really delete?").clicked() {
+                                    action = Some(Action::Delete(i));
+                                }
+                            } else if ui.small_button("Delete").clicked() {
+                                self.confirm_delete = Some(i);
+                            }
+                            if ui.add_enabled(!self.job_running, egui::Button::new("Re-export").small()).clicked() {
+                                action = Some(Action::ReExport(i));
+                            }
+                        });
+                    });
+                });
+                ui.separator();
+            }
+        });
+
+        let result = match action {
+            None => return,
+            Some(Action::Rename(i, name)) => {
+                self.renaming = None;
+                self.library.rename(i, &name).map(|path| format!("Renamed to {:?}", path))
+            }
+            Some(Action::Delete(i)) => {
+                self.confirm_delete = None;
+                let path = self.library.entries[i].path.clone();
+                self.thumbnails.remove(&path);
+                self.library.delete(i).map(|_| format!("Deleted {:?}", path))
+            }
+            Some(Action::ReExport(i)) => {
+                let input = self.library.entries[i].path.clone();
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+                let dir = input.parent().unwrap_or(std::path::Path::new("."));
+                let output = filename::unique_path(dir, &format!("{}.{}", stem, self.reexport_format));
+                self.status_message = format!("Exporting to {:?}...", output);
+                self.run_job(ui.ctx(), move || JobDone::ReExported(edit::convert(&input, &output)));
+                return;
+            }
+        };
+        self.status_message = match result.and_then(|message| self.library.save().map(|_| message)) {
+            Ok(message) => message,
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn recover_orphans(&mut self) {
+        let mut recovered = 0;
+        let mut errors = Vec::new();
+        for partial in std::mem::take(&mut self.orphans) {
+            match recovery::recover(&partial) {
+                Ok(_) => recovered += 1,
+                Err(e) => errors.push(e),
+            }
+        }
+        self.status_message = if errors.is_empty() {
+            format!("Recovered {} recording(s)", recovered)
+        } else {
+            format!("Recovered {} recording(s). Error: {}", recovered, errors.join("; "))
+        };
+        self.orphans = recovery::find_orphans(&self.output_dir);
+    }
+
+    fn template_context(&self) -> filename::TemplateContext<'_> {
+        filename::TemplateContext {
+            now: chrono::Local::now(),
+            mode: self.mode.as_str(),
+            monitor: self.monitors.get(self.selected_monitor_index).map(|m| m.name.as_str()).unwrap_or_default(),
+            ext: &self.format,
+            segmented: self.segmentation.is_enabled() && !self.replay_buffer,
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if !self.stream_key.trim().is_empty() {
+            self.stream_key_problem = stream::save_key(&self.stream_target, &self.stream_key)
+                .err()
+                .map(|e| format!("{}, it is only kept until the recorder closes", e));
         }
+        self.settings.last = self.current_profile();
+        if let Err(e) = self.settings.save() {
+            self.status_message = format!("Error: {}", e);
+        }
+    }
+
+    fn refresh_audio_devices(&mut self) {
+        // Keep the current selection if the device is still there
+        let selected_id = self.audio_devices.get(self.selected_audio_device_index).map(|d| d.id.clone());
+        self.audio_devices = get_audio_devices();
+        self.selected_audio_device_index = selected_id
+            .and_then(|id| self.audio_devices.iter().position(|d| d.id == id))
+            .unwrap_or(0);
     }
 }
 
 impl eframe::App for ScreenRecorderApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let save_replay_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::CTRL | egui::Modifiers::SHIFT, egui::Key::S);
+        if self.recorder.is_buffering_replay() && ctx.input_mut(|i| i.consume_shortcut(&save_replay_shortcut)) {
+            self.save_replay(ctx);
+        }
+
+        while let Ok(action) = self.hotkey_actions.try_recv() {
+            self.on_hotkey(action);
+        }
+        while let Ok(action) = self.tray_actions.try_recv() {
+            self.on_tray(action, ctx);
+        }
+        while let Ok(call) = self.control_calls.try_recv() {
+            let result = self.on_control(call.request.clone());
+            call.answer(result);
+        }
+        while let Ok(done) = self.job_results.try_recv() {
+            self.on_job_done(done);
+        }
+        for result in self.recorder.take_finished() {
+            self.on_recording_finished(result);
+        }
+        if self.recorder.finishing().is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+
+        let events = self.scheduler.tick(&mut self.settings.schedules, self.recorder.is_recording());
+        if !events.is_empty() {
+            for event in events {
+                self.on_schedule(event);
+            }
+            self.save_settings(); // One-off schedules are gone now
+        }
+        if let Some(reason) = self.recorder.check_exited() {
+            self.stop_recording();
+            self.status_message = format!("{}. {}", reason, self.status_message);
+        }
+        if let Some(reason) = self.recorder.check_limits() {
+            self.stop_recording();
+            self.status_message = format!("Stopped automatically, {}. {}", reason, self.status_message);
+        }
+        if self.scheduler.running().is_some() || self.settings.schedules.iter().any(|s| s.enabled) {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+
+        self.show_countdown(ctx);
+        if self.minimized_for_recording && self.countdown.is_none() && !self.recorder.is_recording() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            self.minimized_for_recording = false;
+        }
+
+        // Only the tray icon is left while recording, if wanted
+        let recording = self.recorder.is_recording();
+        if recording && !self.was_recording && self.tray.is_some() && self.settings.tray.hide_while_recording {
+            if HIDE_TO_TRAY {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+            } else {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+            }
+            self.hidden_to_tray = true;
+        } else if !recording && self.hidden_to_tray {
+            self.show_window(ctx);
+        }
+        self.was_recording = recording;
+        let tray_state = self.tray_state();
+        if let Some(tray) = &mut self.tray {
+            tray.set_state(tray_state);
+            if recording {
+                // Keeps the elapsed time going while the window is hidden
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+        }
+
+        self.update_preview(ctx);
+        self.show_trim(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Rust Screen Recorder");
             ui.separator();
@@ -143,7 +1530,17 @@ impl eframe::App for ScreenRecorderApp {
                 if self.recorder.is_recording() {
                     if self.recorder.is_paused() {
                         ui.colored_label(egui::Color32::YELLOW, format!("Paused ({})", time_str));
+                    } else if self.recorder.is_streaming() {
+                        ui.colored_label(egui::Color32::RED, format!("🔴 Live ({})", time_str));
+                        ctx.request_repaint();
+                    } else if self.recorder.is_buffering_replay() {
+                        ui.colored_label(egui::Color32::LIGHT_BLUE, format!("Buffering last {}s ({})", self.replay_seconds, time_str));
+                        ctx.request_repaint();
                     } else {
+                        let time_str = match self.recorder.current_segment() {
+                            Some(n) => format!("{}, segment {}", time_str, n),
+                            None => time_str,
+                        };
                         ui.colored_label(egui::Color32::RED, format!("Recording... ({})", time_str));
                         ctx.request_repaint(); // Animation
                     }
@@ -155,32 +1552,94 @@ impl eframe::App for ScreenRecorderApp {
             if !self.status_message.is_empty() {
                 ui.small(&self.status_message);
             }
+            if let Some((schedule, Some(stop_at))) = self.scheduler.running() {
+                ui.small(format!("\"{}\" stops at {}", schedule.name, stop_at.format("%H:%M")));
+            }
+
+            if !self.orphans.is_empty() && !self.recorder.is_recording() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("{} unfinished recording(s) found", self.orphans.len()),
+                    ).on_hover_text(self.orphans.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>().join("\n"));
+                    if ui.button("Recover").clicked() {
+                        self.recover_orphans();
+                    }
+                });
+            }
             ui.separator();
 
             // Settings (Disable if recording)
             ui.add_enabled_ui(!self.recorder.is_recording(), |ui| {
 
+                // Profiles
+                ui.horizontal(|ui| {
+                    ui.label("Profile:");
+                    let mut load = None;
+                    egui::ComboBox::from_id_salt("profile_combo")
+                        .selected_text(self.settings.active_profile.as_deref().unwrap_or("(none)"))
+                        .show_ui(ui, |ui| {
+                            for name in self.settings.profiles.keys() {
+                                let selected = self.settings.active_profile.as_ref() == Some(name);
+                                if ui.selectable_label(selected, name).clicked() {
+                                    load = Some(name.clone());
+                                }
+                            }
+                        });
+                    if let Some(name) = load {
+                        let profile = self.settings.profiles[&name].clone();
+                        self.apply_profile(&profile);
+                        self.settings.active_profile = Some(name.clone());
+                        self.status_message = format!("Loaded profile \"{}\"", name);
+                        self.save_settings();
+                    }
+
+                    if let Some(name) = self.settings.active_profile.clone()
+                        && ui.button("🗑").on_hover_text("Delete Profile").clicked()
+                    {
+                        self.settings.profiles.remove(&name);
+                        self.settings.active_profile = None;
+                        self.status_message = format!("Deleted profile \"{}\"", name);
+                        self.save_settings();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.new_profile_name).hint_text("Profile name").desired_width(150.0));
+                    let name = self.new_profile_name.trim().to_string();
+                    if ui.add_enabled(!name.is_empty(), egui::Button::new("Save Profile")).clicked() {
+                        self.settings.profiles.insert(name.clone(), self.current_profile());
+                        self.settings.active_profile = Some(name.clone());
+                        self.new_profile_name.clear();
+                        self.status_message = format!("Saved profile \"{}\"", name);
+                        self.save_settings();
+                    }
+                });
+                ui.separator();
+
                 // Mode Selection
                 ui.horizontal(|ui| {
                     ui.label("Mode:");
-                    egui::ComboBox::from_id_source("mode_combo")
+                    egui::ComboBox::from_id_salt("mode_combo")
                         .selected_text(match self.mode {
                             RecordingMode::Screen => "Screen Only",
                             RecordingMode::Camera => "Camera Only",
                             RecordingMode::PiP => "Screen + Camera",
+                            RecordingMode::AudioOnly => "Audio Only",
                         })
                         .show_ui(ui, |ui| {
                             ui.selectable_value(&mut self.mode, RecordingMode::Screen, "Screen Only");
                             ui.selectable_value(&mut self.mode, RecordingMode::Camera, "Camera Only");
                             ui.selectable_value(&mut self.mode, RecordingMode::PiP, "Screen + Camera");
+                            ui.selectable_value(&mut self.mode, RecordingMode::AudioOnly, "Audio Only");
                         });
                 });
+                self.fit_format_to_mode();
 
                 // Monitor Selection (Only for Screen modes)
-                if self.mode != RecordingMode::Camera {
+                if matches!(self.mode, RecordingMode::Screen | RecordingMode::PiP) {
                     ui.horizontal(|ui| {
                         ui.label("Monitor:");
-                        egui::ComboBox::from_id_source("monitor_combo")
+                        egui::ComboBox::from_id_salt("monitor_combo")
                             .selected_text(&self.monitors[self.selected_monitor_index].name)
                             .show_ui(ui, |ui| {
                                 for (i, mon) in self.monitors.iter().enumerate() {
@@ -222,13 +1681,13 @@ impl eframe::App for ScreenRecorderApp {
                 }
 
                 // Camera Selection (Only for Camera or PiP modes)
-                if self.mode != RecordingMode::Screen {
+                if matches!(self.mode, RecordingMode::Camera | RecordingMode::PiP) {
                     ui.horizontal(|ui| {
                         ui.label("Camera:");
                         if self.video_devices.is_empty() {
                             ui.colored_label(egui::Color32::RED, "No cameras found");
                         } else {
-                            egui::ComboBox::from_id_source("camera_combo")
+                            egui::ComboBox::from_id_salt("camera_combo")
                                 .selected_text(&self.video_devices[self.selected_video_device_index].name)
                                 .show_ui(ui, |ui| {
                                     for (i, dev) in self.video_devices.iter().enumerate() {
@@ -241,16 +1700,31 @@ impl eframe::App for ScreenRecorderApp {
 
                 // Audio
                 ui.collapsing("Audio", |ui| {
-                    ui.checkbox(&mut self.audio_enabled, "Record Audio");
+                    let audio_only = self.mode == RecordingMode::AudioOnly;
+                    if audio_only {
+                        self.audio_enabled = true;
+                    }
+                    ui.add_enabled(!audio_only, egui::Checkbox::new(&mut self.audio_enabled, "Record Audio"));
                     if self.audio_enabled {
                         ui.horizontal(|ui| {
                             ui.label("Device:");
-                            egui::ComboBox::from_id_source("audio_combo")
-                                .selected_text(&self.selected_audio_device)
+                            let selected_text = self.audio_devices
+                                .get(self.selected_audio_device_index)
+                                .map(|d| d.label())
+                                .unwrap_or_default();
+                            egui::ComboBox::from_id_salt("audio_combo")
+                                .selected_text(selected_text)
                                 .width(200.0)
                                 .show_ui(ui, |ui| {
-                                    for dev in &self.audio_devices {
-                                        ui.selectable_value(&mut self.selected_audio_device, dev.clone(), dev);
+                                    let mut last_group = None;
+                                    for (i, dev) in self.audio_devices.iter().enumerate() {
+                                        // Header for each sound card
+                                        if dev.group.is_some() && dev.group != last_group {
+                                            ui.separator();
+                                            ui.weak(dev.group.as_deref().unwrap_or_default());
+                                        }
+                                        last_group = dev.group.clone();
+                                        ui.selectable_value(&mut self.selected_audio_device_index, i, &dev.name);
                                     }
                                 });
 
@@ -259,92 +1733,446 @@ impl eframe::App for ScreenRecorderApp {
                             }
                         });
                         ui.small("Select your input device (e.g., Microphone)");
+
+                        let default = AudioCodec::default_for(&self.format, self.current_video_codec());
+                        ui.horizontal(|ui| {
+                            ui.label("Codec:");
+                            let selected = match self.audio.codec {
+                                Some(codec) => codec.label().to_string(),
+                                None => format!("Default ({})", default.label()),
+                            };
+                            egui::ComboBox::from_id_salt("audio_codec_combo")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.audio.codec, None, format!("Default ({})", default.label()));
+                                    for codec in codec::AUDIO_CODECS {
+                                        let available = self.capabilities.as_ref().is_none_or(|caps| caps.has_audio(*codec));
+                                        ui.add_enabled_ui(available, |ui| {
+                                            ui.selectable_value(&mut self.audio.codec, Some(*codec), codec.label())
+                                        })
+                                        .response
+                                        .on_disabled_hover_text(format!("ffmpeg was built without {}", codec.encoder()));
+                                    }
+                                });
+                            let codec = self.audio.codec.unwrap_or(default);
+                            if codec.is_lossy() {
+                                ui.add(egui::DragValue::new(&mut self.audio.kbps).range(6..=512).suffix(" kbps"));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Sample rate:");
+                            egui::ComboBox::from_id_salt("sample_rate_combo")
+                                .selected_text(format!("{} Hz", self.audio.sample_rate))
+                                .show_ui(ui, |ui| {
+                                    let codec = self.audio.codec.unwrap_or(default);
+                                    for rate in codec.sample_rates() {
+                                        ui.selectable_value(&mut self.audio.sample_rate, *rate, format!("{} Hz", rate));
+                                    }
+                                });
+                            ui.radio_value(&mut self.audio.channels, 1, "Mono");
+                            ui.radio_value(&mut self.audio.channels, 2, "Stereo");
+                        });
+                        if let Err(e) = self.validate_audio() {
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
                     }
                 });
 
+                // Preview
+                ui.collapsing("Preview", |ui| {
+                    self.show_preview(ui);
+                });
+
                 // Output
                 ui.collapsing("Output", |ui| {
                     ui.horizontal(|ui| {
                         ui.label("Path:");
                         ui.label(self.output_dir.to_string_lossy());
-                        if ui.button("Browse...").clicked() {
-                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                                self.output_dir = path;
-                            }
+                        if ui.button("Browse...").clicked()
+                            && let Some(path) = rfd::FileDialog::new().pick_folder()
+                        {
+                            self.output_dir = path;
+                            self.orphans = recovery::find_orphans(&self.output_dir);
                         }
                     });
                     ui.horizontal(|ui| {
                         ui.label("Filename:");
-                        ui.text_edit_singleline(&mut self.filename);
+                        ui.text_edit_singleline(&mut self.filename)
+                            .on_hover_text(format!("Placeholders: {}", filename::PLACEHOLDERS_HELP));
                     });
+                    ui.small(format!("→ {}", filename::render_template(&self.filename, &self.template_context())));
+                    ui.checkbox(&mut self.overwrite, "Overwrite existing files");
                     ui.horizontal(|ui| {
                         ui.label("Format:");
-                        egui::ComboBox::from_id_source("fmt_combo")
+                        egui::ComboBox::from_id_salt("fmt_combo")
                             .selected_text(&self.format)
                             .show_ui(ui, |ui| {
-                                ui.selectable_value(&mut self.format, "mp4".to_string(), "MP4 (H.264)");
-                                ui.selectable_value(&mut self.format, "webm".to_string(), "WebM (VP9)");
+                                if self.mode == RecordingMode::AudioOnly {
+                                    for (container, label) in codec::AUDIO_CONTAINERS {
+                                        ui.selectable_value(&mut self.format, container.to_string(), *label);
+                                    }
+                                    return;
+                                }
+                                for (container, label) in codec::CONTAINERS {
+                                    ui.selectable_value(&mut self.format, container.to_string(), *label);
+                                }
+                                ui.selectable_value(&mut self.format, "gif".to_string(), "GIF");
+                                ui.selectable_value(&mut self.format, "webp".to_string(), "Animated WebP");
+                            });
+                    });
+                    if AnimatedFormat::from_container(&self.format).is_none() && self.mode != RecordingMode::AudioOnly {
+                        ui.horizontal(|ui| {
+                            ui.label("Codec:");
+                            let default = VideoCodec::default_for(&self.format);
+                            let selected = match self.video_codec {
+                                Some(codec) => codec.label().to_string(),
+                                None => format!("Default ({})", default.label()),
+                            };
+                            egui::ComboBox::from_id_salt("codec_combo")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.video_codec, None, format!("Default ({})", default.label()));
+                                    for codec in codec::VIDEO_CODECS.iter().filter(|c| c.check_container(&self.format).is_ok()) {
+                                        let available = self.capabilities.as_ref().is_none_or(|caps| caps.has_video(*codec));
+                                        ui.add_enabled_ui(available, |ui| {
+                                            ui.selectable_value(&mut self.video_codec, Some(*codec), codec.label())
+                                        })
+                                        .response
+                                        .on_disabled_hover_text(format!("ffmpeg was built without {}", codec.encoder()));
+                                    }
+                                });
+                        });
+                        if let Err(e) = self.validate_codec() {
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
+                    }
+                    if AnimatedFormat::from_container(&self.format).is_some() {
+                        let animated = &mut self.animated;
+                        ui.horizontal(|ui| {
+                            ui.label("FPS:");
+                            ui.add(egui::DragValue::new(&mut animated.fps).range(1..=50));
+                            let mut scaled = animated.width.is_some();
+                            if ui.checkbox(&mut scaled, "Width:").changed() {
+                                animated.width = scaled.then_some(800);
+                            }
+                            if let Some(width) = &mut animated.width {
+                                ui.add(egui::DragValue::new(width).range(64..=3840).suffix(" px"));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Dither:");
+                            egui::ComboBox::from_id_salt("dither_combo")
+                                .selected_text(animated.dither.label())
+                                .show_ui(ui, |ui| {
+                                    for dither in [Dither::None, Dither::Bayer(3), Dither::FloydSteinberg, Dither::Sierra] {
+                                        if ui.selectable_label(animated.dither.label() == dither.label(), dither.label()).clicked() {
+                                            animated.dither = dither;
+                                        }
+                                    }
+                                });
+                            if let Dither::Bayer(scale) = &mut animated.dither {
+                                ui.add(egui::DragValue::new(scale).range(0..=5)).on_hover_text("Bayer scale");
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Plays:");
+                            ui.add(egui::DragValue::new(&mut animated.plays).range(0..=100))
+                                .on_hover_text("0 loops forever");
+                            let mut limited = animated.max_kb.is_some();
+                            if ui.checkbox(&mut limited, "Max size:").changed() {
+                                animated.max_kb = limited.then_some(10 * 1024);
+                            }
+                            if let Some(kb) = &mut animated.max_kb {
+                                ui.add(egui::DragValue::new(kb).range(50..=1_000_000).suffix(" KB"));
+                            }
+                        });
+                        ui.small("Exported when recording stops, without sound");
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Split:");
+                        let label = match self.segmentation {
+                            Segmentation::Off => "Off",
+                            Segmentation::Minutes(_) => "Every N minutes",
+                            Segmentation::Megabytes(_) => "Every N MB",
+                        };
+                        egui::ComboBox::from_id_salt("segment_combo")
+                            .selected_text(label)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.segmentation, Segmentation::Off, "Off");
+                                if ui.selectable_label(matches!(self.segmentation, Segmentation::Minutes(_)), "Every N minutes").clicked() {
+                                    self.segmentation = Segmentation::Minutes(30);
+                                }
+                                if ui.selectable_label(matches!(self.segmentation, Segmentation::Megabytes(_)), "Every N MB").clicked() {
+                                    self.segmentation = Segmentation::Megabytes(1000);
+                                }
                             });
+                        match &mut self.segmentation {
+                            Segmentation::Off => {}
+                            Segmentation::Minutes(n) => { ui.add(egui::DragValue::new(n).range(1..=1440).suffix(" min")); }
+                            Segmentation::Megabytes(n) => { ui.add(egui::DragValue::new(n).range(1..=100_000).suffix(" MB")); }
+                        }
+                    });
+                    if self.segmentation.is_enabled() {
+                        ui.small("Segments are listed in a .m3u8 index next to them");
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Crash safety:");
+                        egui::ComboBox::from_id_salt("crash_safety_combo")
+                            .selected_text(self.crash_safety.label())
+                            .show_ui(ui, |ui| {
+                                for mode in [CrashSafety::Off, CrashSafety::FragmentedMp4, CrashSafety::Matroska] {
+                                    ui.selectable_value(&mut self.crash_safety, mode, mode.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text("Keep the file playable if the app or machine dies mid-recording");
                     });
+                    if self.crash_safety != CrashSafety::Off && !self.segmentation.is_enabled() {
+                        ui.checkbox(&mut self.remux_on_stop, format!("Remux to regular {} when stopped", self.format.to_uppercase()));
+                    }
                 });
-            });
 
-            ui.separator();
+                // Countdown
+                ui.collapsing("Countdown", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Count down:");
+                        let label = |seconds: u32| if seconds == 0 { "Off".to_string() } else { format!("{} s", seconds) };
+                        egui::ComboBox::from_id_salt("countdown_combo")
+                            .selected_text(label(self.countdown_seconds))
+                            .show_ui(ui, |ui| {
+                                for &seconds in countdown::CHOICES {
+                                    ui.selectable_value(&mut self.countdown_seconds, seconds, label(seconds));
+                                }
+                            });
+                    });
+                    ui.checkbox(&mut self.minimize_on_record, "Minimize this window while recording");
+                    ui.small("Record and the start hotkey count down, schedules start on time");
+                });
 
-            // Controls
-            ui.horizontal(|ui| {
-                if !self.recorder.is_recording() {
-                    let can_record = if self.mode != RecordingMode::Screen && self.video_devices.is_empty() {
-                        false
-                    } else if self.audio_enabled && self.audio_devices.is_empty() {
-                        false
-                    } else {
-                        true
-                    };
+                // Auto-stop
+                ui.collapsing("Auto-stop", |ui| {
+                    let limits = &mut self.limits;
+                    limit_row(ui, "After", &mut limits.max_minutes, 60, 1..=24 * 60, " min");
+                    limit_row(ui, "At a size of", &mut limits.max_megabytes, 4000, 1..=1_000_000, " MB");
+                    limit_row(ui, "When free disk space is below", &mut limits.min_free_megabytes, 2000, 100..=1_000_000, " MB");
+                    if matches!(self.mode, RecordingMode::Screen | RecordingMode::PiP) {
+                        limit_row(ui, "When nothing changes on screen for", &mut limits.idle_minutes, 10, 1..=240, " min");
+                    }
+                    ui.small("Stops cleanly, like pressing Stop");
+                });
 
-                    if ui.add_enabled(can_record, egui::Button::new("🔴 Record")).clicked() {
-                        let path = self.output_dir.join(&self.filename);
+                // Replay buffer
+                ui.collapsing("Replay Buffer", |ui| {
+                    ui.checkbox(&mut self.replay_buffer, "Only keep the last few seconds")
+                        .on_hover_text("Capture continuously and save the recent past on demand");
+                    if self.replay_buffer {
+                        ui.horizontal(|ui| {
+                            ui.label("Keep:");
+                            ui.add(egui::DragValue::new(&mut self.replay_seconds).range(replay::SEGMENT_SECONDS..=3600).suffix(" s"));
+                        });
+                        ui.small("Save with the button or Ctrl+Shift+S while buffering");
+                    }
+                });
 
-                        let camera_dev = if !self.video_devices.is_empty() {
-                            self.video_devices[self.selected_video_device_index].id.clone()
-                        } else {
-                            String::new()
-                        };
+                // Streaming
+                ui.collapsing("Streaming", |ui| {
+                    ui.checkbox(&mut self.stream_enabled, "Stream live instead of recording to a file");
+                    if self.stream_enabled {
+                        ui.horizontal(|ui| {
+                            ui.label("Target:");
+                            let edited = ui.add(egui::TextEdit::singleline(&mut self.stream_target)
+                                .hint_text("rtmp://, srt:// or HLS folder")
+                                .desired_width(220.0))
+                                .changed();
+                            // A pasted URL gives up its key to the field below
+                            if edited && let (server, Some(key)) = stream::split_key(&self.stream_target) {
+                                self.stream_target = server;
+                                self.stream_key = key;
+                            }
+                        });
+                        if stream::is_rtmp(&self.stream_target) {
+                            ui.horizontal(|ui| {
+                                ui.label("Stream key:");
+                                ui.add(egui::TextEdit::singleline(&mut self.stream_key).password(true).desired_width(180.0));
+                            });
+                        }
+                        if let Some(problem) = &self.stream_key_problem {
+                            ui.colored_label(egui::Color32::YELLOW, problem);
+                        }
+                        if let Err(e) = StreamTarget::parse(&self.stream_target) {
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Bitrate:");
+                            ui.add(egui::DragValue::new(&mut self.stream_video_kbps).range(300..=50_000).suffix(" kbps"));
+                            ui.label("Keyframe every:");
+                            ui.add(egui::DragValue::new(&mut self.stream_keyframe_seconds).range(1..=10).suffix(" s"));
+                        });
+                        ui.checkbox(&mut self.stream_local_copy, "Also record to a file");
+                        if self.stream_local_copy {
+                            ui.horizontal(|ui| {
+                                let mut own_bitrate = self.stream_local_kbps.is_some();
+                                if ui.checkbox(&mut own_bitrate, "Own bitrate").changed() {
+                                    // Off shares the stream encode, which costs no extra CPU
+                                    self.stream_local_kbps = own_bitrate.then_some(8000);
+                                }
+                                if let Some(kbps) = &mut self.stream_local_kbps {
+                                    ui.add(egui::DragValue::new(kbps).range(300..=100_000).suffix(" kbps"));
+                                }
+                            });
+                            ui.small("The file keeps recording if the stream drops");
+                        }
+                    }
+                });
 
-                        let audio_dev = if !self.audio_devices.is_empty() {
-                             self.audio_devices[self.selected_audio_device_index].id.clone()
-                        } else {
-                             "default".to_string()
-                        };
+                // Schedules
+                ui.collapsing(format!("Schedule ({})", self.settings.schedules.len()), |ui| {
+                    self.show_schedules(ui);
+                });
 
-                        let config = RecordingConfig {
-                            output_path: path.clone(),
-                            width: self.reg_w,
-                            height: self.reg_h,
-                            x: self.reg_x,
-                            y: self.reg_y,
-                            mode: self.mode.clone(),
-                            camera_device: camera_dev,
-                            audio_enabled: self.audio_enabled,
-                            audio_device: self.selected_audio_device.clone(),
-                            container_format: self.format.clone(),
-                        };
+                // Metadata
+                ui.collapsing("Metadata", |ui| {
+                    egui::Grid::new("metadata_grid").num_columns(2).show(ui, |ui| {
+                        let metadata = &mut self.metadata;
+                        for (label, value) in [("Title:", &mut metadata.title), ("Author:", &mut metadata.author)] {
+                            ui.label(label);
+                            ui.add(egui::TextEdit::singleline(value).desired_width(220.0));
+                            ui.end_row();
+                        }
+                        ui.label("Description:");
+                        ui.add(egui::TextEdit::multiline(&mut metadata.description).desired_rows(2).desired_width(220.0));
+                        ui.end_row();
+                        ui.label("Tags:");
+                        ui.add(egui::TextEdit::singleline(&mut self.metadata_tags).hint_text("demo, sprint 12").desired_width(220.0));
+                        ui.end_row();
+                        ui.label("Comment:");
+                        ui.add(egui::TextEdit::singleline(&mut metadata.comment).desired_width(220.0));
+                        ui.end_row();
+                    });
+                    ui.small("Capture mode, monitor, region, devices, host and app version are added automatically");
+                });
+
+                // Tray icon
+                ui.collapsing("Tray", |ui| {
+                    let mut changed = ui.checkbox(&mut self.settings.tray.enabled, "Show a tray icon")
+                        .on_hover_text("Start, stop, pause and open the last recording from the tray")
+                        .changed();
+                    if self.settings.tray.enabled {
+                        let label = if HIDE_TO_TRAY { "Hide this window while recording" } else { "Minimize this window while recording" };
+                        changed |= ui.checkbox(&mut self.settings.tray.hide_while_recording, label)
+                            .on_hover_text("Click the tray icon or \"Show Window\" to bring it back")
+                            .changed();
+                    }
+                    if let Some(problem) = &self.tray_problem {
+                        ui.colored_label(egui::Color32::RED, problem);
+                    }
+                    if changed {
+                        if self.tray.is_some() != self.settings.tray.enabled {
+                            self.start_tray(ui.ctx());
+                        }
+                        self.save_settings();
+                    }
+                });
+
+                // Global hotkeys
+                ui.collapsing("Hotkeys", |ui| {
+                    let mut changed = ui.checkbox(&mut self.settings.hotkeys.enabled, "Global hotkeys")
+                        .on_hover_text("Work while another window has focus")
+                        .changed();
+                    if self.settings.hotkeys.enabled {
+                        egui::Grid::new("hotkey_grid").num_columns(2).show(ui, |ui| {
+                            for &action in hotkeys::ACTIONS {
+                                ui.label(action.label());
+                                let edit = egui::TextEdit::singleline(self.settings.hotkeys.binding_mut(action))
+                                    .hint_text("e.g. Ctrl+Alt+R")
+                                    .desired_width(120.0);
+                                changed |= ui.add(edit).lost_focus();
+                                ui.end_row();
+                            }
+                        });
+                        for (action, problem) in &self.hotkey_problems {
+                            ui.colored_label(egui::Color32::RED, format!("{}: {}", action.label(), problem));
+                        }
+                    }
+                    if changed {
+                        self.register_hotkeys(ui.ctx());
+                        self.save_settings();
+                    }
+                });
 
-                        match self.recorder.start(&config) {
-                            Ok(_) => self.status_message = format!("Recording to {:?}", path),
-                            Err(e) => self.status_message = format!("Error: {}", e),
+                // Control socket and HTTP server
+                ui.collapsing("Remote Control", |ui| {
+                    if let Some(server) = &self.control {
+                        ui.label(format!("Listening on {}", server.path().display()));
+                        ui.small("Drive this window with `rust_version ctl start|stop|pause|resume|marker|status`");
+                    }
+                    if let Some(problem) = &self.control_problem {
+                        ui.colored_label(egui::Color32::RED, problem);
+                    }
+                    ui.separator();
+
+                    let mut changed = ui.checkbox(&mut self.settings.remote.enabled, "HTTP server on localhost")
+                        .on_hover_text("REST endpoints and a WebSocket with progress, for test rigs and other programs")
+                        .changed();
+                    if changed && self.settings.remote.enabled && self.settings.remote.token.is_empty() {
+                        self.new_remote_token();
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Port:");
+                        changed |= ui.add(egui::DragValue::new(&mut self.settings.remote.port).range(1024..=65535)).lost_focus();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Token:");
+                        ui.add(egui::Label::new(egui::RichText::new(&self.settings.remote.token).monospace()).selectable(true));
+                        if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                            ui.ctx().copy_text(self.settings.remote.token.clone());
+                        }
+                        if ui.small_button("New").on_hover_text("Clients with the old token are locked out").clicked() {
+                            self.new_remote_token();
+                            changed = true;
                         }
+                    });
+                    if let Some(server) = &self.remote {
+                        ui.label(format!("Listening on http://{}", server.addr()));
+                    }
+                    if let Some(problem) = &self.remote_problem {
+                        ui.colored_label(egui::Color32::RED, problem);
+                    }
+                    if changed {
+                        self.start_remote(ui.ctx());
+                        self.save_settings();
+                    }
+                });
+            });
+
+            ui.separator();
+
+            // Controls
+            ui.horizontal(|ui| {
+                if let Some(countdown) = self.countdown {
+                    let seconds = countdown.remaining(std::time::Instant::now()).unwrap_or(0);
+                    ui.label(format!("Starting in {}…", seconds));
+                    if ui.button("✖ Cancel").clicked() {
+                        self.cancel_countdown();
+                    }
+                } else if !self.recorder.is_recording() {
+                    let can_record = self.can_record();
+                    if ui.add_enabled(can_record, egui::Button::new("🔴 Record")).clicked() {
+                        self.request_recording();
                     }
 
                     if !can_record {
                         ui.small("Missing required devices");
                     }
                 } else {
+                    if self.recorder.is_buffering_replay()
+                        && ui.add_enabled(!self.job_running, egui::Button::new("💾 Save Replay")).clicked()
+                    {
+                        self.save_replay(ui.ctx());
+                    }
+
                     if ui.button("⏹ Stop").clicked() {
-                        match self.recorder.stop() {
-                            Ok(_) => self.status_message = "Saved.".to_string(),
-                            Err(e) => self.status_message = format!("Error stopping: {}", e),
-                        }
+                        self.stop_recording();
                     }
 
                     // Pause/Resume Logic
@@ -352,28 +2180,174 @@ impl eframe::App for ScreenRecorderApp {
                         // Disable buttons on Windows
                         ui.add_enabled(false, egui::Button::new("⏸ Pause")).on_disabled_hover_text("Pause is not supported on Windows");
                     } else {
-                        if self.recorder.is_paused() {
-                            if ui.button("▶ Resume").clicked() {
-                                let _ = self.recorder.resume();
-                            }
-                        } else {
-                            if ui.button("⏸ Pause").clicked() {
-                                let _ = self.recorder.pause();
-                            }
+                        let label = if self.recorder.is_paused() { "▶ Resume" } else { "⏸ Pause" };
+                        if ui.button(label).clicked() {
+                            self.toggle_pause();
                         }
                     }
+                    if ui.button("📍 Marker").on_hover_text("Becomes a chapter of the recording").clicked() {
+                        self.drop_marker();
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut self.marker_label)
+                        .hint_text(format!("Marker {}", self.recorder.markers().len() + 1))
+                        .desired_width(100.0));
                 }
             });
 
             // Add Open Folder button
-            if !self.recorder.is_recording() && ui.button("Open Output Folder").clicked() {
-                 let _ = open::that(&self.output_dir);
+            if !self.recorder.is_recording() {
+                ui.horizontal(|ui| {
+                    if ui.button("Open Output Folder").clicked() {
+                        let _ = open::that(&self.output_dir);
+                    }
+                    // Playlists and animations are not something to trim
+                    let trimmable = self.recorder.last_output()
+                        .filter(|p| p.is_file())
+                        .filter(|p| !p.extension().is_some_and(|e| e == "m3u8" || e == "gif" || e == "webp"))
+                        .map(|p| p.to_path_buf());
+                    if let Some(path) = trimmable
+                        && self.trim.is_none()
+                        && ui.button("✂ Trim…").clicked()
+                    {
+                        self.open_trim(path);
+                    }
+                });
             }
+
+            let title = format!("History ({})", self.library.entries.len());
+            egui::CollapsingHeader::new(title).id_salt("history").show(ui, |ui| {
+                if self.library.entries.is_empty() {
+                    ui.small("Finished recordings show up here");
+                } else {
+                    self.show_history(ui);
+                }
+            });
         });
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        for result in self.recorder.wait_finishing() {
+            self.on_recording_finished(result);
+        }
+        self.save_settings();
+    }
 }
 
 fn main() -> eframe::Result<()> {
+    // `ctl` talks to a running instance instead of starting one
+    if std::env::args().nth(1).as_deref() == Some("ctl") {
+        let command = match cli::parse_ctl(std::env::args().skip(2)) {
+            Ok(cli::CtlCommand::Help) => {
+                println!("{}", cli::CTL_USAGE);
+                return Ok(());
+            }
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("{}\n\n{}", e, cli::CTL_USAGE);
+                std::process::exit(2);
+            }
+        };
+        match control::ctl(&control::socket_path(), &command) {
+            Ok(result) => println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let mut settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{}, using defaults", e);
+        Settings::default()
+    });
+
+    settings.move_stream_keys();
+
+    if args.list_profiles {
+        for name in settings.profiles.keys() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    if args.list_schedules {
+        for schedule in &settings.schedules {
+            let state = if schedule.enabled { "" } else { " (off)" };
+            println!("{}{}: {}", schedule.name, state, schedule.summary());
+        }
+        return Ok(());
+    }
+
+    // A schedule from the command line is kept like one added in the GUI
+    if let Some(start) = &args.start {
+        let stop = args.stop.as_deref().unwrap_or("");
+        let schedule = Start::parse(start, SystemClock.now()).and_then(|start| {
+            Ok(Schedule {
+                name: "Command line".to_string(),
+                start,
+                stop: Stop::parse(stop)?,
+                profile: args.profile.clone(),
+                enabled: true,
+            })
+        });
+        match schedule {
+            Ok(schedule) => {
+                println!("Scheduled: {}", schedule.summary());
+                if !settings.schedules.contains(&schedule) {
+                    settings.schedules.push(schedule);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // --profile wins over whatever was used last
+    let mut profile = match &args.profile {
+        Some(name) => match settings.profile(name) {
+            Ok(profile) => {
+                let profile = profile.clone();
+                settings.active_profile = Some(name.clone());
+                profile
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        },
+        None => settings.last.clone(),
+    };
+
+    if let Some(target) = &args.stream {
+        if let Err(e) = StreamTarget::parse(target) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        profile.stream_enabled = true;
+        profile.stream_target = target.clone();
+    }
+    if let Some(kbps) = args.stream_bitrate {
+        profile.stream_video_kbps = kbps;
+    }
+    if args.record_copy {
+        profile.stream_local_copy = true;
+    }
+
     // Log info
     println!("Starting Screen Recorder...");
 
@@ -387,6 +2361,41 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Screen Recorder",
         native_options,
-        Box::new(|cc| Ok(Box::new(ScreenRecorderApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(ScreenRecorderApp::new(cc, settings, profile)))),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduled_start_keeps_the_gui_and_saved_settings() {
+        let meeting = Profile { mode: RecordingMode::Camera, format: "mkv".to_string(), ..Profile::default() };
+        let mut settings = Settings::default();
+        settings.profiles.insert("Meeting".to_string(), meeting);
+        settings.active_profile = Some("Desk".to_string());
+        let monitor = MonitorInfo { name: "Monitor 1".to_string(), width: 1920, height: 1080, x: 0, y: 0 };
+        let mut app = ScreenRecorderApp::with_devices(settings, vec![monitor], Vec::new(), Vec::new(), Library::default());
+        app.apply_profile(&Profile::default());
+        app.stream_key = "secret".to_string();
+        app.settings.last = app.current_profile();
+        let gui = app.current_profile();
+        let saved = app.settings.clone();
+
+        app.on_schedule(schedule::Event::Start(Schedule {
+            name: "Standup".to_string(),
+            start: Start::Weekly { time: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(), days: Vec::new() },
+            stop: Stop::Manual,
+            profile: Some("Meeting".to_string()),
+            enabled: true,
+        }));
+
+        // The camera profile was tried, there is no camera here
+        assert!(app.status_message.contains("Missing required devices"), "{}", app.status_message);
+        assert_eq!(app.recording_profile.as_deref(), Some("Meeting"));
+        assert_eq!(app.current_profile(), gui);
+        assert_eq!(app.stream_key, "secret");
+        assert_eq!(app.settings, saved);
+    }
+}
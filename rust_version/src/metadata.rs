@@ -0,0 +1,124 @@
+use crate::recorder::{RecordingConfig, RecordingMode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// What the user says about a recording. Empty fields are left out.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub comment: String,
+}
+
+impl Metadata {
+    /// Splits comma-separated tags as typed in the GUI.
+    pub fn parse_tags(text: &str) -> Vec<String> {
+        text.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect()
+    }
+
+    // Keys most players know: "artist" is what MP4, Matroska, Vorbis comments
+    // and WAV all show as the author
+    fn pairs(&self) -> Vec<(&'static str, String)> {
+        [
+            ("title", self.title.trim().to_string()),
+            ("artist", self.author.trim().to_string()),
+            ("description", self.description.trim().to_string()),
+            ("keywords", self.tags.join(", ")),
+            ("comment", self.comment.trim().to_string()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+}
+
+/// MP4-style containers only keep keys of their own unless told to write
+/// arbitrary ones, which the capture details need.
+pub const MOV_FLAG: &str = "+use_metadata_tags";
+
+pub fn needs_mov_flag(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "mp4" || e == "mov" || e == "m4a")
+}
+
+fn host_name() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let name = std::env::var("COMPUTERNAME").ok();
+    #[cfg(not(target_os = "windows"))]
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok());
+    name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+/// How the recording was made, for archive tooling to index.
+fn capture_pairs(config: &RecordingConfig) -> Vec<(&'static str, String)> {
+    let mut pairs = vec![("capture_mode", config.mode.as_str().to_string())];
+    if matches!(config.mode, RecordingMode::Screen | RecordingMode::PiP) {
+        if let Some(monitor) = &config.monitor {
+            pairs.push(("capture_monitor", monitor.clone()));
+        }
+        pairs.push(("capture_region", format!("{}x{}+{}+{}", config.width, config.height, config.x, config.y)));
+    }
+    if matches!(config.mode, RecordingMode::Camera | RecordingMode::PiP)
+        && let Some(camera) = &config.camera_device
+    {
+        pairs.push(("capture_camera", camera.name.clone()));
+    }
+    if let Some(audio) = &config.audio_device {
+        pairs.push(("capture_audio", audio.name.clone()));
+    }
+    if let Some(host) = host_name() {
+        pairs.push(("capture_host", host));
+    }
+    pairs.push(("recorder", format!("Rust Screen Recorder {}", env!("CARGO_PKG_VERSION"))));
+    pairs.push(("creation_time", "now".to_string())); // ffmpeg fills in the start time
+    pairs
+}
+
+/// `-metadata key=value` arguments for the user's fields and the capture details.
+pub fn args(config: &RecordingConfig) -> Vec<String> {
+    config.metadata.pairs().into_iter()
+        .chain(capture_pairs(config))
+        .flat_map(|(key, value)| ["-metadata".to_string(), format!("{}={}", key, value)])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_list::{DeviceProvider, MockProvider};
+
+    #[test]
+    fn user_fields_come_before_capture_details() {
+        let config = RecordingConfig {
+            mode: RecordingMode::PiP,
+            monitor: Some("Monitor 2".to_string()),
+            x: 1920,
+            width: 1280,
+            height: 720,
+            camera_device: MockProvider::default().video_devices().into_iter().next(),
+            metadata: Metadata {
+                title: " Sprint demo ".to_string(),
+                tags: Metadata::parse_tags("demo, , sprint 12,"),
+                ..Metadata::default()
+            },
+            ..RecordingConfig::default()
+        };
+        let args = args(&config);
+        let values: Vec<&str> = args.iter().skip(1).step_by(2).map(String::as_str).collect();
+        assert_eq!(&values[..5], [
+            "title=Sprint demo",
+            "keywords=demo, sprint 12",
+            "capture_mode=pip",
+            "capture_monitor=Monitor 2",
+            "capture_region=1280x720+1920+0",
+        ]);
+        assert!(values[5].starts_with("capture_camera="));
+        assert!(values.iter().any(|v| v.starts_with("recorder=Rust Screen Recorder ")));
+        assert!(args.iter().step_by(2).all(|a| a == "-metadata"));
+    }
+}
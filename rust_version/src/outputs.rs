@@ -0,0 +1,334 @@
+use crate::codec::{AudioCodec, AudioSettings, VideoCodec};
+use crate::metadata;
+use crate::stream::{self, StreamConfig, StreamTarget};
+use std::path::PathBuf;
+
+/// One destination of a recording with several outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputTarget {
+    File { path: PathBuf, format: String }, // format: "mp4", "webm", "mkv"
+    Stream(StreamTarget),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSpec {
+    pub target: OutputTarget,
+    pub video_kbps: Option<u32>, // None shares the main encode if the codec fits
+}
+
+/// How the outputs are encoded, besides each output's own bitrate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Encoding {
+    pub video_codec: Option<VideoCodec>, // For files, None picks the container's default
+    pub audio: AudioSettings,            // For files, streams always get AAC
+    pub keyframe_seconds: u32,           // For streams
+    pub fragmented_files: bool,
+}
+
+impl Encoding {
+    fn video_for(&self, format: &str) -> VideoCodec {
+        self.video_codec.unwrap_or_else(|| VideoCodec::default_for(format))
+    }
+
+    // A file takes the stream's H.264/AAC as is only when no codec settings
+    // were changed, otherwise the user's choice wins over the shared encode
+    fn file_fits_stream(&self, format: &str) -> bool {
+        self.video_codec.is_none()
+            && self.audio == AudioSettings::default()
+            && self.video_for(format) == VideoCodec::H264
+            && self.audio.codec_for(format, VideoCodec::H264) == AudioCodec::Aac
+    }
+
+    // Whether a stream can ride along with the encode of a file in `format`
+    fn stream_fits_file(&self, format: &str) -> bool {
+        self.video_for(format) == VideoCodec::H264 && self.audio.codec_for(format, VideoCodec::H264) == AudioCodec::Aac
+    }
+}
+
+impl OutputTarget {
+    /// The `[options]destination` part of a tee slave.
+    fn tee_slave(&self, fragmented: bool) -> String {
+        let (options, destination) = match self {
+            OutputTarget::File { path, format } => {
+                // Unknown formats were rejected when planning the encodes
+                let mut options = vec![format!("f={}", tee_format(format).unwrap_or(format))];
+                if matches!(format.as_str(), "mp4" | "mov") {
+                    // The index goes last unless the file is fragmented
+                    let layout = if fragmented { "+frag_keyframe+empty_moov" } else { "+faststart" };
+                    options.push(format!("movflags={}{}", layout, metadata::MOV_FLAG));
+                }
+                (options, path.to_string_lossy().to_string())
+            }
+            OutputTarget::Stream(target) => {
+                // ffmpeg-style args: [-f, fmt, (-opt, value)*, (-y)?, destination]
+                let args = target.output_args();
+                let destination = args.last().cloned().unwrap_or_default();
+                let mut options = Vec::new();
+                let mut rest = args[..args.len() - 1].iter();
+                while let Some(flag) = rest.next() {
+                    if flag == "-y" {
+                        continue;
+                    }
+                    if let Some(value) = rest.next() {
+                        options.push(format!("{}={}", flag.trim_start_matches('-'), escape_tee_option(value)));
+                    }
+                }
+                // A dropped stream must not take the local recording down with it
+                options.push("onfail=ignore".to_string());
+                (options, destination)
+            }
+        };
+        format!("[{}]{}", options.join(":"), escape_tee_destination(&destination))
+    }
+}
+
+// The muxer tee writes a file format with
+fn tee_format(format: &str) -> Result<&'static str, String> {
+    match format {
+        "mp4" => Ok("mp4"),
+        "mov" => Ok("mov"),
+        "webm" => Ok("webm"),
+        "mkv" => Ok("matroska"),
+        other => Err(format!("{} can't be recorded while streaming, use MP4, MOV, WebM or MKV", other.to_uppercase())),
+    }
+}
+
+// Values inside `[...]` are separated by ':', which has to be escaped
+fn escape_tee_option(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, ':' | '\\' | '\'' | '|' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The slave list is split on '|', and '[' would start an option block
+fn escape_tee_destination(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '\\' | '\'' | '|' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Outputs sharing one encoder
+struct EncodeGroup {
+    args: Vec<String>,
+    targets: Vec<OutputTarget>,
+}
+
+// The encoder options `spec` gets when it doesn't share another encode
+fn encoder_args(spec: &OutputSpec, encoding: &Encoding, with_audio: bool) -> Result<Vec<String>, String> {
+    let mut args = match &spec.target {
+        OutputTarget::Stream(target) => StreamConfig {
+            target: target.clone(),
+            video_kbps: spec.video_kbps.unwrap_or(stream::DEFAULT_VIDEO_KBPS),
+            keyframe_seconds: encoding.keyframe_seconds,
+        }
+        .encoder_args(with_audio),
+        OutputTarget::File { format, .. } => {
+            tee_format(format)?;
+            let video = encoding.video_for(format);
+            video.check_container(format)?;
+            let mut args = video.encoder_args(format);
+            if let Some(kbps) = spec.video_kbps {
+                if !video.rate_controlled() {
+                    return Err(format!("{} has no bitrate to set", video.label()));
+                }
+                args.extend([
+                    "-b:v".into(), format!("{}k", kbps),
+                    "-maxrate".into(), format!("{}k", kbps),
+                    "-bufsize".into(), format!("{}k", kbps * 2),
+                ]);
+            }
+            if with_audio {
+                // tee has no default codecs, so audio must be spelled out
+                encoding.audio.validate(format, video)?;
+                args.extend(encoding.audio.encoder_args(format, video));
+            }
+            args
+        }
+    };
+    // mp4 and flv want the codec headers up front, and the encoder is shared
+    args.extend(["-flags".into(), "+global_header".into()]);
+    Ok(args)
+}
+
+// Whether `extra`, having no bitrate of its own, can take the main encode
+fn rides_along(main: &OutputSpec, extra: &OutputSpec, encoding: &Encoding) -> bool {
+    if extra.video_kbps.is_some() {
+        return false;
+    }
+    match (&main.target, &extra.target) {
+        (OutputTarget::Stream(_), OutputTarget::File { format, .. }) => encoding.file_fits_stream(format),
+        (OutputTarget::File { format, .. }, OutputTarget::Stream(_)) => encoding.stream_fits_file(format),
+        (OutputTarget::Stream(_), OutputTarget::Stream(_)) => true,
+        // Files with the same settings end up in one encode anyway
+        (OutputTarget::File { .. }, OutputTarget::File { .. }) => false,
+    }
+}
+
+/// Groups the outputs so that everything that can share an encode does.
+/// `main` is the output whose settings define the shared encode. Fails when
+/// a file's format or codec settings don't go together.
+fn group_outputs(main: &OutputSpec, extras: &[OutputSpec], encoding: &Encoding, with_audio: bool) -> Result<Vec<EncodeGroup>, String> {
+    let mut groups = vec![EncodeGroup {
+        args: encoder_args(main, encoding, with_audio)?,
+        targets: vec![main.target.clone()],
+    }];
+
+    for extra in extras {
+        // Built either way, so that a file sharing the stream encode is checked too
+        let args = encoder_args(extra, encoding, with_audio)?;
+        if rides_along(main, extra, encoding) {
+            groups[0].targets.push(extra.target.clone());
+            continue;
+        }
+        match groups.iter_mut().find(|g| g.args == args) {
+            Some(group) => group.targets.push(extra.target.clone()),
+            None => groups.push(EncodeGroup { args, targets: vec![extra.target.clone()] }),
+        }
+    }
+    Ok(groups)
+}
+
+/// The number of video encodes `build_args` will need, so the caller can split
+/// a filtered video stream into that many labels.
+pub fn encode_count(main: &OutputSpec, extras: &[OutputSpec], encoding: &Encoding, with_audio: bool) -> Result<usize, String> {
+    Ok(group_outputs(main, extras, encoding, with_audio)?.len())
+}
+
+/// Output arguments for a recording going to several places at once: one
+/// `-map ... -f tee` block per encode. `video_sources` has one entry per encode
+/// (an input stream like `0:v`, or a filter label like `[v0]`). `metadata` is
+/// repeated for every block, tee hands it on to its outputs.
+pub fn build_args(
+    main: &OutputSpec,
+    extras: &[OutputSpec],
+    encoding: &Encoding,
+    video_sources: &[String],
+    audio_source: Option<&str>,
+    metadata: &[String],
+) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for (group, video) in group_outputs(main, extras, encoding, audio_source.is_some())?.iter().zip(video_sources) {
+        args.extend(["-map".to_string(), video.clone()]);
+        if let Some(audio) = audio_source {
+            args.extend(["-map".to_string(), audio.to_string()]);
+        }
+        args.extend(group.args.iter().cloned());
+        args.extend_from_slice(metadata);
+        let slaves: Vec<String> = group.targets.iter().map(|t| t.tee_slave(encoding.fragmented_files)).collect();
+        args.extend(["-f".to_string(), "tee".to_string(), slaves.join("|")]);
+    }
+    Ok(args)
+}
+
+/// Where the local copy ends up, the first file among the outputs, or else
+/// the HLS playlist.
+pub fn first_file(main: &OutputSpec, extras: &[OutputSpec]) -> Option<PathBuf> {
+    let outputs = || std::iter::once(main).chain(extras);
+    outputs()
+        .find_map(|o| match &o.target {
+            OutputTarget::File { path, .. } => Some(path.clone()),
+            OutputTarget::Stream(_) => None,
+        })
+        .or_else(|| outputs().find_map(|o| match &o.target {
+            OutputTarget::Stream(target) => target.playlist(),
+            OutputTarget::File { .. } => None,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(url: &str, kbps: Option<u32>) -> OutputSpec {
+        OutputSpec { target: OutputTarget::Stream(StreamTarget::parse(url).unwrap()), video_kbps: kbps }
+    }
+
+    fn file(path: &str, format: &str, kbps: Option<u32>) -> OutputSpec {
+        OutputSpec { target: OutputTarget::File { path: PathBuf::from(path), format: format.to_string() }, video_kbps: kbps }
+    }
+
+    fn encoding(video_codec: Option<VideoCodec>) -> Encoding {
+        Encoding { video_codec, audio: AudioSettings::default(), keyframe_seconds: 2, fragmented_files: false }
+    }
+
+    #[test]
+    fn local_copy_shares_the_stream_encode() {
+        let main = stream("rtmp://localhost/live/key", Some(3000));
+        let extras = [file("/v/archive.mp4", "mp4", None)];
+        assert_eq!(encode_count(&main, &extras, &encoding(None), true), Ok(1));
+
+        let args = build_args(&main, &extras, &encoding(None), &["0:v".to_string()], Some("1:a"), &[]).unwrap();
+        assert_eq!(args.iter().filter(|a| *a == "tee").count(), 1);
+        assert!(args.contains(&"3000k".to_string()));
+        assert_eq!(
+            args.last().unwrap(),
+            "[f=flv:onfail=ignore]rtmp://localhost/live/key|[f=mp4:movflags=+faststart+use_metadata_tags]/v/archive.mp4"
+        );
+    }
+
+    #[test]
+    fn own_bitrate_or_codec_means_a_second_encode() {
+        let main = stream("srt://10.0.0.2:9000", Some(2500));
+        let extras = [file("/v/hq.mp4", "mp4", Some(8000)), file("/v/web.webm", "webm", None)];
+        let fragmented = Encoding { fragmented_files: true, ..encoding(None) };
+        assert_eq!(encode_count(&main, &extras, &fragmented, false), Ok(3));
+
+        let sources: Vec<String> = (0..3).map(|i| format!("[v{}]", i)).collect();
+        let metadata = ["-metadata".to_string(), "title=Demo".to_string()];
+        let args = build_args(&main, &extras, &fragmented, &sources, None, &metadata).unwrap();
+        assert_eq!(args.iter().filter(|a| *a == "tee").count(), 3);
+        assert_eq!(args.iter().filter(|a| *a == "title=Demo").count(), 3);
+        assert!(args.contains(&"[f=mp4:movflags=+frag_keyframe+empty_moov+use_metadata_tags]/v/hq.mp4".to_string()));
+        assert!(args.contains(&"libvpx-vp9".to_string()));
+        assert!(args.contains(&"[f=mpegts:onfail=ignore]srt://10.0.0.2:9000?pkt_size=1316".to_string()));
+    }
+
+    #[test]
+    fn files_keep_the_chosen_codecs() {
+        let main = stream("rtmp://localhost/live/key", None);
+        let extras = [file("/v/archive.mkv", "mkv", None)];
+        let ffv1 = Encoding {
+            audio: AudioSettings { kbps: 256, ..AudioSettings::default() },
+            ..encoding(Some(VideoCodec::Ffv1))
+        };
+        let sources = ["0:v".to_string(), "0:v".to_string()];
+        let args = build_args(&main, &extras, &ffv1, &sources, Some("1:a"), &[]).unwrap().join(" ");
+        assert!(args.contains("-c:v ffv1 -level 3"));
+        // Lossless video gets FLAC in Matroska, the stream still gets AAC
+        assert!(args.contains("-c:a flac"));
+        assert!(args.contains("-c:a aac"));
+
+        let aac_256 = Encoding { audio: AudioSettings { kbps: 256, ..AudioSettings::default() }, ..encoding(None) };
+        assert_eq!(encode_count(&main, &extras, &aac_256, true), Ok(2));
+        let args = build_args(&main, &extras, &aac_256, &sources, Some("1:a"), &[]).unwrap().join(" ");
+        assert!(args.contains("-c:a aac -b:a 256k"));
+
+        // Pairs the container can't hold, and formats tee can't write
+        assert!(encode_count(&main, &[file("/v/a.mp4", "mp4", None)], &encoding(Some(VideoCodec::ProRes)), false).is_err());
+        assert!(encode_count(&main, &[file("/v/a.gif", "gif", None)], &encoding(None), false).is_err());
+        assert!(encode_count(&main, &[file("/v/a.mkv", "mkv", Some(8000))], &encoding(Some(VideoCodec::Ffv1)), false).is_err());
+    }
+
+    #[test]
+    fn hls_options_are_escaped() {
+        let main = file("/v/clip.mp4", "mp4", None);
+        let extras = [stream("hls:/srv/live", None)];
+        let args = build_args(&main, &extras, &encoding(None), &["0:v".to_string()], None, &[]).unwrap();
+        let slaves = args.last().unwrap();
+        assert!(slaves.contains("[f=hls:hls_time=2:hls_list_size=6:"));
+        assert!(slaves.ends_with(":onfail=ignore]/srv/live/index.m3u8"));
+        assert_eq!(first_file(&main, &extras), Some(PathBuf::from("/v/clip.mp4")));
+        assert_eq!(first_file(&extras[0], &[main]), Some(PathBuf::from("/v/clip.mp4")));
+        assert_eq!(first_file(&extras[0], &[]), Some(PathBuf::from("/srv/live/index.m3u8")));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::recorder::{self, RecordingConfig};
+use std::io::Read;
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 180;
+
+// Enough to see what is on screen, without competing with the rest of the
+// desktop for CPU
+const FPS: u32 = 4;
+
+/// The filter graph that scales the (composed) inputs down to the preview
+/// size, letterboxed so the aspect ratio stays right.
+fn filter_graph(compose: Option<&str>) -> String {
+    let scale = format!(
+        "fps={fps},scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+        fps = FPS,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    match compose {
+        Some(compose) => format!("{},{}", compose, scale),
+        None => format!("[0:v]{}", scale),
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    frame: Option<Vec<u8>>, // Newest RGBA frame not picked up yet
+    problem: Option<String>, // Why ffmpeg stopped
+}
+
+/// Low-rate thumbnails of what a recording with the same config would show,
+/// read from an ffmpeg rawvideo pipe. Stopped when dropped, so the devices are
+/// free again for the recording.
+pub struct Preview {
+    child: Child,
+    shared: Arc<Mutex<Shared>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Preview {
+    /// `on_frame` is called from another thread for every new frame.
+    pub fn start(config: &RecordingConfig, on_frame: impl Fn() + Send + 'static) -> Result<Self, String> {
+        let (mut cmd, compose) = recorder::video_inputs(config, FPS)?;
+        cmd.arg("-v").arg("error")
+           .arg("-filter_complex").arg(filter_graph(compose))
+           .arg("-an")
+           .arg("-f").arg("rawvideo")
+           .arg("-pix_fmt").arg("rgba")
+           .arg("-")
+           .stdin(Stdio::null())
+           .stdout(Stdio::piped())
+           .stderr(Stdio::piped());
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+
+        let mut stdout = child.stdout.take().ok_or("No output from ffmpeg")?;
+        let mut stderr = child.stderr.take().ok_or("No output from ffmpeg")?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let frames = shared.clone();
+        let reader = std::thread::spawn(move || {
+            let mut buffer = vec![0u8; WIDTH * HEIGHT * 4];
+            while stdout.read_exact(&mut buffer).is_ok() {
+                frames.lock().unwrap().frame = Some(buffer.clone());
+                on_frame();
+            }
+            // Only says something if ffmpeg gave up on its own
+            let mut log = String::new();
+            let _ = stderr.read_to_string(&mut log);
+            if let Some(line) = log.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+                frames.lock().unwrap().problem = Some(line.to_string());
+                on_frame();
+            }
+        });
+        Ok(Self { child, shared, reader: Some(reader) })
+    }
+
+    /// The newest frame since the last call, `WIDTH` x `HEIGHT` RGBA.
+    pub fn take_frame(&self) -> Option<Vec<u8>> {
+        self.shared.lock().unwrap().frame.take()
+    }
+
+    pub fn problem(&self) -> Option<String> {
+        self.shared.lock().unwrap().problem.clone()
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_list::{DeviceProvider, MockProvider};
+    use crate::recorder::RecordingMode;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn pip_is_composed_before_scaling() {
+        assert!(filter_graph(None).starts_with("[0:v]fps=4,scale=320:180:"));
+        assert!(filter_graph(Some("[0:v][1:v] overlay=main_w-overlay_w-10:10"))
+            .starts_with("[0:v][1:v] overlay=main_w-overlay_w-10:10,fps=4,scale="));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn previews_the_camera() {
+        let config = RecordingConfig {
+            mode: RecordingMode::Camera,
+            camera_device: MockProvider::default().video_devices().into_iter().next(),
+            ..RecordingConfig::default()
+        };
+        let preview = Preview::start(&config, || {}).unwrap();
+        let started = Instant::now();
+        let frame = loop {
+            if let Some(frame) = preview.take_frame() {
+                break frame;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "no frame: {:?}", preview.problem());
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(frame.len(), WIDTH * HEIGHT * 4);
+    }
+}
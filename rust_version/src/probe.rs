@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::process::Command;
+
+/// What ffprobe says about a recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaInfo {
+    pub duration: f64, // Seconds
+    pub video_codec: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub pix_fmt: Option<String>,
+    pub video_kbps: Option<u32>, // Not every container knows
+    pub audio_codec: Option<String>,
+    pub audio_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+impl MediaInfo {
+    pub fn has_audio(&self) -> bool {
+        self.audio_codec.is_some()
+    }
+}
+
+pub fn probe(path: &Path) -> Result<MediaInfo, String> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration:stream=codec_type,codec_name,width,height,pix_fmt,bit_rate,sample_rate,channels"])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to start ffprobe: {}", e))?;
+    if !out.status.success() {
+        return Err(format!("ffprobe failed on {:?}: {}", path, String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(parse_media_info(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Parses ffprobe's default output: `[STREAM]`/`[FORMAT]` sections of
+/// `key=value` lines. Only the first video and audio stream count.
+pub fn parse_media_info(text: &str) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    let mut section: Vec<(&str, &str)> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line == "[/STREAM]" {
+            let get = |key| section.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+            let number = |key| get(key).and_then(|v: &str| v.parse::<u32>().ok()); // "N/A" is None
+            let kbps = number("bit_rate").map(|bps| bps / 1000);
+            let codec = get("codec_name").map(str::to_string);
+            match get("codec_type") {
+                Some("video") if info.video_codec.is_none() => {
+                    info.video_codec = codec;
+                    info.width = number("width").unwrap_or(0);
+                    info.height = number("height").unwrap_or(0);
+                    info.pix_fmt = get("pix_fmt").map(str::to_string);
+                    info.video_kbps = kbps;
+                }
+                Some("audio") if info.audio_codec.is_none() => {
+                    info.audio_codec = codec;
+                    info.audio_kbps = kbps;
+                    info.sample_rate = number("sample_rate");
+                    info.channels = number("channels");
+                }
+                _ => {}
+            }
+            section.clear();
+        } else if let Some(duration) = line.strip_prefix("duration=") {
+            info.duration = duration.parse().unwrap_or(0.0); // "N/A" while still being written
+        } else if let Some(pair) = line.split_once('=') {
+            section.push(pair);
+        }
+    }
+    info
+}
+
+/// Timestamps of the video keyframes, in seconds.
+pub fn keyframes(path: &Path) -> Result<Vec<f64>, String> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-skip_frame", "nokey",
+               "-show_entries", "frame=pts_time", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to start ffprobe: {}", e))?;
+    if !out.status.success() {
+        return Err(format!("ffprobe failed on {:?}: {}", path, String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(parse_keyframes(&String::from_utf8_lossy(&out.stdout)))
+}
+
+pub fn parse_keyframes(text: &str) -> Vec<f64> {
+    text.lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_streams_and_duration() {
+        let text = "[STREAM]\ncodec_name=h264\ncodec_type=video\nwidth=1920\nheight=1080\npix_fmt=yuv420p\nbit_rate=N/A\n[/STREAM]\n\
+                    [STREAM]\ncodec_name=opus\ncodec_type=audio\nsample_rate=48000\nchannels=2\nbit_rate=96000\n[/STREAM]\n\
+                    [FORMAT]\nduration=12.480000\n[/FORMAT]\n";
+        let info = parse_media_info(text);
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!(info.pix_fmt.as_deref(), Some("yuv420p"));
+        assert_eq!(info.video_kbps, None);
+        assert_eq!(info.audio_codec.as_deref(), Some("opus"));
+        assert_eq!((info.audio_kbps, info.sample_rate, info.channels), (Some(96), Some(48000), Some(2)));
+        assert_eq!(info.duration, 12.48);
+
+        let audio_only = parse_media_info("[STREAM]\ncodec_name=flac\ncodec_type=audio\n[/STREAM]\n[FORMAT]\nduration=N/A\n[/FORMAT]\n");
+        assert!(audio_only.video_codec.is_none());
+        assert_eq!(audio_only.duration, 0.0);
+    }
+
+    #[test]
+    fn parses_keyframe_times() {
+        assert_eq!(parse_keyframes("0.000000\n1.000000,\n2.033333\n\nN/A\n"), vec![0.0, 1.0, 2.033333]);
+    }
+}
@@ -1,14 +1,40 @@
+use crate::animated::{self, AnimatedFormat, AnimatedOptions};
+use crate::chapters::{self, Marker};
+use crate::codec::{AudioSettings, VideoCodec};
+use crate::device_list::{Backend, Device};
+use crate::limits::{self, IdleWatch, Progress, StopLimits, StopReason};
+use crate::metadata::{self, Metadata};
+use crate::outputs::{self, Encoding, OutputSpec, OutputTarget};
+use crate::recovery::{self, CrashSafety};
+use crate::replay;
+use crate::segment::{self, Segmentation};
+use crate::stats::{self, Stats};
+use crate::stream::{self, StreamConfig};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
 use std::time::{Duration, Instant};
-use std::path::PathBuf;
 #[cfg(target_os = "windows")]
 use std::io::Write; // Needed for writing to stdin
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RecordingMode {
     Screen,
     Camera,
     PiP, // Screen + Camera
+    AudioOnly,
+}
+
+impl RecordingMode {
+    /// Short lowercase name, used in file names.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordingMode::Screen => "screen",
+            RecordingMode::Camera => "camera",
+            RecordingMode::PiP => "pip",
+            RecordingMode::AudioOnly => "audio",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -19,158 +45,311 @@ pub struct RecordingConfig {
     pub x: i32,
     pub y: i32,
     pub mode: RecordingMode,
-    pub camera_device: String,
-    pub audio_enabled: bool,
-    pub audio_device: String, // e.g., "default" or "Microphone (Realtek Audio)"
-    pub container_format: String, // "mp4", "webm"
+    pub camera_device: Option<Device>,
+    pub audio_device: Option<Device>, // None records without audio
+    pub audio: AudioSettings, // File recordings only, streams use their own
+    pub container_format: String, // codec::CONTAINERS, codec::AUDIO_CONTAINERS, or "gif"/"webp" exported on stop
+    pub video_codec: Option<VideoCodec>, // None picks the container's usual one
+    pub overwrite: bool, // Otherwise ffmpeg refuses to touch an existing output
+    pub crash_safety: CrashSafety,
+    pub remux_on_stop: bool, // Turn the crash-safe file into a regular one on stop
+    pub segmentation: Segmentation,
+    pub replay_seconds: Option<u32>, // Replay buffer: only keep the last N seconds
+    pub stream: Option<StreamConfig>, // Stream live instead of writing output_path
+    pub extra_outputs: Vec<OutputSpec>, // Written at the same time as the main output
+    pub animated: AnimatedOptions, // For "gif" and "webp"
+    pub metadata: Metadata,
+    pub monitor: Option<String>, // Name of the captured monitor, only for the metadata
+    pub limits: StopLimits, // Checked with `Recorder::check_limits`
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::from("recording.mp4"),
+            width: 1920,
+            height: 1080,
+            x: 0,
+            y: 0,
+            mode: RecordingMode::Screen,
+            camera_device: None,
+            audio_device: None,
+            audio: AudioSettings::default(),
+            container_format: "mp4".to_string(),
+            video_codec: None,
+            overwrite: false,
+            crash_safety: CrashSafety::Off,
+            remux_on_stop: true,
+            segmentation: Segmentation::Off,
+            replay_seconds: None,
+            stream: None,
+            extra_outputs: Vec::new(),
+            animated: AnimatedOptions::default(),
+            metadata: Metadata::default(),
+            monitor: None,
+            limits: StopLimits::default(),
+        }
+    }
+}
+
+const PIP_OVERLAY: &str = "[0:v][1:v] overlay=main_w-overlay_w-10:10";
+
+// Where the running recording goes, and what to do with it once stopped
+struct ActiveOutput {
+    writing_to: PathBuf,
+    output_path: PathBuf,
+    crash_safety: CrashSafety,
+    remux_on_stop: bool,
+    overwrite: bool,
+    segment_pattern: Option<PathBuf>,
+    replay: Option<ReplayRing>,
+    streaming: bool,
+    animated: Option<(AnimatedFormat, AnimatedOptions)>, // Export writing_to on stop
+}
+
+// Work left once ffmpeg has exited, done on a worker thread so stopping
+// doesn't wait for it
+struct Finishing {
+    step: &'static str, // Shown while it runs, e.g. "Exporting the animation"
+    handle: std::thread::JoinHandle<Result<PathBuf, String>>,
+}
+
+// The rolling segments of a replay buffer
+struct ReplayRing {
+    dir: std::sync::Arc<replay::RingDir>,
+    ext: &'static str,
+    keep_seconds: u32,
 }
 
 pub struct Recorder {
     child: Option<Child>,
+    active_output: Option<ActiveOutput>,
+    last_output: Option<PathBuf>,
+    segment_checked: Option<(Instant, u32)>,
     start_time: Option<Instant>,
     paused_duration: Duration,
     last_pause_time: Option<Instant>,
+    markers: Vec<Marker>,
+    limits: StopLimits,
+    limits_checked: Option<Instant>,
+    idle_watch: Option<IdleWatch>,
+    stats: Option<stats::Latest>,
+    log: Option<std::thread::JoinHandle<Option<String>>>, // Gives the last line ffmpeg wrote once it exits
+    finishing: Vec<Finishing>,
 }
 
-pub fn get_audio_devices() -> Vec<String> {
-    let mut devices = Vec::new();
-
+fn add_screen_input(cmd: &mut Command, config: &RecordingConfig, framerate: u32) {
     #[cfg(target_os = "windows")]
     {
-        // Run ffmpeg -list_devices true -f dshow -i dummy
-        // The output is in stderr.
-        let output = Command::new("ffmpeg")
-            .arg("-list_devices").arg("true")
-            .arg("-f").arg("dshow")
-            .arg("-i").arg("dummy")
-            .output();
-
-        if let Ok(output) = output {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let mut in_audio_section = false;
-            for line in stderr.lines() {
-                if line.contains("DirectShow video devices") {
-                    in_audio_section = false;
-                } else if line.contains("DirectShow audio devices") {
-                    in_audio_section = true;
-                } else if in_audio_section {
-                    // Lines look like:
-                    // [dshow @ ...]  "Microphone (Realtek Audio)"
-                    // [dshow @ ...]     Alternative name "..."
-                    if let Some(start_quote) = line.find('"') {
-                        if let Some(end_quote) = line[start_quote+1..].find('"') {
-                             let device_name = &line[start_quote+1..start_quote+1+end_quote];
-                             // Avoid "Alternative name" lines usually
-                             if !line.contains("Alternative name") {
-                                 devices.push(device_name.to_string());
-                             }
-                        }
-                    }
-                }
+        // Windows: gdigrab
+        cmd.arg("-f").arg("gdigrab")
+           .arg("-framerate").arg(framerate.to_string())
+           .arg("-offset_x").arg(config.x.to_string())
+           .arg("-offset_y").arg(config.y.to_string())
+           .arg("-video_size").arg(format!("{}x{}", config.width, config.height))
+           .arg("-i").arg("desktop");
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        // Linux: x11grab (Assuming X11)
+        cmd.arg("-f").arg("x11grab")
+           .arg("-video_size").arg(format!("{}x{}", config.width, config.height))
+           .arg("-framerate").arg(framerate.to_string())
+           .arg("-i").arg(format!(":0.0+{},{}", config.x, config.y));
+    }
+}
+
+fn add_camera_input(cmd: &mut Command, device: &Device, size: Option<&str>) {
+    match device.backend {
+        Backend::Dshow => {
+            cmd.arg("-f").arg("dshow");
+            if let Some(size) = size {
+                cmd.arg("-video_size").arg(size);
             }
+            cmd.arg("-i").arg(format!("video={}", device.id));
+        }
+        Backend::Lavfi => {
+            cmd.arg("-f").arg("lavfi")
+               .arg("-i").arg(&device.id);
+        }
+        _ => {
+            cmd.arg("-f").arg(device.backend.ffmpeg_format())
+               .arg("-framerate").arg("30")
+               .arg("-video_size").arg(size.unwrap_or("640x480")) // Default safe resolution
+               .arg("-i").arg(&device.id);
         }
     }
+}
 
-    #[cfg(not(target_os = "windows"))]
-    {
-        // Linux: for now just return "default" and maybe some common ones
-        devices.push("default".to_string());
-        devices.push("hw:0,0".to_string());
+fn add_audio_input(cmd: &mut Command, device: &Device) {
+    match device.backend {
+        Backend::Dshow => {
+            cmd.arg("-f").arg("dshow")
+               .arg("-i").arg(format!("audio={}", device.id));
+        }
+        backend => {
+            cmd.arg("-f").arg(backend.ffmpeg_format())
+               .arg("-i").arg(&device.id);
+        }
+    }
+}
+
+/// The video inputs of `config`, with the screen captured at `framerate`, and
+/// the filter that composes them into the recorded picture (the PiP overlay)
+/// if there is one. Used by the preview, which must look like the recording.
+pub fn video_inputs(config: &RecordingConfig, framerate: u32) -> Result<(Command, Option<&'static str>), String> {
+    let mut cmd = Command::new("ffmpeg");
+    match config.mode {
+        RecordingMode::Screen => add_screen_input(&mut cmd, config, framerate),
+        RecordingMode::Camera => {
+            add_camera_input(&mut cmd, config.camera_device.as_ref().ok_or("No camera selected")?, None);
+        }
+        RecordingMode::PiP => {
+            add_screen_input(&mut cmd, config, framerate);
+            add_camera_input(&mut cmd, config.camera_device.as_ref().ok_or("No camera selected")?, Some("320x240"));
+            return Ok((cmd, Some(PIP_OVERLAY)));
+        }
+        RecordingMode::AudioOnly => return Err("Audio-only recordings have no picture".to_string()),
+    }
+    Ok((cmd, None))
+}
+
+// -movflags for an MP4-style output, if it needs any
+fn movflags(fragmented: bool, output: &std::path::Path) -> Option<String> {
+    let mut flags = String::new();
+    if fragmented {
+        // Keep the file playable at every keyframe, no moov atom needed at the end
+        flags.push_str("+frag_keyframe+empty_moov");
     }
+    if metadata::needs_mov_flag(output) {
+        flags.push_str(metadata::MOV_FLAG);
+    }
+    (!flags.is_empty()).then_some(flags)
+}
 
-    if devices.is_empty() {
-        devices.push("default".to_string());
+// Size of what has been recorded so far. The replay ring keeps its own size.
+fn written_bytes(output: &ActiveOutput) -> Option<u64> {
+    if output.replay.is_some() {
+        return None;
+    }
+    match &output.segment_pattern {
+        Some(pattern) => Some(
+            (0..)
+                .map(|i| segment::segment_path(pattern, i))
+                .map_while(|path| std::fs::metadata(path).ok())
+                .map(|m| m.len())
+                .sum(),
+        ),
+        None => std::fs::metadata(&output.writing_to).ok().map(|m| m.len()),
     }
+}
 
-    devices
+// Chapters are a bonus, a recording that can't take them is still saved
+fn save_markers(recording: &std::path::Path, markers: &[Marker], duration: f64) {
+    if let Err(e) = chapters::save(recording, markers, duration) {
+        eprintln!("Markers of {:?} not saved: {}", recording, e);
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Recorder {
     pub fn new() -> Self {
         Self {
             child: None,
+            active_output: None,
+            last_output: None,
+            segment_checked: None,
             start_time: None,
             paused_duration: Duration::new(0, 0),
             last_pause_time: None,
+            markers: Vec::new(),
+            limits: StopLimits::default(),
+            limits_checked: None,
+            idle_watch: None,
+            stats: None,
+            log: None,
+            finishing: Vec::new(),
         }
     }
 
     pub fn start(&mut self, config: &RecordingConfig) -> Result<(), String> {
+        self.start_capture(config)?;
+        self.limits = config.limits;
+        self.limits_checked = None;
+        if let Some(minutes) = config.limits.idle_minutes
+            && matches!(config.mode, RecordingMode::Screen | RecordingMode::PiP)
+        {
+            let config = config.clone();
+            let input = move || {
+                let mut input = Command::new("ffmpeg");
+                input.arg("-hide_banner").arg("-nostats");
+                add_screen_input(&mut input, &config, 30);
+                input
+            };
+            // Without it the recording just runs until another limit or Stop
+            self.idle_watch = IdleWatch::start(input, minutes)
+                .map_err(|e| eprintln!("Idle detection not running: {}", e))
+                .ok();
+        }
+        Ok(())
+    }
+
+    fn start_capture(&mut self, config: &RecordingConfig) -> Result<(), String> {
         if self.child.is_some() {
             return Err("Already recording".to_string());
         }
 
+        let animated_format = AnimatedFormat::from_container(&config.container_format);
+        if animated_format.is_some()
+            && (config.stream.is_some() || config.replay_seconds.is_some()
+                || config.segmentation.is_enabled() || !config.extra_outputs.is_empty())
+        {
+            return Err("GIF and WebP can't be streamed, split or used as a replay buffer".to_string());
+        }
+        let audio_only = config.mode == RecordingMode::AudioOnly;
+        if audio_only {
+            if config.audio_device.is_none() {
+                return Err("No audio device selected".to_string());
+            }
+            if config.stream.is_some() || !config.extra_outputs.is_empty() || animated_format.is_some() {
+                return Err("Audio-only recordings can only go to a file".to_string());
+            }
+        }
+
         let mut cmd = Command::new("ffmpeg");
+        // Frame rate and size for the remote status, read in `spawn`
+        cmd.arg("-progress").arg("pipe:1");
 
         // --- Input 1: Desktop / Primary Video Source ---
         match config.mode {
-            RecordingMode::Screen | RecordingMode::PiP => {
-                #[cfg(target_os = "windows")]
-                {
-                    // Windows: gdigrab
-                    cmd.arg("-f").arg("gdigrab")
-                       .arg("-framerate").arg("30")
-                       .arg("-offset_x").arg(config.x.to_string())
-                       .arg("-offset_y").arg(config.y.to_string())
-                       .arg("-video_size").arg(format!("{}x{}", config.width, config.height))
-                       .arg("-i").arg("desktop");
-                }
-                #[cfg(not(target_os = "windows"))]
-                {
-                     // Linux: x11grab (Assuming X11)
-                    cmd.arg("-f").arg("x11grab")
-                       .arg("-video_size").arg(format!("{}x{}", config.width, config.height))
-                       .arg("-framerate").arg("30")
-                       .arg("-i").arg(format!(":0.0+{},{}", config.x, config.y));
-                }
-            },
+            RecordingMode::Screen | RecordingMode::PiP => add_screen_input(&mut cmd, config, 30),
             RecordingMode::Camera => {
                 // If Camera only mode, the camera is the primary input [0:v]
-                #[cfg(target_os = "windows")]
-                {
-                    cmd.arg("-f").arg("dshow")
-                       .arg("-i").arg(format!("video={}", config.camera_device));
-                }
-                #[cfg(not(target_os = "windows"))]
-                {
-                    cmd.arg("-f").arg("v4l2")
-                       .arg("-framerate").arg("30")
-                       .arg("-video_size").arg("640x480") // Default safe resolution
-                       .arg("-i").arg(&config.camera_device);
-                }
+                let camera = config.camera_device.as_ref().ok_or("No camera selected")?;
+                add_camera_input(&mut cmd, camera, None);
             }
+            RecordingMode::AudioOnly => {} // The audio device is the only input
         }
 
         // --- Input 2: Camera (Only for PiP) ---
         if config.mode == RecordingMode::PiP {
-             #[cfg(target_os = "windows")]
-             {
-                 cmd.arg("-f").arg("dshow")
-                    .arg("-video_size").arg("320x240") // Fixed small size for PiP
-                    .arg("-i").arg(format!("video={}", config.camera_device));
-             }
-             #[cfg(not(target_os = "windows"))]
-             {
-                 cmd.arg("-f").arg("v4l2")
-                    .arg("-framerate").arg("30")
-                    .arg("-video_size").arg("320x240")
-                    .arg("-i").arg(&config.camera_device);
-             }
+            let camera = config.camera_device.as_ref().ok_or("No camera selected")?;
+            add_camera_input(&mut cmd, camera, Some("320x240")); // Fixed small size for PiP
         }
 
         // --- Input 3 (or 2): Audio ---
-        if config.audio_enabled {
-            #[cfg(target_os = "windows")]
-            {
-                cmd.arg("-f").arg("dshow")
-                   .arg("-i").arg(format!("audio={}", config.audio_device));
-            }
-            #[cfg(not(target_os = "windows"))]
-            {
-                cmd.arg("-f").arg("alsa")
-                   .arg("-i").arg(&config.audio_device);
-            }
-            cmd.arg("-ac").arg("2");
+        if let Some(audio) = &config.audio_device {
+            add_audio_input(&mut cmd, audio);
+        }
+
+        // Several outputs at once go through tee, see outputs.rs
+        if !config.extra_outputs.is_empty() {
+            return self.start_multi(cmd, config);
         }
 
         // --- Filter Complex (For PiP) ---
@@ -178,50 +357,255 @@ impl Recorder {
             // [0:v] is desktop, [1:v] is camera
             // Overlay camera on desktop at top right with 10px padding
             // main_w - overlay_w - 10 : 10
-            cmd.arg("-filter_complex").arg("[0:v][1:v] overlay=main_w-overlay_w-10:10");
+            cmd.arg("-filter_complex").arg(PIP_OVERLAY);
+        }
+
+        // Streaming replaces the file output, with encoder settings made for it
+        if let Some(stream) = &config.stream {
+            stream.target.prepare()?;
+            cmd.args(stream.encoder_args(config.audio_device.is_some()));
+            cmd.args(stream.target.output_args());
+            return self.spawn(cmd, ActiveOutput {
+                writing_to: stream.target.playlist().unwrap_or_default(),
+                output_path: config.output_path.clone(),
+                crash_safety: CrashSafety::Off,
+                remux_on_stop: false,
+                overwrite: false,
+                segment_pattern: None,
+                replay: None,
+                streaming: true,
+                animated: None,
+            });
+        }
+
+        // GIF and WebP are made from a near-lossless capture once stopped
+        if let Some(format) = animated_format {
+            if config.output_path.exists() && !config.overwrite {
+                return Err(format!("{:?} already exists", config.output_path));
+            }
+            let capture = config.output_path.with_extension("capture.mkv");
+            cmd.arg("-an") // Neither format has sound
+               .arg("-c:v").arg("libx264")
+               .arg("-preset").arg("ultrafast")
+               .arg("-crf").arg("10")
+               .arg("-pix_fmt").arg("yuv444p")
+               .arg("-y").arg(&capture);
+            return self.spawn(cmd, ActiveOutput {
+                writing_to: capture,
+                output_path: config.output_path.clone(),
+                crash_safety: CrashSafety::Off,
+                remux_on_stop: false,
+                overwrite: config.overwrite,
+                segment_pattern: None,
+                replay: None,
+                streaming: false,
+                animated: Some((format, config.animated.clone())),
+            });
         }
 
         // Encoding options
-        // Use libx264 for mp4, libvpx-vp9 for webm
-        match config.container_format.as_str() {
-            "webm" => {
-                cmd.arg("-c:v").arg("libvpx-vp9")
-                   .arg("-b:v").arg("2M"); // basic bitrate
+        let codec = config.video_codec.unwrap_or_else(|| VideoCodec::default_for(&config.container_format));
+        if !audio_only {
+            codec.check_container(&config.container_format)?;
+            cmd.args(codec.encoder_args(&config.container_format));
+        }
+        if config.audio_device.is_some() {
+            config.audio.validate(&config.container_format, codec)?;
+            cmd.args(config.audio.encoder_args(&config.container_format, codec));
+        }
+        let split_by_size = matches!(config.segmentation, Segmentation::Megabytes(_)) && config.replay_seconds.is_none();
+        let video_kbps = if audio_only { 0 } else { codec.nominal_kbps() };
+        if split_by_size && !audio_only && !codec.rate_controlled() {
+            // Nothing bounds the size of a segment of a fixed length
+            return Err(format!("{} has no bitrate cap to split by size with, split by time instead", codec.label()));
+        }
+        if split_by_size && !audio_only {
+            // Cap the bitrate so that segments of a fixed length stay under the size
+            cmd.arg("-maxrate").arg(format!("{}k", video_kbps))
+               .arg("-bufsize").arg(format!("{}k", video_kbps * 2));
+        }
+
+        let audio_kbps = match config.audio_device {
+            Some(_) => config.audio.nominal_kbps(&config.container_format, codec),
+            None => 0,
+        };
+        let total_kbps = video_kbps + audio_kbps;
+        let mut replay_ring = None;
+        let writing_to = if let Some(keep_seconds) = config.replay_seconds {
+            // Short segments in a ring that wraps around, see replay.rs
+            let dir = replay::RingDir::create()?;
+            let (format, ext) = replay::segment_format(
+                codec,
+                config.audio_device.as_ref().map(|_| config.audio.codec_for(&config.container_format, codec)),
+            );
+            cmd.arg("-force_key_frames").arg(format!("expr:gte(t,n_forced*{})", replay::SEGMENT_SECONDS))
+               .arg("-f").arg("segment")
+               .arg("-segment_format").arg(format)
+               .arg("-segment_time").arg(replay::SEGMENT_SECONDS.to_string())
+               .arg("-segment_wrap").arg(replay::ring_size(keep_seconds).to_string())
+               .arg("-reset_timestamps").arg("1");
+            let pattern = dir.path().join(format!("replay_%03d.{}", ext));
+            replay_ring = Some(ReplayRing { dir, ext, keep_seconds });
+            pattern
+        } else if let Some(seconds) = config.segmentation.segment_seconds(total_kbps) {
+            // Segments are closed as they go, so a crash only loses the current one
+            let pattern = segment::segment_pattern(&config.output_path);
+            cmd.arg("-force_key_frames").arg(format!("expr:gte(t,n_forced*{})", seconds))
+               .arg("-f").arg("segment")
+               .arg("-segment_time").arg(seconds.to_string())
+               .arg("-reset_timestamps").arg("1")
+               .arg("-segment_list").arg(segment::segment_list_path(&config.output_path))
+               .arg("-segment_list_type").arg("m3u8");
+            let fragmented = config.crash_safety == CrashSafety::FragmentedMp4 && config.container_format == "mp4";
+            if let Some(flags) = movflags(fragmented, &config.output_path) {
+                cmd.arg("-segment_format_options").arg(format!("movflags={}", flags));
+            }
+            pattern
+        } else {
+            // Crash-safe modes write to a partial file that is finalized on stop
+            let writing_to = match config.crash_safety {
+                CrashSafety::Off => config.output_path.clone(),
+                mode => recovery::partial_path_for(&config.output_path, mode),
+            };
+            let fragmented = config.crash_safety == CrashSafety::FragmentedMp4 && writing_to.extension().is_some_and(|e| e == "mp4");
+            if let Some(flags) = movflags(fragmented, &writing_to) {
+                cmd.arg("-movflags").arg(flags);
             }
-            _ => { // default mp4
-                cmd.arg("-c:v").arg("libx264")
-                   .arg("-preset").arg("ultrafast") // fast encoding for real-time
-                   .arg("-crf").arg("23");
+            writing_to
+        };
+
+        // Never clobber an earlier recording unless asked to. The replay ring
+        // is ours and gets overwritten all the time anyway.
+        let overwrite = config.overwrite || replay_ring.is_some();
+        cmd.args(metadata::args(config));
+        cmd.arg(if overwrite { "-y" } else { "-n" }).arg(&writing_to);
 
-                // Ensure pixel format is valid (yuv420p is safe)
-                cmd.arg("-pix_fmt").arg("yuv420p");
+        let segmented = config.segmentation.is_enabled() && replay_ring.is_none();
+        let buffering = replay_ring.is_some();
+        self.spawn(cmd, ActiveOutput {
+            replay: replay_ring,
+            segment_pattern: segmented.then(|| writing_to.clone()),
+            writing_to,
+            output_path: config.output_path.clone(),
+            // Segments need no finalizing
+            crash_safety: if segmented || buffering { CrashSafety::Off } else { config.crash_safety },
+            remux_on_stop: config.remux_on_stop,
+            overwrite: config.overwrite,
+            streaming: false,
+            animated: None,
+        })
+    }
+
+    // The main output (stream or file) plus `extra_outputs`, sharing encodes
+    // where they can. Crash safety only means fragmented MP4 here, and
+    // splitting or the replay buffer are not available.
+    fn start_multi(&mut self, mut cmd: Command, config: &RecordingConfig) -> Result<(), String> {
+        let main = match &config.stream {
+            Some(stream) => OutputSpec {
+                target: OutputTarget::Stream(stream.target.clone()),
+                video_kbps: Some(stream.video_kbps),
+            },
+            None => OutputSpec {
+                target: OutputTarget::File {
+                    path: config.output_path.clone(),
+                    format: config.container_format.clone(),
+                },
+                video_kbps: None,
+            },
+        };
+        for spec in std::iter::once(&main).chain(&config.extra_outputs) {
+            match &spec.target {
+                OutputTarget::Stream(target) => target.prepare()?,
+                // tee has no -n of its own
+                OutputTarget::File { path, .. } if path.exists() && !config.overwrite => {
+                    return Err(format!("{:?} already exists", path));
+                }
+                OutputTarget::File { .. } => {}
             }
         }
 
-        // Overwrite output
-        cmd.arg("-y").arg(&config.output_path);
+        let encoding = Encoding {
+            video_codec: config.video_codec,
+            audio: config.audio,
+            keyframe_seconds: config.stream.as_ref()
+                .map(|s| s.keyframe_seconds)
+                .unwrap_or(stream::DEFAULT_KEYFRAME_SECONDS),
+            fragmented_files: config.crash_safety == CrashSafety::FragmentedMp4,
+        };
+        // Every encode needs its own copy of the video
+        let encodes = outputs::encode_count(&main, &config.extra_outputs, &encoding, config.audio_device.is_some())?;
+        let video_sources: Vec<String> = if config.mode == RecordingMode::PiP {
+            let labels: Vec<String> = (0..encodes).map(|i| format!("[v{}]", i)).collect();
+            cmd.arg("-filter_complex").arg(format!("{},split={}{}", PIP_OVERLAY, encodes, labels.concat()));
+            labels
+        } else {
+            vec!["0:v".to_string(); encodes]
+        };
+        let audio_source = config.audio_device.as_ref().map(|_| {
+            if config.mode == RecordingMode::PiP { "2:a" } else { "1:a" }
+        });
 
+        // tee writes whatever it is told to, files were checked above
+        cmd.arg("-y");
+        cmd.args(outputs::build_args(
+            &main,
+            &config.extra_outputs,
+            &encoding,
+            &video_sources,
+            audio_source,
+            &metadata::args(config),
+        )?);
+
+        self.spawn(cmd, ActiveOutput {
+            writing_to: outputs::first_file(&main, &config.extra_outputs).unwrap_or_default(),
+            output_path: config.output_path.clone(),
+            crash_safety: CrashSafety::Off,
+            remux_on_stop: false,
+            overwrite: config.overwrite,
+            segment_pattern: None,
+            replay: None,
+            streaming: config.stream.is_some(),
+            animated: None,
+        })
+    }
+
+    fn spawn(&mut self, mut cmd: Command, output: ActiveOutput) -> Result<(), String> {
         // Crucial for Windows stopping: We need to write to stdin.
         cmd.stdin(Stdio::piped());
 
-        // Use inherit so user sees ffmpeg logs in terminal
-        cmd.stdout(Stdio::null());
-        cmd.stderr(Stdio::inherit());
+        cmd.stdout(Stdio::piped()); // -progress reports
+        cmd.stderr(Stdio::piped()); // Still shown in the terminal, see stats::watch_log
 
-        let child = cmd.spawn().map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
 
+        self.stats = child.stdout.take().map(stats::watch);
+        self.log = child.stderr.take().map(stats::watch_log);
         self.child = Some(child);
+        self.active_output = Some(output);
+        self.segment_checked = None;
         self.start_time = Some(Instant::now());
         self.paused_duration = Duration::new(0, 0);
+        self.markers.clear();
         self.last_pause_time = None;
 
         Ok(())
     }
 
     pub fn stop(&mut self) -> Result<(), String> {
+        let duration = self.get_duration().as_secs_f64();
+        let markers = std::mem::take(&mut self.markers);
+        self.idle_watch = None;
+        self.stats = None;
+        self.log = None;
+        if self.is_paused() {
+            // A stopped ffmpeg can't act on SIGTERM and would be killed mid-file
+            let _ = self.resume();
+        }
         if let Some(mut child) = self.child.take() {
+            // Nothing to tell ffmpeg if it already exited on its own
+            let exited = matches!(child.try_wait(), Ok(Some(_)));
             #[cfg(target_os = "windows")]
-            {
+            if !exited {
                 // On Windows, killing the process corrupts the MP4.
                 // We must send 'q' to stdin.
                 if let Some(mut stdin) = child.stdin.take() {
@@ -243,7 +627,7 @@ impl Recorder {
             }
 
             #[cfg(not(target_os = "windows"))]
-            {
+            if !exited {
                 // Linux: SIGTERM is standard and works well.
                 let _ = Command::new("kill")
                     .arg("-SIGTERM")
@@ -265,11 +649,96 @@ impl Recorder {
 
             self.start_time = None;
             self.last_pause_time = None;
+
+            if let Some(output) = self.active_output.take() {
+                if output.replay.is_some() {
+                    // Whatever wasn't saved is gone on purpose, the ring is removed
+                    // once a save still copying from it is done
+                    return Ok(());
+                }
+                if output.streaming {
+                    // Only the local copy or HLS target leaves something to open, and
+                    // only the copy is a media file that can carry the markers
+                    if output.writing_to.extension().is_some_and(|e| e == "m3u8") {
+                        self.last_output = Some(output.writing_to);
+                    } else if !output.writing_to.as_os_str().is_empty() {
+                        self.keep_with_markers(output.writing_to, markers, duration);
+                    }
+                    return Ok(());
+                }
+                if let Some((format, options)) = output.animated {
+                    let (capture, target) = (output.writing_to, output.output_path);
+                    self.finish_in_background("Exporting the animation", move || {
+                        let exported = animated::export(&capture, &target, format, &options)
+                            .map_err(|e| format!("{} (capture kept as {:?})", e, capture))?;
+                        let _ = std::fs::remove_file(&capture);
+                        Ok(exported)
+                    });
+                    return Ok(());
+                }
+                if output.segment_pattern.is_none() && output.crash_safety != CrashSafety::Off {
+                    self.finish_in_background("Finalizing the recording", move || {
+                        let kept = recovery::finalize(&output.writing_to, &output.output_path, output.remux_on_stop, output.overwrite)
+                            .map_err(|e| format!("{} (recording kept as {:?})", e, output.writing_to))?;
+                        save_markers(&kept, &markers, duration);
+                        Ok(kept)
+                    });
+                    return Ok(());
+                }
+                let kept = match output.segment_pattern {
+                    Some(_) => segment::segment_list_path(&output.output_path),
+                    None => output.writing_to,
+                };
+                self.keep_with_markers(kept, markers, duration);
+            }
             return Ok(());
         }
         Err("Not recording".to_string())
     }
 
+    fn finish_in_background(&mut self, step: &'static str, work: impl FnOnce() -> Result<PathBuf, String> + Send + 'static) {
+        self.finishing.push(Finishing { step, handle: std::thread::spawn(work) });
+    }
+
+    // Embedding chapters means a remux, so that happens in the background too
+    fn keep_with_markers(&mut self, recording: PathBuf, markers: Vec<Marker>, duration: f64) {
+        if markers.is_empty() {
+            self.last_output = Some(recording);
+            return;
+        }
+        self.finish_in_background("Adding chapters", move || {
+            save_markers(&recording, &markers, duration);
+            Ok(recording)
+        });
+    }
+
+    /// What is still being done with a stopped recording, if anything.
+    pub fn finishing(&self) -> Option<&'static str> {
+        self.finishing.first().map(|f| f.step)
+    }
+
+    /// Results of the post-processing that ended since the last call. The
+    /// saved files become the last output.
+    pub fn take_finished(&mut self) -> Vec<Result<PathBuf, String>> {
+        let (done, running): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.finishing).into_iter().partition(|f| f.handle.is_finished());
+        self.finishing = running;
+        done.into_iter().map(|f| self.join(f)).collect()
+    }
+
+    /// Waits for all post-processing, e.g. before quitting.
+    pub fn wait_finishing(&mut self) -> Vec<Result<PathBuf, String>> {
+        std::mem::take(&mut self.finishing).into_iter().map(|f| self.join(f)).collect()
+    }
+
+    fn join(&mut self, finishing: Finishing) -> Result<PathBuf, String> {
+        let result = finishing.handle.join().unwrap_or_else(|_| Err(format!("{} failed", finishing.step)));
+        if let Ok(path) = &result {
+            self.last_output = Some(path.clone());
+        }
+        result
+    }
+
     pub fn pause(&mut self) -> Result<(), String> {
         #[cfg(target_os = "windows")]
         {
@@ -287,6 +756,9 @@ impl Recorder {
                     .arg("-SIGSTOP")
                     .arg(child.id().to_string())
                     .output();
+                if let Some(watch) = &mut self.idle_watch {
+                    watch.pause();
+                }
 
                 self.last_pause_time = Some(Instant::now());
                 Ok(())
@@ -310,6 +782,12 @@ impl Recorder {
                         .arg("-SIGCONT")
                         .arg(child.id().to_string())
                         .output();
+                    if let Some(watch) = &mut self.idle_watch
+                        && let Err(e) = watch.resume()
+                    {
+                        eprintln!("Idle detection not running: {}", e);
+                        self.idle_watch = None;
+                    }
 
                     self.paused_duration += pause_time.elapsed();
                     self.last_pause_time = None;
@@ -327,10 +805,107 @@ impl Recorder {
         self.child.is_some()
     }
 
+    /// Number of the segment being written (1-based) when splitting the output.
+    pub fn current_segment(&mut self) -> Option<u32> {
+        let pattern = self.active_output.as_ref()?.segment_pattern.as_ref()?;
+
+        // Looking at the disk every frame is wasteful, once a second is plenty
+        match self.segment_checked {
+            Some((checked, current)) if checked.elapsed() < Duration::from_secs(1) => Some(current),
+            _ => {
+                let current = segment::current_segment(pattern);
+                self.segment_checked = Some((Instant::now(), current));
+                Some(current)
+            }
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.active_output.as_ref().is_some_and(|o| o.streaming)
+    }
+
+    pub fn is_buffering_replay(&self) -> bool {
+        self.active_output.as_ref().is_some_and(|o| o.replay.is_some())
+    }
+
+    /// The last N seconds of the replay buffer, for `replay::save` to write
+    /// out off the UI thread. The capture keeps running.
+    pub fn replay_snapshot(&self) -> Result<replay::Snapshot, String> {
+        let ring = self.active_output.as_ref()
+            .and_then(|o| o.replay.as_ref())
+            .ok_or("Replay buffer is not running")?;
+        replay::snapshot(&ring.dir, ring.ext, ring.keep_seconds)
+    }
+
+    /// Makes a replay saved in the background the last output.
+    pub fn replay_saved(&mut self, path: PathBuf) {
+        self.last_output = Some(path);
+    }
+
+    /// The file the last stopped recording ended up in.
+    pub fn last_output(&self) -> Option<&std::path::Path> {
+        self.last_output.as_deref()
+    }
+
+    /// The newest progress ffmpeg reported for the running recording.
+    pub fn stats(&self) -> Option<Stats> {
+        *self.stats.as_ref()?.lock().unwrap()
+    }
+
     pub fn is_paused(&self) -> bool {
         self.last_pause_time.is_some()
     }
 
+    /// Marks the current point of the recording. Paused time doesn't count.
+    /// The markers end up as chapters and in a sidecar JSON when stopped.
+    pub fn add_marker(&mut self, label: Option<String>) -> Result<&Marker, String> {
+        if self.child.is_none() {
+            return Err("Not recording".to_string());
+        }
+        let label = label.filter(|l| !l.trim().is_empty())
+            .unwrap_or_else(|| format!("Marker {}", self.markers.len() + 1));
+        self.markers.push(Marker { seconds: self.get_duration().as_secs_f64(), label });
+        Ok(&self.markers[self.markers.len() - 1])
+    }
+
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Why ffmpeg ended on its own, if it did, e.g. a dropped stream or a
+    /// full disk, for the caller to stop the recording.
+    pub fn check_exited(&mut self) -> Option<String> {
+        let status = self.child.as_mut()?.try_wait().ok()??;
+        // The log is closed by now, so this doesn't wait
+        let last_line = self.log.take().and_then(|log| log.join().ok().flatten());
+        Some(match last_line {
+            Some(line) => format!("ffmpeg exited: {}", line),
+            None => format!("ffmpeg exited ({})", status),
+        })
+    }
+
+    /// The auto-stop limit the running recording has hit, if any, for the
+    /// caller to stop it. Looks at the disk at most once a second.
+    pub fn check_limits(&mut self) -> Option<StopReason> {
+        if !self.limits.is_enabled() || self.limits_checked.is_some_and(|t| t.elapsed() < Duration::from_secs(1)) {
+            return None;
+        }
+        let output = self.active_output.as_ref()?;
+        let progress = Progress {
+            elapsed: self.get_duration(),
+            written_bytes: self.limits.max_megabytes.and_then(|_| written_bytes(output)),
+            free_bytes: self.limits.min_free_megabytes
+                .and_then(|_| output.writing_to.parent())
+                // A bare file name is in the current directory
+                .map(|dir| if dir.as_os_str().is_empty() { std::path::Path::new(".") } else { dir })
+                .and_then(limits::free_bytes),
+            // Whatever happened while paused doesn't count
+            idle: !self.is_paused() && self.idle_watch.as_ref().is_some_and(IdleWatch::is_idle),
+        };
+        self.limits_checked = Some(Instant::now());
+        self.limits.check(&progress)
+    }
+
     pub fn get_duration(&self) -> Duration {
         if let Some(start) = self.start_time {
             let current_duration = if let Some(pause_time) = self.last_pause_time {
@@ -366,3 +941,117 @@ impl WaitTimeout for Child {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_list::{DeviceProvider, MockProvider};
+    use crate::stream::StreamTarget;
+
+    fn audio_only_config(output_path: PathBuf, format: &str) -> RecordingConfig {
+        RecordingConfig {
+            output_path,
+            mode: RecordingMode::AudioOnly,
+            audio_device: MockProvider::default().audio_devices().into_iter().next(),
+            container_format: format.to_string(),
+            ..RecordingConfig::default()
+        }
+    }
+
+    #[test]
+    fn audio_only_needs_a_device_and_a_file() {
+        let mut recorder = Recorder::new();
+        let no_device = RecordingConfig { audio_device: None, ..audio_only_config("talk.ogg".into(), "ogg") };
+        assert_eq!(recorder.start(&no_device).unwrap_err(), "No audio device selected");
+
+        let streamed = RecordingConfig {
+            stream: Some(StreamConfig {
+                target: StreamTarget::parse("rtmp://localhost/live").unwrap(),
+                video_kbps: 1000,
+                keyframe_seconds: 2,
+            }),
+            ..audio_only_config("talk.ogg".into(), "ogg")
+        };
+        assert!(recorder.start(&streamed).is_err());
+        assert!(!recorder.is_recording());
+    }
+
+    #[test]
+    fn size_splitting_needs_a_bitrate_cap() {
+        let config = RecordingConfig {
+            output_path: PathBuf::from("clip.mkv"),
+            container_format: "mkv".to_string(),
+            video_codec: Some(VideoCodec::Ffv1),
+            segmentation: Segmentation::Megabytes(100),
+            ..RecordingConfig::default()
+        };
+        assert!(Recorder::new().start(&config).unwrap_err().contains("split by time"));
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn records_audio_only_to_wav() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-audio-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut recorder = Recorder::new();
+        recorder.start(&audio_only_config(dir.join("notes.wav"), "wav")).unwrap();
+        std::thread::sleep(Duration::from_secs(2));
+        recorder.stop().unwrap();
+
+        let wav = std::fs::read(dir.join("notes.wav")).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert!(wav.len() > 48000); // More than half a second of 16-bit stereo
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A recorder running `script` in place of ffmpeg
+    #[cfg(unix)]
+    fn run_script(script: &str) -> Recorder {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        let mut recorder = Recorder::new();
+        recorder.spawn(cmd, ActiveOutput {
+            writing_to: PathBuf::from("out.mp4"),
+            output_path: PathBuf::from("out.mp4"),
+            crash_safety: CrashSafety::Off,
+            remux_on_stop: false,
+            overwrite: false,
+            segment_pattern: None,
+            replay: None,
+            streaming: false,
+            animated: None,
+        }).unwrap();
+        recorder
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn notices_ffmpeg_exiting_on_its_own() {
+        let mut recorder = run_script("echo 'out.mp4: No space left on device' >&2; exit 1");
+        let started = Instant::now();
+        let reason = loop {
+            if let Some(reason) = recorder.check_exited() {
+                break reason;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(reason, "ffmpeg exited: out.mp4: No space left on device");
+        assert!(recorder.is_recording()); // Until the caller stops it
+        recorder.stop().unwrap();
+        assert!(!recorder.is_recording());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stopping_while_paused_lets_ffmpeg_finish() {
+        let mut recorder = run_script("trap 'exit 0' TERM; while :; do sleep 0.1; done");
+        recorder.pause().unwrap();
+        let started = Instant::now();
+        recorder.stop().unwrap();
+        // Without resuming first, SIGTERM waits out the 5 s and ffmpeg gets killed
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(!recorder.is_paused());
+    }
+}
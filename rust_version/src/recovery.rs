@@ -0,0 +1,181 @@
+use crate::metadata;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// How the file is written while recording, so that a crash (of the app or the
+/// machine) still leaves something playable behind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrashSafety {
+    Off,
+    FragmentedMp4, // -movflags +frag_keyframe+empty_moov
+    Matroska,
+}
+
+impl CrashSafety {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CrashSafety::Off => "Off",
+            CrashSafety::FragmentedMp4 => "Fragmented MP4",
+            CrashSafety::Matroska => "Matroska (MKV)",
+        }
+    }
+}
+
+// In-progress recordings are named `<stem>.partial.<ext>` until they are finalized
+const PARTIAL_MARKER: &str = ".partial";
+
+/// The file ffmpeg writes to while recording to `output` with the given mode.
+pub fn partial_path_for(output: &Path, mode: CrashSafety) -> PathBuf {
+    let ext = output.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let partial_ext = match (mode, ext) {
+        // WebM already is Matroska, and fragmented MP4 makes no sense for it
+        (_, "webm") => "webm",
+        (CrashSafety::FragmentedMp4, _) => ext,
+        _ => "mkv",
+    };
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    output.with_file_name(format!("{}{}.{}", stem, PARTIAL_MARKER, partial_ext))
+}
+
+/// Strips the partial marker: `clip.partial.mkv` -> `clip.mkv`.
+pub fn final_path_for(partial: &Path) -> Option<PathBuf> {
+    let name = partial.file_name()?.to_str()?;
+    let (stem, ext) = name.rsplit_once('.')?;
+    let stem = stem.strip_suffix(PARTIAL_MARKER)?;
+    Some(partial.with_file_name(format!("{}.{}", stem, ext)))
+}
+
+/// Copies the streams into a regular file. MP4 outputs get their index moved to
+/// the front so they start playing before being fully downloaded.
+pub fn remux(input: &Path, output: &Path, overwrite: bool) -> Result<(), String> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-i").arg(input)
+       .arg("-map").arg("0")
+       .arg("-c").arg("copy");
+    if output.extension().is_some_and(|e| e == "mp4") {
+        cmd.arg("-movflags").arg(format!("+faststart{}", metadata::MOV_FLAG)); // Keep the capture details
+    }
+    cmd.arg(if overwrite { "-y" } else { "-n" }).arg(output);
+
+    let out = cmd.stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Remuxing {:?} failed: {}", input, String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+/// Turns a finished partial recording into the real output. Returns the path of
+/// the file that was kept.
+pub fn finalize(partial: &Path, output: &Path, remux_on_stop: bool, overwrite: bool) -> Result<PathBuf, String> {
+    // Nothing to gain from remuxing WebM into WebM, a rename is enough. MP4 is
+    // always remuxed to get rid of the fragments and add faststart.
+    let needs_remux = partial.extension() != output.extension()
+        || output.extension().is_some_and(|e| e == "mp4");
+
+    if remux_on_stop && needs_remux {
+        remux(partial, output, overwrite)?;
+        std::fs::remove_file(partial).map_err(|e| format!("Failed to remove {:?}: {}", partial, e))?;
+        return Ok(output.to_path_buf());
+    }
+
+    let mut kept = match partial.extension() {
+        Some(ext) => output.with_extension(ext),
+        None => output.to_path_buf(),
+    };
+    if !overwrite {
+        let name = kept.file_name().unwrap_or_default().to_string_lossy().to_string();
+        kept = crate::filename::unique_path(kept.parent().unwrap_or(Path::new(".")), &name);
+    }
+    std::fs::rename(partial, &kept).map_err(|e| format!("Failed to rename {:?}: {}", partial, e))?;
+    Ok(kept)
+}
+
+/// Partial recordings left behind in `dir` by a crash.
+pub fn find_orphans(dir: &Path) -> Vec<PathBuf> {
+    let mut orphans: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && final_path_for(path).is_some())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    orphans.sort();
+    orphans
+}
+
+/// Repairs an orphaned partial recording by remuxing whatever made it to disk.
+/// Fragmented MP4 becomes a regular MP4, Matroska stays Matroska.
+pub fn recover(partial: &Path) -> Result<PathBuf, String> {
+    let target = final_path_for(partial).ok_or_else(|| format!("{:?} is not a partial recording", partial))?;
+    let stem = target.file_stem().and_then(|s| s.to_str()).unwrap_or("recording").to_string();
+    let ext = target.extension().and_then(|e| e.to_str()).unwrap_or("mkv").to_string();
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let target = crate::filename::unique_path(dir, &format!("{}_recovered.{}", stem, ext));
+
+    remux(partial, &target, false)?;
+    std::fs::remove_file(partial).map_err(|e| format!("Failed to remove {:?}: {}", partial, e))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_names_depend_on_mode_and_container() {
+        let out = Path::new("/videos/clip.mp4");
+        assert_eq!(partial_path_for(out, CrashSafety::FragmentedMp4), Path::new("/videos/clip.partial.mp4"));
+        assert_eq!(partial_path_for(out, CrashSafety::Matroska), Path::new("/videos/clip.partial.mkv"));
+        assert_eq!(
+            partial_path_for(Path::new("/videos/clip.webm"), CrashSafety::FragmentedMp4),
+            Path::new("/videos/clip.partial.webm")
+        );
+    }
+
+    #[test]
+    fn final_path_strips_the_marker() {
+        assert_eq!(final_path_for(Path::new("/v/a.b.partial.mkv")), Some(PathBuf::from("/v/a.b.mkv")));
+        assert_eq!(final_path_for(Path::new("/v/clip.mp4")), None);
+    }
+
+    #[test]
+    fn finds_only_partial_files() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-orphans-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("done.mp4"), b"").unwrap();
+        std::fs::write(dir.join("crashed.partial.mkv"), b"").unwrap();
+
+        assert_eq!(find_orphans(&dir), vec![dir.join("crashed.partial.mkv")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finalize_without_remux_keeps_the_partial_container() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-finalize-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let partial = dir.join("clip.partial.mkv");
+        std::fs::write(&partial, b"data").unwrap();
+
+        let kept = finalize(&partial, &dir.join("clip.mp4"), false, false).unwrap();
+        assert_eq!(kept, dir.join("clip.mkv"));
+        assert!(!partial.exists());
+
+        // An existing file is only replaced when overwriting
+        std::fs::write(&partial, b"second").unwrap();
+        let kept = finalize(&partial, &dir.join("clip.mp4"), false, false).unwrap();
+        assert_eq!(kept, dir.join("clip_1.mkv"));
+        assert_eq!(std::fs::read(dir.join("clip.mkv")).unwrap(), b"data");
+        std::fs::write(&partial, b"third").unwrap();
+        assert_eq!(finalize(&partial, &dir.join("clip.mp4"), false, true).unwrap(), dir.join("clip.mkv"));
+        assert_eq!(std::fs::read(dir.join("clip.mkv")).unwrap(), b"third");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! Optional HTTP server on localhost: REST endpoints for status, start, stop,
+//! pause, resume and marker, and a WebSocket at `/events` pushing progress.
+//! Requests go to the GUI thread the same way as over the control socket.
+
+use crate::control::{self, Call, Request};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket, protocol::Role};
+
+pub const DEFAULT_PORT: u16 = 8765;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: String, // Clients send it as `Authorization: Bearer <token>`
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: DEFAULT_PORT, token: String::new() }
+    }
+}
+
+/// 128 random bits, hex encoded.
+pub fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| format!("No randomness for a token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+const POLL: Duration = Duration::from_millis(50);
+// How often `/events` sends progress while recording
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+// Limits for what a client may send, way more than any real request needs
+const MAX_HEAD: u64 = 16 * 1024;
+const MAX_BODY: usize = 1024 * 1024;
+
+type OnCall = Arc<dyn Fn() + Send + Sync>;
+
+/// Serves on 127.0.0.1 until dropped.
+pub struct Server {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn start(settings: &RemoteSettings, calls: mpsc::Sender<Call>, on_call: impl Fn() + Send + Sync + 'static) -> Result<Self, String> {
+        if settings.token.is_empty() {
+            return Err("The HTTP server needs a token".to_string());
+        }
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, settings.port))
+            .map_err(|e| format!("Failed to listen on port {}: {}", settings.port, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let token: Arc<str> = settings.token.as_str().into();
+        let on_call: OnCall = Arc::new(on_call);
+        let thread = std::thread::spawn(move || {
+            while !stopped.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let (calls, on_call, token, stopped) = (calls.clone(), on_call.clone(), token.clone(), stopped.clone());
+                        std::thread::spawn(move || serve(stream, &token, &calls, on_call, &stopped));
+                    }
+                    Err(_) => std::thread::sleep(POLL),
+                }
+            }
+        });
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        // Open WebSockets notice within a progress interval
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>, // Names in lower case
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<HttpRequest, String> {
+    let mut head = String::new();
+    let mut limited = reader.take(MAX_HEAD);
+    loop {
+        let before = head.len();
+        if limited.read_line(&mut head).map_err(|e| e.to_string())? == 0 {
+            return Err("Incomplete request".to_string());
+        }
+        if head[before..].trim().is_empty() {
+            break;
+        }
+    }
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err("Malformed request line".to_string());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        ..HttpRequest::default()
+    };
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        let (name, value) = line.split_once(':').ok_or("Malformed header")?;
+        request.headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let length: usize = request.header("content-length").map_or(Ok(0), str::parse).map_err(|_| "Invalid Content-Length")?;
+    if length > MAX_BODY {
+        return Err("Request body too large".to_string());
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).map_err(|e| e.to_string())?;
+    Ok(request)
+}
+
+// The bearer token, or for `/events` (browsers can't set headers on a
+// WebSocket) also `?token=`. Compared in constant time.
+fn authorized(request: &HttpRequest, token: &str) -> bool {
+    let given = request.header("authorization")
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .or_else(|| request.query_param("token").filter(|_| request.path == "/events"));
+    let Some(given) = given else {
+        return false;
+    };
+    !token.is_empty()
+        && given.len() == token.len()
+        && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// The control request behind a REST endpoint, or the status code and why not
+fn route(request: &HttpRequest) -> Result<Request, (u16, String)> {
+    let method = request.path.trim_start_matches('/');
+    let expected = if method == "status" { "GET" } else { "POST" };
+    let params = if request.body.iter().all(u8::is_ascii_whitespace) {
+        Value::Null
+    } else {
+        serde_json::from_slice(&request.body).map_err(|e| (400, format!("Invalid JSON: {}", e)))?
+    };
+    match control::request(method, params) {
+        Ok(_) if request.method != expected => Err((405, format!("Use {} for /{}", expected, method))),
+        Ok(request) => Ok(request),
+        Err(e) if e.code == control::METHOD_NOT_FOUND => Err((404, format!("No endpoint {}", request.path))),
+        Err(e) => Err((400, e.message)),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Error",
+    }
+}
+
+fn send(stream: &mut TcpStream, status: u16, body: &Value) {
+    let body = body.to_string();
+    let auth = if status == 401 { "WWW-Authenticate: Bearer\r\n" } else { "" };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status, reason(status), body.len(), auth, body
+    );
+}
+
+// One request per connection, or a WebSocket until either side closes it
+fn serve(mut stream: TcpStream, token: &str, calls: &mpsc::Sender<Call>, on_call: OnCall, stopped: &AtomicBool) {
+    if stream.set_nonblocking(false).is_err() || stream.set_read_timeout(Some(Duration::from_secs(10))).is_err() {
+        return;
+    }
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let request = match read_request(&mut BufReader::new(reader)) {
+        Ok(request) => request,
+        Err(e) => return send(&mut stream, 400, &json!({ "error": e })),
+    };
+    if !authorized(&request, token) {
+        return send(&mut stream, 401, &json!({ "error": "Missing or wrong bearer token" }));
+    }
+    if request.path == "/events" {
+        return events(stream, &request, calls, on_call.as_ref(), stopped);
+    }
+    match route(&request) {
+        Ok(request) => match control::ask(calls, request, on_call.as_ref()) {
+            Ok(result) => send(&mut stream, 200, &result),
+            Err(e) => send(&mut stream, 409, &json!({ "error": e })),
+        },
+        Err((status, e)) => send(&mut stream, status, &json!({ "error": e })),
+    }
+}
+
+// Sends the status every PROGRESS_INTERVAL while recording, and whenever it
+// changes otherwise
+fn events(mut stream: TcpStream, request: &HttpRequest, calls: &mpsc::Sender<Call>, on_call: &dyn Fn(), stopped: &AtomicBool) {
+    let key = match request.header("sec-websocket-key") {
+        Some(key) if request.header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket")) => key,
+        _ => return send(&mut stream, 400, &json!({ "error": "/events is a WebSocket" })),
+    };
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    );
+    if stream.write_all(handshake.as_bytes()).is_err() || stream.set_read_timeout(Some(PROGRESS_INTERVAL)).is_err() {
+        return;
+    }
+
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let mut last = Value::Null;
+    while !stopped.load(Ordering::SeqCst) {
+        let Ok(status) = control::ask(calls, Request::Status, on_call) else {
+            break;
+        };
+        if status != last || status["recording"] == true {
+            let event = json!({ "event": "progress", "status": status });
+            if socket.send(Message::text(event.to_string())).is_err() {
+                return;
+            }
+            last = status;
+        }
+        // Waits out the interval, answering pings and noticing a close
+        match socket.read() {
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> HttpRequest {
+        read_request(&mut std::io::Cursor::new(raw.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn routes_requests_with_the_right_token() {
+        let start = parse("POST /start HTTP/1.1\r\nAuthorization: Bearer s3cret\r\nContent-Length: 18\r\n\r\n{\"profile\":\"Demo\"}");
+        assert!(authorized(&start, "s3cret"));
+        assert!(!authorized(&start, "s3cre7"));
+        assert!(!authorized(&start, ""));
+        assert_eq!(route(&start), Ok(Request::Start { profile: Some("Demo".to_string()), config: None }));
+
+        let status = parse("GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(!authorized(&status, "s3cret"));
+        assert_eq!(route(&status), Ok(Request::Status));
+        assert_eq!(route(&parse("GET /stop HTTP/1.1\r\n\r\n")).unwrap_err().0, 405);
+        assert_eq!(route(&parse("POST /record HTTP/1.1\r\n\r\n")).unwrap_err().0, 404);
+        assert_eq!(route(&parse("POST /start HTTP/1.1\r\nContent-Length: 1\r\n\r\n{")).unwrap_err().0, 400);
+
+        // Only the WebSocket takes the token in the URL
+        assert!(authorized(&parse("GET /events?token=s3cret HTTP/1.1\r\n\r\n"), "s3cret"));
+        assert!(!authorized(&parse("GET /status?token=s3cret HTTP/1.1\r\n\r\n"), "s3cret"));
+        assert_eq!(new_token().unwrap().len(), 32);
+    }
+
+    fn http(addr: SocketAddr, request: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn serves_rest_and_progress_events() {
+        let settings = RemoteSettings { enabled: true, port: 0, token: "s3cret".to_string() };
+        let (calls, requests) = mpsc::channel::<Call>();
+        let server = Server::start(&settings, calls, || {}).unwrap();
+
+        // Stands in for the GUI thread
+        std::thread::spawn(move || {
+            while let Ok(call) = requests.recv_timeout(Duration::from_secs(10)) {
+                let result = match call.request {
+                    Request::Status => Ok(json!({ "recording": true, "elapsed": 1.5, "fps": 30.0, "size_bytes": 4096 })),
+                    _ => Err("Not recording".to_string()),
+                };
+                call.answer(result);
+            }
+        });
+
+        let (status, body) = http(server.addr(), "GET /status HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body["fps"], 30.0);
+        let (status, body) = http(server.addr(), "POST /pause HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n");
+        assert_eq!((status, body), (409, json!({ "error": "Not recording" })));
+        assert_eq!(http(server.addr(), "GET /status HTTP/1.1\r\n\r\n").0, 401);
+
+        let stream = TcpStream::connect(server.addr()).unwrap();
+        let url = format!("ws://{}/events?token=s3cret", server.addr());
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        let Message::Text(event) = socket.read().unwrap() else { panic!("not text") };
+        let event: Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(event["event"], "progress");
+        assert_eq!(event["status"]["size_bytes"], 4096);
+    }
+}
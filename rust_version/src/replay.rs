@@ -0,0 +1,186 @@
+use crate::codec::{AudioCodec, VideoCodec};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::SystemTime;
+
+/// Length of each segment in the ring. Shorter means the saved replay is closer
+/// to the requested length, but more files to juggle.
+pub const SEGMENT_SECONDS: u32 = 2;
+
+pub const DEFAULT_REPLAY_SECONDS: u32 = 60;
+
+/// Where the rolling segments of one recording live while buffering. Removed
+/// once the capture has stopped and no save is still copying from it.
+pub struct RingDir(PathBuf);
+
+impl RingDir {
+    /// A fresh directory per recording, so a save still finishing from the
+    /// last one can't collide with the next.
+    pub fn create() -> Result<Arc<Self>, String> {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "screen-recorder-replay-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        Ok(Arc::new(Self(dir)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for RingDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// How many segments the ring holds: enough for `keep_seconds`, plus the one
+/// being written and one spare so saving never races the oldest being reused.
+pub fn ring_size(keep_seconds: u32) -> u32 {
+    keep_seconds.max(1).div_ceil(SEGMENT_SECONDS) + 2
+}
+
+/// Segment container for the ring. MPEG-TS is cut-anywhere safe for H.264 and
+/// HEVC with AAC or Opus, VP9 stays in WebM, everything else goes into Matroska.
+pub fn segment_format(video: VideoCodec, audio: Option<AudioCodec>) -> (&'static str, &'static str) {
+    let ts_audio = matches!(audio, None | Some(AudioCodec::Aac) | Some(AudioCodec::Opus));
+    match video {
+        VideoCodec::H264 | VideoCodec::H264Lossless | VideoCodec::Hevc if ts_audio => ("mpegts", "ts"),
+        VideoCodec::Vp9 if ts_audio => ("webm", "webm"),
+        _ => ("matroska", "mkv"),
+    }
+}
+
+/// The finished segments covering the last `keep_seconds`, oldest first. The
+/// newest file is still being written to and is left out.
+pub fn retained_segments(dir: &Path, ext: &str, keep_seconds: u32) -> Vec<PathBuf> {
+    let mut segments: Vec<(SystemTime, PathBuf)> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == ext))
+            .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    segments.sort();
+    segments.pop();
+
+    let wanted = keep_seconds.max(1).div_ceil(SEGMENT_SECONDS) as usize;
+    let skip = segments.len().saturating_sub(wanted);
+    segments.into_iter().skip(skip).map(|(_, path)| path).collect()
+}
+
+/// The segments a replay save copies, picked while the capture keeps running.
+/// Keeps the ring around until the save is done with it.
+pub struct Snapshot {
+    ring: Arc<RingDir>,
+    ext: &'static str,
+    segments: Vec<PathBuf>,
+}
+
+/// Picks the retained segments to save. Quick enough for the UI thread, the
+/// slow part is `save`.
+pub fn snapshot(ring: &Arc<RingDir>, ext: &'static str, keep_seconds: u32) -> Result<Snapshot, String> {
+    let segments = retained_segments(ring.path(), ext, keep_seconds);
+    if segments.is_empty() {
+        return Err("Nothing buffered yet".to_string());
+    }
+    Ok(Snapshot { ring: ring.clone(), ext, segments })
+}
+
+/// Joins the snapshot's segments into `output` without re-encoding. The
+/// segments are copied aside first so the running capture can keep
+/// overwriting the ring.
+pub fn save(snapshot: &Snapshot, output: &Path) -> Result<PathBuf, String> {
+    let Snapshot { ring, ext, segments } = snapshot;
+    let staging = ring.path().join("saving");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| format!("Failed to create {:?}: {}", staging, e))?;
+
+    let mut list = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let copy = staging.join(format!("{:03}.{}", i, ext));
+        std::fs::copy(segment, &copy).map_err(|e| format!("Failed to copy {:?}: {}", segment, e))?;
+        list.push_str(&format!("file '{}'\n", copy.display()));
+    }
+    let list_path = staging.join("list.txt");
+    std::fs::write(&list_path, list).map_err(|e| format!("Failed to write {:?}: {}", list_path, e))?;
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-v").arg("error")
+       .arg("-f").arg("concat")
+       .arg("-safe").arg("0")
+       .arg("-i").arg(&list_path)
+       .arg("-c").arg("copy");
+    if output.extension().is_some_and(|e| e == "mp4") {
+        // AAC in MPEG-TS uses ADTS headers that MP4 doesn't want
+        cmd.arg("-bsf:a").arg("aac_adtstoasc")
+           .arg("-movflags").arg("+faststart");
+    }
+    cmd.arg("-n").arg(output);
+
+    let result = cmd.stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e));
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let out = result?;
+    if out.status.success() {
+        Ok(output.to_path_buf())
+    } else {
+        Err(format!("Saving replay failed: {}", String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn ring_covers_the_requested_length() {
+        assert_eq!(ring_size(60), 32);
+        assert_eq!(ring_size(5), 5);
+    }
+
+    #[test]
+    fn keeps_the_newest_finished_segments() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-ring-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The ring wraps, so file names say nothing about the order
+        let base = SystemTime::now() - Duration::from_secs(60);
+        for (i, name) in ["replay_003.ts", "replay_000.ts", "replay_001.ts", "replay_002.ts"].iter().enumerate() {
+            let file = std::fs::File::create(dir.join(name)).unwrap();
+            file.set_modified(base + Duration::from_secs(2 * i as u64)).unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        assert_eq!(
+            retained_segments(&dir, "ts", 4),
+            vec![dir.join("replay_000.ts"), dir.join("replay_001.ts")]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ring_is_kept_until_the_save_is_done() {
+        let ring = RingDir::create().unwrap();
+        let dir = ring.path().to_path_buf();
+        std::fs::write(dir.join("replay_000.ts"), b"").unwrap();
+        std::fs::write(dir.join("replay_001.ts"), b"").unwrap(); // Still being written
+
+        let snapshot = snapshot(&ring, "ts", 60).unwrap();
+        assert_eq!(snapshot.segments.len(), 1);
+        drop(ring); // The recording stopped
+        assert!(dir.exists());
+        drop(snapshot);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,393 @@
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where "now" comes from. Tests use a clock they can move by hand.
+pub trait Clock {
+    /// Local wall-clock time.
+    fn now(&self) -> NaiveDateTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+const WEEKDAYS: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+const WEEKEND: [Weekday; 2] = [Weekday::Sat, Weekday::Sun];
+
+/// When a scheduled recording starts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Start {
+    Once(NaiveDateTime),
+    Weekly { time: NaiveTime, days: Vec<Weekday> }, // No days means every day
+}
+
+/// When a scheduled recording stops.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stop {
+    Manual,
+    After(u64), // Seconds
+    At(NaiveTime), // The first time it's this late after starting
+}
+
+/// "90s", "15m", "1h30m".
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let invalid = || format!("\"{}\" is not a duration like 90s, 15m or 1h30m", text);
+    let mut total = 0;
+    let mut number = String::new();
+    for c in text.trim().chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' if !number.is_empty() => {
+                let n: u64 = number.parse().map_err(|_| invalid())?;
+                total += n * match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                number.clear();
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let mut text = String::new();
+    for (n, unit) in [(h, 'h'), (m, 'm'), (s, 's')] {
+        if n > 0 {
+            text.push_str(&format!("{}{}", n, unit));
+        }
+    }
+    if text.is_empty() { "0s".to_string() } else { text }
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M").map_err(|_| format!("\"{}\" is not a time like 09:00", text.trim()))
+}
+
+fn parse_days(text: &str) -> Result<Vec<Weekday>, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "daily" => Ok(Vec::new()),
+        "weekdays" => Ok(WEEKDAYS.to_vec()),
+        "weekends" => Ok(WEEKEND.to_vec()),
+        days => days.split(',')
+            .map(|d| d.trim().parse::<Weekday>().map_err(|_| format!("\"{}\" is not a day like mon or friday", d.trim())))
+            .collect(),
+    }
+}
+
+// The first moment after `after` that is `time` on one of `days`
+fn next_time_on(after: NaiveDateTime, time: NaiveTime, days: &[Weekday]) -> NaiveDateTime {
+    let mut date = after.date();
+    loop {
+        let candidate = date.and_time(time);
+        if candidate > after && (days.is_empty() || days.contains(&date.weekday())) {
+            return candidate;
+        }
+        date = date.succ_opt().unwrap_or(NaiveDate::MAX);
+    }
+}
+
+impl Start {
+    /// Accepts "in 10m", "09:00" (the next one), "2026-10-19 09:00",
+    /// "daily 09:00", "weekdays 09:00", "weekends 10:30" or "mon,thu 14:00".
+    pub fn parse(text: &str, now: NaiveDateTime) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(countdown) = text.strip_prefix("in ") {
+            return Ok(Start::Once(now + TimeDelta::seconds(parse_duration(countdown)? as i64)));
+        }
+        if let Ok(at) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+            if at <= now {
+                return Err(format!("{} has already passed", text));
+            }
+            return Ok(Start::Once(at));
+        }
+        match text.rsplit_once(' ') {
+            Some((days, time)) => Ok(Start::Weekly { time: parse_time(time)?, days: parse_days(days)? }),
+            None => Ok(Start::Once(next_time_on(now, parse_time(text)?, &[]))),
+        }
+    }
+
+    /// The first start strictly after `after`.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Start::Once(at) => (*at > after).then_some(*at),
+            Start::Weekly { time, days } => Some(next_time_on(after, *time, days)),
+        }
+    }
+}
+
+impl fmt::Display for Start {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Start::Once(at) => write!(f, "{}", at.format("%Y-%m-%d %H:%M")),
+            Start::Weekly { time, days } => {
+                let days = match days.as_slice() {
+                    [] => "daily".to_string(),
+                    d if d == WEEKDAYS => "weekdays".to_string(),
+                    d if d == WEEKEND => "weekends".to_string(),
+                    d => d.iter().map(|day| day.to_string().to_lowercase()).collect::<Vec<_>>().join(","),
+                };
+                write!(f, "{} {}", days, time.format("%H:%M"))
+            }
+        }
+    }
+}
+
+impl Stop {
+    /// Accepts "" (stop by hand), "after 15m", "15m", "at 10:00" or "10:00".
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if text.is_empty() || text == "manual" {
+            return Ok(Stop::Manual);
+        }
+        if let Some(time) = text.strip_prefix("at ") {
+            return Ok(Stop::At(parse_time(time)?));
+        }
+        let duration = text.strip_prefix("after ").unwrap_or(text);
+        if duration.contains(':') {
+            Ok(Stop::At(parse_time(duration)?))
+        } else {
+            Ok(Stop::After(parse_duration(duration)?))
+        }
+    }
+
+    pub fn stop_time(&self, started: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Stop::Manual => None,
+            Stop::After(seconds) => Some(started + TimeDelta::seconds(*seconds as i64)),
+            Stop::At(time) => Some(next_time_on(started, *time, &[])),
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Manual => f.write_str("manual"),
+            Stop::After(seconds) => write!(f, "after {}", format_duration(*seconds)),
+            Stop::At(time) => write!(f, "at {}", time.format("%H:%M")),
+        }
+    }
+}
+
+/// A recording to start (and maybe stop) by itself.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    pub start: Start,
+    pub stop: Stop,
+    pub profile: Option<String>, // None records with the current settings
+    pub enabled: bool,
+}
+
+impl Schedule {
+    pub fn summary(&self) -> String {
+        let mut text = format!("{}, stop {}", self.start, self.stop);
+        if let Some(profile) = &self.profile {
+            text.push_str(&format!(" · {}", profile));
+        }
+        text
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Start(Schedule),
+    Stop(Schedule),
+    Skipped(Schedule), // Something was already recording
+    Missed(Schedule), // A one-off that passed while the app wasn't running
+}
+
+/// Decides when schedules fire. Keeps no schedules of its own, they live in
+/// the settings.
+pub struct Scheduler {
+    clock: Box<dyn Clock>,
+    last_check: NaiveDateTime,
+    running: Option<(Schedule, Option<NaiveDateTime>)>, // With its stop time
+}
+
+impl Scheduler {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        // Starts missed while the app wasn't running are not made up for
+        let last_check = clock.now();
+        Self { clock, last_check, running: None }
+    }
+
+    pub fn now(&self) -> NaiveDateTime {
+        self.clock.now()
+    }
+
+    /// The schedule behind the current recording, and when it stops.
+    pub fn running(&self) -> Option<&(Schedule, Option<NaiveDateTime>)> {
+        self.running.as_ref()
+    }
+
+    /// The enabled schedule that starts next.
+    pub fn next<'a>(&self, schedules: &'a [Schedule]) -> Option<(&'a Schedule, NaiveDateTime)> {
+        schedules.iter()
+            .filter(|s| s.enabled)
+            .filter_map(|s| Some((s, s.start.next_after(self.last_check)?)))
+            .min_by_key(|(_, at)| *at)
+    }
+
+    /// Everything that became due since the last call. One-off schedules are
+    /// removed once they fire, or once they are in the past.
+    pub fn tick(&mut self, schedules: &mut Vec<Schedule>, recording: bool) -> Vec<Event> {
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        let mut busy = recording;
+
+        if let Some((schedule, stop_at)) = &self.running {
+            if !recording {
+                self.running = None; // Stopped by hand
+            } else if stop_at.is_some_and(|at| at <= now) {
+                events.push(Event::Stop(schedule.clone()));
+                self.running = None;
+                busy = false;
+            }
+        }
+
+        let last_check = self.last_check;
+        schedules.retain(|schedule| {
+            if let Start::Once(at) = schedule.start
+                && at <= last_check
+            {
+                events.push(Event::Missed(schedule.clone()));
+                return false;
+            }
+            let due = schedule.enabled && schedule.start.next_after(last_check).is_some_and(|at| at <= now);
+            if !due {
+                return true;
+            }
+            if busy {
+                events.push(Event::Skipped(schedule.clone()));
+            } else {
+                self.running = Some((schedule.clone(), schedule.stop.stop_time(now)));
+                events.push(Event::Start(schedule.clone()));
+                busy = true;
+            }
+            !matches!(schedule.start, Start::Once(_))
+        });
+
+        self.last_check = now;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A clock that only moves when told to
+    #[derive(Clone)]
+    struct TestClock(Rc<Cell<NaiveDateTime>>);
+
+    impl TestClock {
+        fn set(&self, text: &str) {
+            self.0.set(at(text));
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> NaiveDateTime {
+            self.0.get()
+        }
+    }
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn schedule(name: &str, start: Start, stop: Stop) -> Schedule {
+        Schedule { name: name.to_string(), start, stop, profile: None, enabled: true }
+    }
+
+    #[test]
+    fn parses_starts_and_stops() {
+        let now = at("2026-10-16 10:00"); // A Friday
+        assert_eq!(Start::parse("in 1h30m", now).unwrap(), Start::Once(at("2026-10-16 11:30")));
+        assert_eq!(Start::parse("09:00", now).unwrap(), Start::Once(at("2026-10-17 09:00")));
+        assert_eq!(Start::parse("2026-12-24 18:00", now).unwrap(), Start::Once(at("2026-12-24 18:00")));
+
+        let standup = Start::parse("weekdays 09:00", now).unwrap();
+        assert_eq!(standup.to_string(), "weekdays 09:00");
+        assert_eq!(standup.next_after(now), Some(at("2026-10-19 09:00"))); // Monday
+        assert_eq!(Start::parse("Mon, thursday 14:00", now).unwrap().to_string(), "mon,thu 14:00");
+        assert!(Start::parse("someday 09:00", now).is_err());
+        assert!(Start::parse("in 5 minutes", now).is_err());
+        assert!(Start::parse("2026-10-16 09:00", now).is_err()); // Would never fire
+
+        assert_eq!(Stop::parse("").unwrap(), Stop::Manual);
+        assert_eq!(Stop::parse("after 15m").unwrap().to_string(), "after 15m");
+        assert_eq!(Stop::parse("09:30").unwrap().stop_time(at("2026-10-16 10:00")), Some(at("2026-10-17 09:30")));
+        assert_eq!(format_duration(5400), "1h30m");
+    }
+
+    #[test]
+    fn fires_on_time_and_stops_after_the_duration() {
+        let clock = TestClock(Rc::new(Cell::new(at("2026-10-19 08:55"))));
+        let mut scheduler = Scheduler::new(Box::new(clock.clone()));
+        let standup = schedule("Standup", Start::parse("weekdays 09:00", clock.now()).unwrap(), Stop::After(15 * 60));
+        let demo = schedule("Demo", Start::Once(at("2026-10-19 09:05")), Stop::Manual);
+        let mut schedules = vec![standup.clone(), demo.clone()];
+
+        assert!(scheduler.tick(&mut schedules, false).is_empty());
+        assert_eq!(scheduler.next(&schedules).unwrap().1, at("2026-10-19 09:00"));
+
+        // A tick that comes late still catches the start
+        clock.set("2026-10-19 09:00");
+        assert_eq!(scheduler.tick(&mut schedules, false), vec![Event::Start(standup.clone())]);
+        assert_eq!(scheduler.running().unwrap().1, Some(at("2026-10-19 09:15")));
+
+        // The one-off demo comes due while the standup records, and is dropped
+        clock.set("2026-10-19 09:06");
+        assert_eq!(scheduler.tick(&mut schedules, true), vec![Event::Skipped(demo)]);
+        assert_eq!(schedules.len(), 1);
+
+        clock.set("2026-10-19 09:15");
+        assert_eq!(scheduler.tick(&mut schedules, true), vec![Event::Stop(standup)]);
+        assert!(scheduler.running().is_none());
+        assert_eq!(scheduler.next(&schedules).unwrap().1, at("2026-10-20 09:00"));
+    }
+
+    #[test]
+    fn stopping_by_hand_ends_the_schedule() {
+        let clock = TestClock(Rc::new(Cell::new(at("2026-10-19 08:59"))));
+        let mut scheduler = Scheduler::new(Box::new(clock.clone()));
+        let mut schedules = vec![schedule("Talk", Start::Once(at("2026-10-19 09:00")), Stop::After(3600))];
+
+        clock.set("2026-10-19 09:00");
+        assert_eq!(scheduler.tick(&mut schedules, false).len(), 1);
+        clock.set("2026-10-19 09:20");
+        assert!(scheduler.tick(&mut schedules, false).is_empty());
+        assert!(scheduler.running().is_none());
+
+        // A manual recording later on is not stopped by the old schedule
+        clock.set("2026-10-19 10:00");
+        assert!(scheduler.tick(&mut schedules, true).is_empty());
+    }
+
+    #[test]
+    fn one_offs_missed_before_launch_are_dropped() {
+        let clock = TestClock(Rc::new(Cell::new(at("2026-10-19 10:00"))));
+        let mut scheduler = Scheduler::new(Box::new(clock.clone()));
+        let missed = schedule("Talk", Start::Once(at("2026-10-19 09:00")), Stop::Manual);
+        let mut schedules = vec![missed.clone()];
+
+        assert_eq!(scheduler.tick(&mut schedules, false), vec![Event::Missed(missed)]);
+        assert!(schedules.is_empty());
+    }
+}
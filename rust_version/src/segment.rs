@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Splitting long recordings into several files with ffmpeg's segment muxer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Segmentation {
+    Off,
+    Minutes(u32),
+    Megabytes(u32),
+}
+
+impl Segmentation {
+    pub fn is_enabled(&self) -> bool {
+        *self != Segmentation::Off
+    }
+
+    /// Segment length in seconds. The segment muxer only splits by time, so
+    /// size limits are turned into a duration using the capped bitrate, with
+    /// 5% left for the container. The size is approximate either way.
+    pub fn segment_seconds(&self, bitrate_kbps: u32) -> Option<u64> {
+        match *self {
+            Segmentation::Off => None,
+            Segmentation::Minutes(minutes) => Some(u64::from(minutes.max(1)) * 60),
+            Segmentation::Megabytes(mb) => {
+                let bits = u64::from(mb.max(1)) * 1_000_000 * 8 * 95 / 100;
+                Some((bits / (u64::from(bitrate_kbps.max(1)) * 1000)).max(1))
+            }
+        }
+    }
+}
+
+/// Placeholder in the filename template for the segment number.
+pub const SEGMENT_PLACEHOLDER: &str = "{segment}";
+
+// The number ffmpeg puts into the segment names, e.g. clip_007.mp4
+const SEGMENT_DIGITS: usize = 3;
+
+/// The ffmpeg output pattern for the segments of `output`: the `{segment}`
+/// placeholder if the name has one, otherwise `<stem>_%03d.<ext>`.
+pub fn segment_pattern(output: &Path) -> PathBuf {
+    let name = output.file_name().and_then(|n| n.to_str()).unwrap_or("recording.mp4");
+    let number = format!("%0{}d", SEGMENT_DIGITS);
+    let name = if name.contains(SEGMENT_PLACEHOLDER) {
+        name.replace(SEGMENT_PLACEHOLDER, &number)
+    } else {
+        match name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}_{}.{}", stem, number, ext),
+            None => format!("{}_{}", name, number),
+        }
+    };
+    output.with_file_name(name)
+}
+
+/// The playlist listing the finished segments, `<stem>.m3u8`.
+pub fn segment_list_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    output.with_file_name(format!("{}.m3u8", stem.replace(SEGMENT_PLACEHOLDER, "")))
+}
+
+/// Path of segment `index` (0-based) for a pattern from `segment_pattern`.
+pub fn segment_path(pattern: &Path, index: u32) -> PathBuf {
+    let name = pattern.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let number = format!("{:0width$}", index, width = SEGMENT_DIGITS);
+    pattern.with_file_name(name.replace(&format!("%0{}d", SEGMENT_DIGITS), &number))
+}
+
+/// Number of the segment currently being written (1-based), judging by
+/// which files exist so far.
+pub fn current_segment(pattern: &Path) -> u32 {
+    (0..).take_while(|&i| segment_path(pattern, i).exists()).count().max(1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_and_index_names() {
+        let out = Path::new("/v/2026-03-14_screen.mp4");
+        assert_eq!(segment_pattern(out), Path::new("/v/2026-03-14_screen_%03d.mp4"));
+        assert_eq!(segment_list_path(out), Path::new("/v/2026-03-14_screen.m3u8"));
+        assert_eq!(segment_path(&segment_pattern(out), 12), Path::new("/v/2026-03-14_screen_012.mp4"));
+
+        let templated = Path::new("/v/part{segment}_talk.webm");
+        assert_eq!(segment_pattern(templated), Path::new("/v/part%03d_talk.webm"));
+        assert_eq!(segment_list_path(templated), Path::new("/v/part_talk.m3u8"));
+    }
+
+    #[test]
+    fn size_limits_become_durations() {
+        assert_eq!(Segmentation::Minutes(30).segment_seconds(4000), Some(1800));
+        // 95 of 100 MB at 4 Mbit/s is 190 seconds
+        assert_eq!(Segmentation::Megabytes(100).segment_seconds(4000), Some(190));
+        assert_eq!(Segmentation::Off.segment_seconds(4000), None);
+    }
+
+    #[test]
+    fn counts_written_segments() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-segments-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pattern = segment_pattern(&dir.join("clip.mp4"));
+        assert_eq!(current_segment(&pattern), 1);
+
+        std::fs::write(segment_path(&pattern, 0), b"").unwrap();
+        std::fs::write(segment_path(&pattern, 1), b"").unwrap();
+        assert_eq!(current_segment(&pattern), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::animated::AnimatedOptions;
+use crate::codec::{AudioSettings, VideoCodec};
+use crate::filename::DEFAULT_TEMPLATE;
+use crate::hotkeys::HotkeySettings;
+use crate::limits::StopLimits;
+use crate::metadata::Metadata;
+use crate::recorder::RecordingMode;
+use crate::recovery::CrashSafety;
+use crate::remote::RemoteSettings;
+use crate::replay::DEFAULT_REPLAY_SECONDS;
+use crate::schedule::Schedule;
+use crate::segment::Segmentation;
+use crate::stream;
+use crate::tray::TraySettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Everything needed to set up a recording. The last used one is restored on
+/// launch, named ones can be saved and switched to from the GUI or `--profile`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub mode: RecordingMode,
+    pub monitor: Option<String>, // Monitor name, e.g. "Monitor 2"
+    pub region: Option<Region>,  // None records the whole monitor
+    // Devices are matched by their ID, which stays the same across reboots
+    pub camera_device: Option<String>,
+    pub audio_enabled: bool,
+    pub audio_device: Option<String>,
+    pub audio: AudioSettings,
+    pub output_dir: Option<PathBuf>, // None uses the Videos directory
+    pub filename: String, // Template, see `filename::render_template`
+    pub format: String,
+    pub video_codec: Option<VideoCodec>, // None uses the format's default
+    pub overwrite: bool, // Replace existing files instead of numbering
+    pub crash_safety: CrashSafety,
+    pub remux_on_stop: bool,
+    pub segmentation: Segmentation,
+    pub replay_buffer: bool,
+    pub replay_seconds: u32,
+    pub stream_enabled: bool,
+    pub stream_target: String, // rtmp://, rtmps://, srt:// or a directory for HLS, the RTMP key is in the keyring
+    pub stream_video_kbps: u32,
+    pub stream_keyframe_seconds: u32,
+    pub stream_local_copy: bool, // Also record to a file while streaming
+    pub stream_local_kbps: Option<u32>, // None shares the stream encode
+    pub animated: AnimatedOptions,
+    pub metadata: Metadata, // Written into every recording
+    pub limits: StopLimits, // When to stop on its own
+    pub countdown_seconds: u32, // 0 starts right away
+    pub minimize_on_record: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            mode: RecordingMode::Screen,
+            monitor: None,
+            region: None,
+            camera_device: None,
+            audio_enabled: false,
+            audio_device: None,
+            audio: AudioSettings::default(),
+            output_dir: None,
+            filename: DEFAULT_TEMPLATE.to_string(),
+            format: "mp4".to_string(),
+            video_codec: None,
+            overwrite: false,
+            crash_safety: CrashSafety::Off,
+            remux_on_stop: true,
+            segmentation: Segmentation::Off,
+            replay_buffer: false,
+            replay_seconds: DEFAULT_REPLAY_SECONDS,
+            stream_enabled: false,
+            stream_target: String::new(),
+            stream_video_kbps: stream::DEFAULT_VIDEO_KBPS,
+            stream_keyframe_seconds: stream::DEFAULT_KEYFRAME_SECONDS,
+            stream_local_copy: false,
+            stream_local_kbps: None,
+            animated: AnimatedOptions::default(),
+            metadata: Metadata::default(),
+            limits: StopLimits::default(),
+            countdown_seconds: 0,
+            minimize_on_record: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub last: Profile,
+    pub active_profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    pub hotkeys: HotkeySettings, // Global, not per profile
+    pub tray: TraySettings,
+    pub preview: bool, // Live preview while not recording
+    pub remote: RemoteSettings,
+    pub schedules: Vec<Schedule>,
+}
+
+impl Settings {
+    /// `settings.toml` in the platform config dir, e.g. `~/.config/screen-recorder/`.
+    pub fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "screen-recorder")
+            .map(|dirs| dirs.config_dir().join("settings.toml"))
+    }
+
+    /// Loads the settings, falling back to defaults if there are none yet.
+    pub fn load() -> Result<Self, String> {
+        match Self::path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {:?}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("No config directory available")?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &std::path::Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        std::fs::write(path, self.to_toml()?)
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid settings: {}", e))
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string_pretty(self).map_err(|e| format!("Failed to serialize settings: {}", e))
+    }
+
+    /// Moves stream keys left in the RTMP URLs of older settings to the OS
+    /// keyring. A profile keeps its key if the keyring can't take it.
+    pub fn move_stream_keys(&mut self) {
+        for profile in std::iter::once(&mut self.last).chain(self.profiles.values_mut()) {
+            if let (server, Some(key)) = stream::split_key(&profile.stream_target)
+                && stream::save_key(&server, &key).is_ok()
+            {
+                profile.stream_target = server;
+            }
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile, String> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| format!("No profile named \"{}\"", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::{Start, Stop};
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut settings = Settings::default();
+        settings.last.audio_enabled = true;
+        settings.last.audio_device = Some("sysdefault:CARD=PCH".to_string());
+        settings.profiles.insert(
+            "Bug report".to_string(),
+            Profile {
+                mode: RecordingMode::PiP,
+                region: Some(Region { x: 10, y: 20, width: 800, height: 600 }),
+                format: "webm".to_string(),
+                segmentation: Segmentation::Minutes(30),
+                limits: StopLimits { max_minutes: Some(90), idle_minutes: Some(5), ..StopLimits::default() },
+                ..Profile::default()
+            },
+        );
+        settings.active_profile = Some("Bug report".to_string());
+        let now = chrono::NaiveDate::from_ymd_opt(2026, 10, 16).unwrap().and_hms_opt(10, 0, 0).unwrap();
+        for (start, stop) in [("weekdays 09:00", "after 15m"), ("in 10m", "")] {
+            settings.schedules.push(Schedule {
+                name: "Standup".to_string(),
+                start: Start::parse(start, now).unwrap(),
+                stop: Stop::parse(stop).unwrap(),
+                profile: Some("Bug report".to_string()),
+                enabled: true,
+            });
+        }
+
+        let text = settings.to_toml().unwrap();
+        assert_eq!(Settings::from_toml(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        let settings = Settings::from_toml("[last]\nmode = \"Camera\"\n").unwrap();
+        assert_eq!(settings.last.mode, RecordingMode::Camera);
+        assert_eq!(settings.last.filename, DEFAULT_TEMPLATE);
+        assert!(settings.profiles.is_empty());
+    }
+
+    #[test]
+    fn missing_file_is_not_an_error() {
+        let path = std::env::temp_dir().join("screen-recorder-no-such-dir/settings.toml");
+        assert_eq!(Settings::load_from(&path).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn unknown_profile_is_reported() {
+        assert!(Settings::default().profile("Talk recording").is_err());
+    }
+}
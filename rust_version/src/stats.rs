@@ -0,0 +1,91 @@
+use std::io::{BufRead, BufReader, Read};
+use std::sync::{Arc, Mutex};
+
+/// What ffmpeg reports about the running encode through `-progress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub frames: u64,
+    pub fps: f64,
+    pub total_bytes: u64, // Everything written so far, to all outputs
+}
+
+/// Shared with the thread reading the reports, `None` until the first one.
+pub type Latest = Arc<Mutex<Option<Stats>>>;
+
+/// Reads `-progress` reports from `output` until ffmpeg closes it.
+pub fn watch(output: impl Read + Send + 'static) -> Latest {
+    let latest: Latest = Arc::default();
+    let shared = latest.clone();
+    std::thread::spawn(move || {
+        let mut report = Stats::default();
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            if apply(&mut report, &line) {
+                *shared.lock().unwrap() = Some(report);
+            }
+        }
+    });
+    latest
+}
+
+/// Passes ffmpeg's log on to ours and gives back the last line it wrote,
+/// once ffmpeg closes it.
+pub fn watch_log(log: impl Read + Send + 'static) -> std::thread::JoinHandle<Option<String>> {
+    std::thread::spawn(move || {
+        let mut last = None;
+        for line in BufReader::new(log).split(b'\n').map_while(Result::ok) {
+            let line = String::from_utf8_lossy(&line);
+            eprintln!("{}", line.trim_end());
+            // Progress is redrawn in place with \r, the newest text is last
+            if let Some(text) = line.rsplit('\r').map(str::trim).find(|t| !t.is_empty()) {
+                last = Some(text.to_string());
+            }
+        }
+        last
+    })
+}
+
+// Takes one `key=value` line into `report`, true once the report is complete.
+// Values ffmpeg doesn't know yet ("N/A") keep the previous ones.
+fn apply(report: &mut Stats, line: &str) -> bool {
+    let Some((key, value)) = line.trim().split_once('=') else {
+        return false;
+    };
+    match key {
+        "frame" => report.frames = value.parse().unwrap_or(report.frames),
+        "fps" => report.fps = value.parse().unwrap_or(report.fps),
+        "total_size" => report.total_bytes = value.parse().unwrap_or(report.total_bytes),
+        "progress" => return true,
+        _ => {}
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_progress_reports() {
+        let reports = "frame=30\nfps=29.97\nbitrate=N/A\ntotal_size=N/A\nout_time_us=1000000\nprogress=continue\n\
+                       frame=61\nfps=30.01\ntotal_size=524336\nprogress=end\n";
+        let latest = watch(std::io::Cursor::new(reports));
+        let started = std::time::Instant::now();
+        while latest.lock().unwrap().is_none_or(|s| s.frames != 61) {
+            assert!(started.elapsed() < std::time::Duration::from_secs(5));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(*latest.lock().unwrap(), Some(Stats { frames: 61, fps: 30.01, total_bytes: 524336 }));
+
+        let mut report = Stats::default();
+        assert!(!apply(&mut report, "total_size=N/A"));
+        assert!(apply(&mut report, "progress=continue"));
+        assert_eq!(report, Stats::default());
+    }
+    #[test]
+    fn keeps_the_last_log_line() {
+        let log = "Input #0, x11grab\nframe=  10 fps=30\rframe=  20 fps=30\r\nrtmp://host/app: Broken pipe\n\n";
+        let last = watch_log(std::io::Cursor::new(log)).join().unwrap();
+        assert_eq!(last.as_deref(), Some("rtmp://host/app: Broken pipe"));
+        assert_eq!(watch_log(std::io::Cursor::new("")).join().unwrap(), None);
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where a live stream goes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StreamTarget {
+    Rtmp { url: String }, // rtmp:// or rtmps://, including the stream key
+    Srt { url: String },
+    Hls { dir: PathBuf, segment_seconds: u32, playlist_size: u32 },
+}
+
+/// A stream target plus how to encode for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamConfig {
+    pub target: StreamTarget,
+    pub video_kbps: u32,
+    pub keyframe_seconds: u32,
+}
+
+pub const DEFAULT_VIDEO_KBPS: u32 = 4500;
+pub const DEFAULT_KEYFRAME_SECONDS: u32 = 2; // What most ingest servers ask for
+const AUDIO_KBPS: u32 = 128;
+const FRAMERATE: u32 = 30; // Matches the capture framerate
+
+const HLS_SEGMENT_SECONDS: u32 = 2;
+const HLS_PLAYLIST_SIZE: u32 = 6;
+const HLS_PLAYLIST: &str = "index.m3u8";
+
+// Keyring entries are per server, so profiles for different services keep their own key
+const KEYRING_SERVICE: &str = "screen-recorder stream key";
+
+/// RTMP targets are the ones with a stream key.
+pub fn is_rtmp(target: &str) -> bool {
+    let lower = target.trim().to_lowercase();
+    lower.starts_with("rtmp://") || lower.starts_with("rtmps://")
+}
+
+/// Splits an RTMP URL into the server and the stream key, which is whatever
+/// follows the app (`rtmp://host/app/KEY`). Other targets have no key.
+pub fn split_key(target: &str) -> (String, Option<String>) {
+    let target = target.trim();
+    let path = target.split_once("://").map(|(_, rest)| rest).unwrap_or_default();
+    match path.splitn(3, '/').nth(2) {
+        Some(key) if is_rtmp(target) && !key.is_empty() => {
+            let server = &target[..target.len() - key.len() - 1];
+            (server.to_string(), Some(key.to_string()))
+        }
+        _ => (target.to_string(), None),
+    }
+}
+
+/// The URL to stream to, with the key put back after the server.
+pub fn with_key(server: &str, key: &str) -> String {
+    let key = key.trim();
+    if key.is_empty() || !is_rtmp(server) {
+        return server.trim().to_string();
+    }
+    format!("{}/{}", server.trim().trim_end_matches('/'), key)
+}
+
+/// The stream key saved for `server` in the OS keyring, if any.
+pub fn load_key(server: &str) -> Result<Option<String>, String> {
+    if !is_rtmp(server) {
+        return Ok(None);
+    }
+    let entry = keyring::Entry::new(KEYRING_SERVICE, server.trim()).map_err(|e| e.to_string())?;
+    match entry.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Could not read the stream key from the keyring: {}", e)),
+    }
+}
+
+/// Keeps the stream key for `server` in the OS keyring instead of the settings.
+pub fn save_key(server: &str, key: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, server.trim())
+        .and_then(|entry| entry.set_password(key.trim()))
+        .map_err(|e| format!("Could not save the stream key in the keyring: {}", e))
+}
+
+impl StreamTarget {
+    /// Understands `rtmp://`, `rtmps://` and `srt://` URLs, anything else is
+    /// taken as a directory to write HLS into (an optional `hls:` prefix is dropped).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err("No stream target given".to_string());
+        }
+
+        let lower = value.to_lowercase();
+        if lower.starts_with("rtmp://") || lower.starts_with("rtmps://") {
+            Ok(StreamTarget::Rtmp { url: value.to_string() })
+        } else if lower.starts_with("srt://") {
+            Ok(StreamTarget::Srt { url: value.to_string() })
+        } else if lower.contains("://") {
+            Err(format!("Unsupported stream protocol: {}", value))
+        } else {
+            Ok(StreamTarget::Hls {
+                dir: PathBuf::from(value.strip_prefix("hls:").unwrap_or(value)),
+                segment_seconds: HLS_SEGMENT_SECONDS,
+                playlist_size: HLS_PLAYLIST_SIZE,
+            })
+        }
+    }
+
+    /// The HLS playlist players should open, for the local HLS target.
+    pub fn playlist(&self) -> Option<PathBuf> {
+        match self {
+            StreamTarget::Hls { dir, .. } => Some(dir.join(HLS_PLAYLIST)),
+            _ => None,
+        }
+    }
+
+    /// Creates the HLS directory. Network targets need nothing.
+    pub fn prepare(&self) -> Result<(), String> {
+        if let StreamTarget::Hls { dir, .. } = self {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        Ok(())
+    }
+
+    /// Muxer options and destination, placed after the encoder options.
+    pub fn output_args(&self) -> Vec<String> {
+        match self {
+            StreamTarget::Rtmp { url } => vec!["-f".into(), "flv".into(), url.clone()],
+            StreamTarget::Srt { url } => {
+                // One MPEG-TS packet group per UDP datagram
+                let url = if url.contains("pkt_size=") {
+                    url.clone()
+                } else if url.contains('?') {
+                    format!("{}&pkt_size=1316", url)
+                } else {
+                    format!("{}?pkt_size=1316", url)
+                };
+                vec!["-f".into(), "mpegts".into(), url]
+            }
+            StreamTarget::Hls { dir, segment_seconds, playlist_size } => vec![
+                "-f".into(), "hls".into(),
+                "-hls_time".into(), segment_seconds.to_string(),
+                // Only keep the last few segments around, older ones are deleted
+                "-hls_list_size".into(), playlist_size.to_string(),
+                "-hls_flags".into(), "delete_segments+independent_segments".into(),
+                "-hls_segment_filename".into(), dir.join("segment_%05d.ts").to_string_lossy().to_string(),
+                "-y".into(),
+                dir.join(HLS_PLAYLIST).to_string_lossy().to_string(),
+            ],
+        }
+    }
+}
+
+impl StreamConfig {
+    /// H.264/AAC at a constant bitrate with a fixed keyframe interval and no
+    /// lookahead, which is what ingest servers and low-latency players expect.
+    pub fn encoder_args(&self, with_audio: bool) -> Vec<String> {
+        let kbps = format!("{}k", self.video_kbps);
+        let gop = (self.keyframe_seconds.max(1) * FRAMERATE).to_string();
+        let mut args: Vec<String> = vec![
+            "-c:v".into(), "libx264".into(),
+            "-preset".into(), "veryfast".into(),
+            "-tune".into(), "zerolatency".into(),
+            "-pix_fmt".into(), "yuv420p".into(),
+            "-b:v".into(), kbps.clone(),
+            "-minrate".into(), kbps.clone(),
+            "-maxrate".into(), kbps.clone(),
+            "-bufsize".into(), kbps,
+            "-x264-params".into(), "nal-hrd=cbr:force-cfr=1".into(),
+            "-g".into(), gop.clone(),
+            "-keyint_min".into(), gop,
+            "-sc_threshold".into(), "0".into(), // No extra keyframes on scene cuts
+        ];
+        if with_audio {
+            args.extend([
+                "-c:a".into(), "aac".into(),
+                "-b:a".into(), format!("{}k", AUDIO_KBPS),
+                "-ar".into(), "44100".into(),
+                "-ac".into(), "2".into(),
+            ]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_list::{DeviceProvider, MockProvider};
+    use crate::recorder::{Recorder, RecordingConfig, RecordingMode};
+    use std::time::Duration;
+
+    #[test]
+    fn parses_targets_by_scheme() {
+        assert_eq!(
+            StreamTarget::parse("rtmps://live.example.com/app/KEY").unwrap(),
+            StreamTarget::Rtmp { url: "rtmps://live.example.com/app/KEY".to_string() }
+        );
+        assert!(matches!(StreamTarget::parse("srt://10.0.0.2:9000").unwrap(), StreamTarget::Srt { .. }));
+        assert_eq!(
+            StreamTarget::parse("hls:/tmp/live").unwrap().playlist(),
+            Some(PathBuf::from("/tmp/live/index.m3u8"))
+        );
+        assert!(StreamTarget::parse("udp://239.0.0.1:1234").is_err());
+        assert!(StreamTarget::parse("  ").is_err());
+    }
+
+    #[test]
+    fn stream_keys_are_split_off_rtmp_urls() {
+        assert_eq!(
+            split_key("rtmps://live.example.com/app/KEY"),
+            ("rtmps://live.example.com/app".to_string(), Some("KEY".to_string()))
+        );
+        assert_eq!(split_key("rtmp://localhost/live"), ("rtmp://localhost/live".to_string(), None));
+        assert_eq!(split_key("srt://host:9000/a/b"), ("srt://host:9000/a/b".to_string(), None));
+        assert_eq!(split_key("/tmp/live/stream"), ("/tmp/live/stream".to_string(), None));
+
+        assert_eq!(with_key("rtmp://localhost/live/", " KEY"), "rtmp://localhost/live/KEY");
+        assert_eq!(with_key("rtmp://localhost/live", ""), "rtmp://localhost/live");
+        assert_eq!(with_key("/tmp/live", "KEY"), "/tmp/live");
+    }
+
+    #[test]
+    fn srt_gets_a_packet_size() {
+        let args = StreamTarget::parse("srt://host:9000?latency=200").unwrap().output_args();
+        assert_eq!(args, vec!["-f", "mpegts", "srt://host:9000?latency=200&pkt_size=1316"]);
+    }
+
+    #[test]
+    fn encoder_is_cbr_with_fixed_gop() {
+        let config = StreamConfig {
+            target: StreamTarget::parse("rtmp://localhost/live/key").unwrap(),
+            video_kbps: 3000,
+            keyframe_seconds: 2,
+        };
+        let args = config.encoder_args(false).join(" ");
+        assert!(args.contains("-tune zerolatency"));
+        assert!(args.contains("-minrate 3000k -maxrate 3000k"));
+        assert!(args.contains("-g 60 -keyint_min 60"));
+        assert!(!args.contains("-c:a"));
+    }
+
+    fn mock_camera_config(stream: StreamTarget) -> RecordingConfig {
+        let mock = MockProvider::default();
+        RecordingConfig {
+            mode: RecordingMode::Camera,
+            camera_device: mock.video_devices().into_iter().next(),
+            audio_device: mock.audio_devices().into_iter().next(),
+            stream: Some(StreamConfig { target: stream, video_kbps: 1000, keyframe_seconds: 1 }),
+            ..RecordingConfig::default()
+        }
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn streams_to_a_local_rtmp_receiver() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-rtmp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let received = dir.join("received.flv");
+        let url = "rtmp://127.0.0.1:19350/live/test";
+
+        let mut receiver = std::process::Command::new("ffmpeg")
+            .args(["-v", "error", "-listen", "1", "-i", url, "-c", "copy", "-y"])
+            .arg(&received)
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+
+        let mut recorder = Recorder::new();
+        recorder.start(&mock_camera_config(StreamTarget::parse(url).unwrap())).unwrap();
+        std::thread::sleep(Duration::from_secs(3));
+        recorder.stop().unwrap();
+        let _ = receiver.wait();
+
+        assert!(std::fs::metadata(&received).map(|m| m.len() > 0).unwrap_or(false));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg"]
+    fn writes_a_rotating_hls_playlist() {
+        let dir = std::env::temp_dir().join(format!("screen-recorder-hls-{}", std::process::id()));
+        let target = StreamTarget::Hls { dir: dir.clone(), segment_seconds: 1, playlist_size: 2 };
+
+        let mut recorder = Recorder::new();
+        recorder.start(&mock_camera_config(target)).unwrap();
+        std::thread::sleep(Duration::from_secs(5));
+        recorder.stop().unwrap();
+
+        let playlist = std::fs::read_to_string(dir.join(HLS_PLAYLIST)).unwrap();
+        assert_eq!(playlist.matches(".ts").count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
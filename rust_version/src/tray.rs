@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+
+/// What a tray menu entry does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Start,
+    Stop,
+    TogglePause,
+    OpenLast,
+    ShowWindow,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraySettings {
+    pub enabled: bool,
+    pub hide_while_recording: bool, // Only the tray icon is left while recording
+}
+
+impl Default for TraySettings {
+    fn default() -> Self {
+        Self { enabled: false, hide_while_recording: true }
+    }
+}
+
+/// What the tray icon shows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct State {
+    pub recording: bool,
+    pub paused: bool,
+    pub elapsed_secs: u64,
+    pub can_pause: bool,
+    pub has_last: bool, // There is a recording to open
+}
+
+impl State {
+    pub fn summary(&self) -> String {
+        let time = format!("{:02}:{:02}", self.elapsed_secs / 60, self.elapsed_secs % 60);
+        match (self.recording, self.paused) {
+            (true, true) => format!("Paused ({})", time),
+            (true, false) => format!("Recording ({})", time),
+            _ => "Idle".to_string(),
+        }
+    }
+}
+
+// A menu entry, `None` is the status line at the top
+struct Entry {
+    label: String,
+    action: Option<Action>,
+    enabled: bool,
+}
+
+fn menu(state: &State) -> Vec<Entry> {
+    let entry = |label: &str, action, enabled| Entry { label: label.to_string(), action: Some(action), enabled };
+    vec![
+        Entry { label: state.summary(), action: None, enabled: false },
+        entry("Start", Action::Start, !state.recording),
+        entry("Stop", Action::Stop, state.recording),
+        entry(if state.paused { "Resume" } else { "Pause" }, Action::TogglePause, state.recording && state.can_pause),
+        entry("Open Last Recording", Action::OpenLast, state.has_last && !state.recording),
+        entry("Show Window", Action::ShowWindow, true),
+    ]
+}
+
+// The icon is a dot: red while recording, amber when paused, grey otherwise.
+// Drawn here so it doesn't depend on the icon theme.
+const ICON_SIZE: usize = 32;
+
+fn icon_rgba(state: &State) -> Vec<[u8; 4]> {
+    let color = match (state.recording, state.paused) {
+        (true, false) => [220, 30, 30],
+        (true, true) => [230, 160, 20],
+        _ => [140, 140, 140],
+    };
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0 - 2.0;
+    (0..ICON_SIZE * ICON_SIZE)
+        .map(|i| {
+            let (x, y) = ((i % ICON_SIZE) as f32 - center, (i / ICON_SIZE) as f32 - center);
+            // One pixel of soft edge
+            let alpha = (radius + 0.5 - (x * x + y * y).sqrt()).clamp(0.0, 1.0);
+            [color[0], color[1], color[2], (alpha * 255.0) as u8]
+        })
+        .collect()
+}
+
+/// The tray icon and its menu. Chosen entries are passed to `on_action`
+/// from another thread. Removed when dropped.
+pub struct Tray {
+    state: State,
+    inner: platform::Handle,
+}
+
+impl Tray {
+    pub fn start(state: State, on_action: impl Fn(Action) + Send + 'static) -> Result<Self, String> {
+        let inner = platform::start(state.clone(), Box::new(on_action))?;
+        Ok(Self { state, inner })
+    }
+
+    /// Updates the icon, tooltip and menu if anything changed.
+    pub fn set_state(&mut self, state: State) {
+        if state != self.state {
+            self.inner.set_state(state.clone());
+            self.state = state;
+        }
+    }
+}
+
+type OnAction = Box<dyn Fn(Action) + Send>;
+
+// StatusNotifierItem over D-Bus, shown by KDE, most other desktops and, with
+// the AppIndicator extension, GNOME
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{Action, ICON_SIZE, OnAction, State, icon_rgba, menu};
+    use ksni::blocking::TrayMethods;
+    use ksni::menu::StandardItem;
+
+    struct Sni {
+        state: State,
+        on_action: OnAction,
+    }
+
+    impl ksni::Tray for Sni {
+        fn id(&self) -> String {
+            "screen-recorder".to_string()
+        }
+
+        fn title(&self) -> String {
+            "Screen Recorder".to_string()
+        }
+
+        fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+            // ARGB in network byte order
+            let data = icon_rgba(&self.state).into_iter().flat_map(|[r, g, b, a]| [a, r, g, b]).collect();
+            vec![ksni::Icon { width: ICON_SIZE as i32, height: ICON_SIZE as i32, data }]
+        }
+
+        fn tool_tip(&self) -> ksni::ToolTip {
+            ksni::ToolTip {
+                title: "Screen Recorder".to_string(),
+                description: self.state.summary(),
+                ..ksni::ToolTip::default()
+            }
+        }
+
+        fn activate(&mut self, _x: i32, _y: i32) {
+            (self.on_action)(Action::ShowWindow);
+        }
+
+        fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+            menu(&self.state)
+                .into_iter()
+                .map(|entry| {
+                    let action = entry.action;
+                    StandardItem {
+                        label: entry.label,
+                        enabled: entry.enabled,
+                        activate: Box::new(move |tray: &mut Self| {
+                            if let Some(action) = action {
+                                (tray.on_action)(action);
+                            }
+                        }),
+                        ..StandardItem::default()
+                    }
+                    .into()
+                })
+                .collect()
+        }
+    }
+
+    pub struct Handle(ksni::blocking::Handle<Sni>);
+
+    pub fn start(state: State, on_action: OnAction) -> Result<Handle, String> {
+        Sni { state, on_action }
+            .spawn()
+            .map(Handle)
+            .map_err(|e| format!("No tray available: {}", e))
+    }
+
+    impl Handle {
+        pub fn set_state(&self, state: State) {
+            self.0.update(|tray| tray.state = state);
+        }
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            self.0.shutdown().wait();
+        }
+    }
+}
+
+// A notification area icon owned by a hidden window on its own thread, which
+// gets the clicks and shows the menu
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{Action, ICON_SIZE, OnAction, State, icon_rgba, menu};
+    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex, mpsc};
+    use std::thread::JoinHandle;
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::Shell::{
+        NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW, Shell_NotifyIconW,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        AppendMenuW, CreateIcon, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyIcon, DestroyMenu,
+        DestroyWindow, DispatchMessageW, GetCursorPos, GetMessageW, MF_GRAYED, MF_STRING, MSG, PostMessageW,
+        PostQuitMessage, RegisterClassW, SetForegroundWindow, TPM_RETURNCMD, TPM_RIGHTBUTTON, TrackPopupMenu,
+        TranslateMessage, WM_APP, WM_CLOSE, WM_CONTEXTMENU, WM_DESTROY, WM_LBUTTONUP, WM_RBUTTONUP, WNDCLASSW,
+    };
+
+    const WM_TRAY: u32 = WM_APP + 1; // Mouse events on the icon
+    const WM_REFRESH: u32 = WM_APP + 2; // The state changed
+
+    thread_local! {
+        // The window procedure has no other way to reach them
+        static TRAY: RefCell<Option<(Arc<Mutex<State>>, OnAction)>> = const { RefCell::new(None) };
+    }
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn current_state() -> State {
+        TRAY.with(|tray| tray.borrow().as_ref().map(|(state, _)| state.lock().unwrap().clone()).unwrap_or_default())
+    }
+
+    fn run(action: Action) {
+        TRAY.with(|tray| {
+            if let Some((_, on_action)) = tray.borrow().as_ref() {
+                on_action(action);
+            }
+        });
+    }
+
+    fn notify(hwnd: HWND, message: u32) {
+        let state = current_state();
+        let mut data: NOTIFYICONDATAW = unsafe { std::mem::zeroed() };
+        data.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = hwnd;
+        data.uID = 1;
+        data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+        data.uCallbackMessage = WM_TRAY;
+        let tip = wide(&format!("Screen Recorder: {}", state.summary()));
+        let len = tip.len().min(data.szTip.len() - 1);
+        data.szTip[..len].copy_from_slice(&tip[..len]);
+
+        // 32-bit icons take their alpha from the colour bits, the mask can stay empty
+        let colour: Vec<u8> = icon_rgba(&state).into_iter().flat_map(|[r, g, b, a]| [b, g, r, a]).collect();
+        let mask = vec![0u8; ICON_SIZE * ICON_SIZE.div_ceil(16) * 2];
+        let size = ICON_SIZE as i32;
+        data.hIcon = unsafe {
+            CreateIcon(GetModuleHandleW(std::ptr::null()), size, size, 1, 32, mask.as_ptr(), colour.as_ptr())
+        };
+        unsafe {
+            Shell_NotifyIconW(message, &data);
+            // The shell keeps its own copy
+            if !data.hIcon.is_null() {
+                DestroyIcon(data.hIcon);
+            }
+        }
+    }
+
+    fn show_menu(hwnd: HWND) {
+        let entries = menu(&current_state());
+        unsafe {
+            let popup = CreatePopupMenu();
+            for (id, entry) in (1..).zip(&entries) {
+                let flags = if entry.enabled { MF_STRING } else { MF_STRING | MF_GRAYED };
+                AppendMenuW(popup, flags, id, wide(&entry.label).as_ptr());
+            }
+            let mut cursor = POINT { x: 0, y: 0 };
+            GetCursorPos(&mut cursor);
+            // Otherwise the menu stays open when clicking elsewhere
+            SetForegroundWindow(hwnd);
+            let chosen = TrackPopupMenu(popup, TPM_RETURNCMD | TPM_RIGHTBUTTON, cursor.x, cursor.y, 0, hwnd, std::ptr::null());
+            DestroyMenu(popup);
+            if let Some(action) = (chosen as usize).checked_sub(1).and_then(|i| entries.get(i)).and_then(|e| e.action) {
+                run(action);
+            }
+        }
+    }
+
+    unsafe extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        match message {
+            WM_TRAY => match lparam as u32 {
+                WM_LBUTTONUP => run(Action::ShowWindow),
+                WM_RBUTTONUP | WM_CONTEXTMENU => show_menu(hwnd),
+                _ => {}
+            },
+            WM_REFRESH => notify(hwnd, NIM_MODIFY),
+            WM_CLOSE => unsafe {
+                DestroyWindow(hwnd);
+            },
+            WM_DESTROY => {
+                notify(hwnd, NIM_DELETE);
+                unsafe { PostQuitMessage(0) };
+            }
+            _ => return unsafe { DefWindowProcW(hwnd, message, wparam, lparam) },
+        }
+        0
+    }
+
+    pub struct Handle {
+        hwnd: usize, // HWND isn't Send
+        state: Arc<Mutex<State>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    pub fn start(state: State, on_action: OnAction) -> Result<Handle, String> {
+        let state = Arc::new(Mutex::new(state));
+        let shared = state.clone();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = std::thread::spawn(move || unsafe {
+            let instance = GetModuleHandleW(std::ptr::null());
+            let class_name = wide("ScreenRecorderTray");
+            let mut class: WNDCLASSW = std::mem::zeroed();
+            class.lpfnWndProc = Some(window_proc);
+            class.hInstance = instance;
+            class.lpszClassName = class_name.as_ptr();
+            RegisterClassW(&class);
+            // Never shown, it only receives messages
+            let hwnd = CreateWindowExW(
+                0, class_name.as_ptr(), class_name.as_ptr(), 0, 0, 0, 0, 0,
+                std::ptr::null_mut(), std::ptr::null_mut(), instance, std::ptr::null(),
+            );
+            if hwnd.is_null() {
+                let _ = ready_tx.send(Err("Could not create the tray window".to_string()));
+                return;
+            }
+            TRAY.with(|tray| *tray.borrow_mut() = Some((shared, on_action)));
+            notify(hwnd, NIM_ADD);
+            let _ = ready_tx.send(Ok(hwnd as usize));
+
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        });
+        match ready_rx.recv() {
+            Ok(Ok(hwnd)) => Ok(Handle { hwnd, state, thread: Some(thread) }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => Err("The tray stopped unexpectedly".to_string()),
+        }
+    }
+
+    impl Handle {
+        pub fn set_state(&self, state: State) {
+            *self.state.lock().unwrap() = state;
+            unsafe { PostMessageW(self.hwnd as HWND, WM_REFRESH, 0, 0) };
+        }
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe { PostMessageW(self.hwnd as HWND, WM_CLOSE, 0, 0) };
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use super::{OnAction, State};
+
+    pub struct Handle;
+
+    pub fn start(_: State, _: OnAction) -> Result<Handle, String> {
+        Err("A tray icon is not supported on this platform".to_string())
+    }
+
+    impl Handle {
+        pub fn set_state(&self, _: State) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_follows_the_recording_state() {
+        let enabled = |state: &State| -> Vec<Option<Action>> {
+            menu(state).into_iter().filter(|e| e.enabled).map(|e| e.action).collect()
+        };
+        let idle = State { can_pause: true, has_last: true, ..State::default() };
+        assert_eq!(enabled(&idle), [Some(Action::Start), Some(Action::OpenLast), Some(Action::ShowWindow)]);
+
+        let paused = State { recording: true, paused: true, elapsed_secs: 83, ..idle.clone() };
+        assert_eq!(menu(&paused)[0].label, "Paused (01:23)");
+        assert_eq!(menu(&paused)[3].label, "Resume");
+        assert_eq!(enabled(&paused), [Some(Action::Stop), Some(Action::TogglePause), Some(Action::ShowWindow)]);
+
+        let icon = icon_rgba(&paused);
+        assert_eq!(icon.len(), ICON_SIZE * ICON_SIZE);
+        assert_eq!(icon[0][3], 0); // Transparent corner
+        assert_eq!(icon[ICON_SIZE * ICON_SIZE / 2 + ICON_SIZE / 2], [230, 160, 20, 255]);
+    }
+}